@@ -1,18 +1,194 @@
-#![allow(unused)]
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mymesi::db::db::DbSession;
+use mymesi::workload::generator::KeyDistribution;
+use mymesi::workload::workload::{Workload, WorkloadConfig};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+const KEYS: u64 = 1000;
 
-fn fibonacci(n: u64) -> u64 {
-    match n {
-        0 => 1,
-        1 => 1,
-        n => fibonacci(n - 1) + fibonacci(n - 2),
+/// `directory` 不模拟磁盘延迟，只测量协议本身的开销
+fn directory() -> Arc<RwLock<Directory>> {
+    sliced_directory(1, Duration::ZERO)
+}
+
+fn sliced_directory(slices: usize, db_latency: Duration) -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(db_latency),
+        DirectoryConfig {
+            slices,
+            ..DirectoryConfig::default()
+        },
+    )))
+}
+
+/// `hit_path` 单线程命中本地缓存的读写
+fn hit_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hit_path");
+    let directory = directory();
+    let mut ct: CacheController<String> = CacheController::new(directory.clone());
+    let key = "hit".to_string();
+    ct.set(key.clone(), "0".to_string());
+
+    group.bench_function("get", |b| b.iter(|| ct.get(black_box(key.clone()))));
+    group.bench_function("set_modified", |b| {
+        b.iter(|| ct.set(black_box(key.clone()), "1".to_string()))
+    });
+
+    let config = ControllerConfig {
+        model: ConsistencyModel::TSO,
+        ..ControllerConfig::default()
+    };
+    let mut tso: CacheController<String> = CacheController::with_config(directory, config);
+    tso.set("tso".to_string(), "0".to_string());
+    tso.fence();
+    group.bench_function("set_tso_buffered", |b| {
+        b.iter(|| tso.set(black_box("tso".to_string()), "1".to_string()))
+    });
+    group.finish();
+}
+
+/// `coherence_miss` 每次操作都经过目录并与另一个线程的副本交互
+fn coherence_miss(c: &mut Criterion) {
+    let mut group = c.benchmark_group("coherence_miss");
+    let directory = directory();
+    let mut c0: CacheController<String> = CacheController::new(directory.clone());
+    let mut c1: CacheController<String> = CacheController::new(directory.clone());
+    let key = "miss".to_string();
+
+    // 两个线程轮流写，每次写都使对方的 Modified 副本失效
+    group.bench_function("write_invalidate", |b| {
+        b.iter(|| {
+            c0.set(key.clone(), "0".to_string());
+            c1.set(key.clone(), "1".to_string());
+        })
+    });
+
+    // 写后另一个线程读，Modified 副本写回并降级为 Shared
+    group.bench_function("read_downgrade", |b| {
+        b.iter(|| {
+            c0.set(key.clone(), "0".to_string());
+            black_box(c1.get(key.clone()));
+        })
+    });
+
+    // 读共享后写，upgrade 使另一个 Shared 副本失效
+    group.bench_function("upgrade", |b| {
+        b.iter(|| {
+            c0.get(key.clone());
+            c1.get(key.clone());
+            c0.set(key.clone(), "0".to_string());
+        })
+    });
+
+    // 无其他共享者的冷缺失
+    let mut cold: CacheController<String> = CacheController::new(directory);
+    let mut i = 0u64;
+    group.bench_function("cold_read", |b| {
+        b.iter(|| {
+            i += 1;
+            cold.get(format!("cold.{}", i))
+        })
+    });
+    group.finish();
+}
+
+/// `run` n 个线程各自执行 iters 次操作，返回总耗时
+fn run(n: usize, read_ratio: f64, distribution: &KeyDistribution, iters: u64) -> Duration {
+    run_on(directory(), n, read_ratio, distribution, iters)
+}
+
+fn run_on(
+    directory: Arc<RwLock<Directory>>,
+    n: usize,
+    read_ratio: f64,
+    distribution: &KeyDistribution,
+    iters: u64,
+) -> Duration {
+    let workload = Workload::new(WorkloadConfig {
+        records: KEYS,
+        distribution: distribution.clone(),
+        read: read_ratio,
+        update: 1.0 - read_ratio,
+        ..WorkloadConfig::default()
+    });
+    let barrier = Arc::new(Barrier::new(n + 1));
+    let handles: Vec<_> = (0..n)
+        .map(|t| {
+            let directory = directory.clone();
+            let barrier = barrier.clone();
+            let mut client = workload.client(t, n, 0);
+            thread::spawn(move || {
+                let mut ct: CacheController<String> = CacheController::new(directory);
+                barrier.wait();
+                for _ in 0..iters {
+                    client.execute(&mut ct);
+                }
+                barrier.wait();
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    barrier.wait();
+    let elapsed = start.elapsed();
+    for handle in handles {
+        handle.join().unwrap();
     }
+    elapsed
 }
 
-fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("fib 20", |b| b.iter(|| fibonacci(black_box(20))));
+/// `throughput` 不同线程数、读写比例和 key 分布下的吞吐量
+/// 每次迭代为每个线程各执行一次操作
+fn throughput(c: &mut Criterion) {
+    let distributions = [
+        ("uniform", KeyDistribution::Uniform),
+        ("zipfian", KeyDistribution::Zipfian(0.99)),
+        (
+            "hotspot",
+            KeyDistribution::Hotspot {
+                hot_fraction: 0.01,
+                hot_probability: 0.9,
+            },
+        ),
+    ];
+    for (name, distribution) in &distributions {
+        for read_ratio in [0.5, 0.9, 0.99] {
+            let mut group = c.benchmark_group(format!("throughput/{}/read{}", name, read_ratio));
+            group.sample_size(10);
+            group.measurement_time(Duration::from_secs(2));
+            for n in [1, 2, 4, 8] {
+                group.throughput(Throughput::Elements(n as u64));
+                group.bench_with_input(BenchmarkId::new("threads", n), &n, |b, &n| {
+                    b.iter_custom(|iters| run(n, read_ratio, distribution, iters))
+                });
+            }
+            group.finish();
+        }
+    }
+}
+
+/// `slices` 8 个线程读写各半的负载下，吞吐量随目录分片数的变化
+/// 写回 db 时持有分片的 socket 锁，模拟 db 延迟才能看出单个目录的排队
+fn slices(c: &mut Criterion) {
+    let mut group = c.benchmark_group("slices");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(2));
+    group.throughput(Throughput::Elements(8));
+    for slices in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("threads/8", slices), &slices, |b, &s| {
+            b.iter_custom(|iters| {
+                let directory = sliced_directory(s, Duration::from_micros(50));
+                run_on(directory, 8, 0.5, &KeyDistribution::Uniform, iters)
+            })
+        });
+    }
+    group.finish();
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, hit_path, coherence_miss, throughput, slices);
 criterion_main!(benches);
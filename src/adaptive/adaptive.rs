@@ -0,0 +1,418 @@
+//! 按 key 的共享模式自适应地选择协议
+//!
+//! 目录为每个 key 保留最近的访问历史，每次访问后重新分类：
+//! - 迁移（migratory）：线程轮流先读后写，读时直接装入 Exclusive，省掉随后的 upgrade
+//! - 生产者-消费者：只有一个线程写，其他线程读，写时改为写更新，消费者的副本保持有效
+//! - 其他：使用 Directory 配置的协议
+//!
+//! 目录看不到命中本地缓存的访问。Exclusive 副本上的写是静默的，
+//! 目录在之后的请求使该副本写回时补记一次写；写更新后仍持有副本的共享者记为读了新值。
+use crate::stats::stats::inc;
+use crate::ThreadID;
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// `AdaptiveConfig` 共享模式识别的参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveConfig {
+    /// 每个 key 保留的访问数
+    pub history: usize,
+    /// 历史中的访问数达到 min_accesses 后才分类
+    pub min_accesses: usize,
+    /// 迁移：先读后写、且上一次写来自其他线程的写，至少占读的比例
+    /// 生产者-消费者：其他线程的读至少占写的比例
+    pub threshold: f64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        AdaptiveConfig {
+            history: 16,
+            min_accesses: 6,
+            threshold: 0.5,
+        }
+    }
+}
+
+/// `SharingPattern` key 的共享模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SharingPattern {
+    /// 访问太少或没有明显的模式
+    Other,
+    Migratory,
+    ProducerConsumer,
+}
+
+impl fmt::Display for SharingPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SharingPattern::Other => write!(f, "other"),
+            SharingPattern::Migratory => write!(f, "migratory"),
+            SharingPattern::ProducerConsumer => write!(f, "producer-consumer"),
+        }
+    }
+}
+
+/// `Access` 目录看到或推断出的一次访问
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub thread: ThreadID,
+    pub write: bool,
+}
+
+/// `classify` 由访问历史判断共享模式
+pub fn classify(history: &VecDeque<Access>, config: &AdaptiveConfig) -> SharingPattern {
+    if history.len() < config.min_accesses.max(2) {
+        return SharingPattern::Other;
+    }
+    let reads = history.iter().filter(|a| !a.write).count();
+    let writes = history.len() - reads;
+
+    // 先读后写，且该线程读之前的最后一次写来自其他线程
+    let mut handoffs = 0;
+    let mut last_writer = None;
+    for (i, a) in history.iter().enumerate() {
+        if !a.write {
+            continue;
+        }
+        let read_first = i > 0 && !history[i - 1].write && history[i - 1].thread == a.thread;
+        if read_first && last_writer.is_some_and(|w| w != a.thread) {
+            handoffs += 1;
+        }
+        last_writer = Some(a.thread);
+    }
+    if handoffs >= 2 && handoffs as f64 >= config.threshold * reads as f64 {
+        return SharingPattern::Migratory;
+    }
+
+    let mut writers = history.iter().filter(|a| a.write).map(|a| a.thread);
+    if let Some(producer) = writers.next() {
+        let consumed = history
+            .iter()
+            .filter(|a| !a.write && a.thread != producer)
+            .count();
+        if writes >= 2
+            && writers.all(|w| w == producer)
+            && consumed > 0
+            && consumed as f64 >= config.threshold * writes as f64
+        {
+            return SharingPattern::ProducerConsumer;
+        }
+    }
+    SharingPattern::Other
+}
+
+/// `KeyHistory` 一个 key 的访问历史和当前模式
+#[derive(Debug, Clone)]
+struct KeyHistory {
+    accesses: VecDeque<Access>,
+    pattern: SharingPattern,
+    switches: u64,
+    // 上次取走之后有 Modified 副本写回
+    written: bool,
+}
+
+impl Default for KeyHistory {
+    fn default() -> Self {
+        KeyHistory {
+            accesses: VecDeque::new(),
+            pattern: SharingPattern::Other,
+            switches: 0,
+            written: false,
+        }
+    }
+}
+
+/// `KeyPattern` 一个 key 的分类，以及它切换模式的次数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPattern {
+    pub key: String,
+    pub pattern: SharingPattern,
+    pub switches: u64,
+}
+
+/// `AdaptiveStats` 按 key 自适应选择协议的统计快照
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdaptiveStats {
+    /// 当前被识别为迁移、生产者-消费者模式的 key 数
+    pub migratory_keys: u64,
+    pub producer_consumer_keys: u64,
+    /// key 切换到迁移优化、写更新、默认协议的次数
+    pub switches_to_migratory: u64,
+    pub switches_to_update: u64,
+    pub switches_to_default: u64,
+    /// 有其他共享者时按迁移优化装入 Exclusive 的读
+    pub migratory_grants: u64,
+    /// 按写更新处理的写请求
+    pub update_writes: u64,
+}
+
+impl AdaptiveStats {
+    pub fn switches(&self) -> u64 {
+        self.switches_to_migratory + self.switches_to_update + self.switches_to_default
+    }
+}
+
+impl fmt::Display for AdaptiveStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "keys migratory {} producer-consumer {}",
+            self.migratory_keys, self.producer_consumer_keys
+        )?;
+        writeln!(
+            f,
+            "switches to migratory {} update {} default {}",
+            self.switches_to_migratory, self.switches_to_update, self.switches_to_default
+        )?;
+        write!(
+            f,
+            "migratory grants {} update writes {}",
+            self.migratory_grants, self.update_writes
+        )
+    }
+}
+
+/// `AdaptiveCounters` 自适应协议选择内部的计数器，key 数在快照时统计
+#[derive(Debug, Default)]
+struct AdaptiveCounters {
+    pub switches_to_migratory: AtomicU64,
+    pub switches_to_update: AtomicU64,
+    pub switches_to_default: AtomicU64,
+    pub migratory_grants: AtomicU64,
+    pub update_writes: AtomicU64,
+}
+
+impl AdaptiveCounters {
+    fn counters(&self) -> [&AtomicU64; 5] {
+        [
+            &self.switches_to_migratory,
+            &self.switches_to_update,
+            &self.switches_to_default,
+            &self.migratory_grants,
+            &self.update_writes,
+        ]
+    }
+
+    pub fn snapshot(&self) -> AdaptiveStats {
+        let [switches_to_migratory, switches_to_update, switches_to_default, migratory_grants, update_writes] =
+            self.counters().map(|c| c.load(Ordering::Relaxed));
+        AdaptiveStats {
+            switches_to_migratory,
+            switches_to_update,
+            switches_to_default,
+            migratory_grants,
+            update_writes,
+            ..AdaptiveStats::default()
+        }
+    }
+
+    pub fn reset(&self) {
+        for c in self.counters() {
+            c.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// `Adaptive` 目录中按 key 记录访问并分类
+/// 调用方持有 key 的目录项时调用，同一个 key 的访问按目录处理的顺序记录
+pub struct Adaptive {
+    config: AdaptiveConfig,
+    keys: DashMap<String, KeyHistory>,
+    stats: AdaptiveCounters,
+}
+
+impl Adaptive {
+    pub fn new(config: AdaptiveConfig) -> Adaptive {
+        Adaptive {
+            config,
+            keys: DashMap::new(),
+            stats: AdaptiveCounters::default(),
+        }
+    }
+
+    pub fn pattern(&self, key: &str) -> SharingPattern {
+        self.keys
+            .get(key)
+            .map_or(SharingPattern::Other, |h| h.pattern)
+    }
+
+    /// `patterns` 每个 key 的分类，按 key 排序
+    pub fn patterns(&self) -> Vec<KeyPattern> {
+        let mut patterns: Vec<_> = self
+            .keys
+            .iter()
+            .map(|h| KeyPattern {
+                key: h.key().clone(),
+                pattern: h.pattern,
+                switches: h.switches,
+            })
+            .collect();
+        patterns.sort_by(|a, b| a.key.cmp(&b.key));
+        patterns
+    }
+
+    pub fn stats(&self) -> AdaptiveStats {
+        let mut stats = self.stats.snapshot();
+        for h in self.keys.iter() {
+            match h.pattern {
+                SharingPattern::Migratory => stats.migratory_keys += 1,
+                SharingPattern::ProducerConsumer => stats.producer_consumer_keys += 1,
+                SharingPattern::Other => {}
+            }
+        }
+        stats
+    }
+
+    /// `reset_stats` 清零计数器，不影响访问历史和分类
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    pub(crate) fn migratory_grant(&self) {
+        inc(&self.stats.migratory_grants);
+    }
+
+    pub(crate) fn update_write(&self) {
+        inc(&self.stats.update_writes);
+    }
+
+    /// `written` key 的一个 Modified 副本写回了 LLC 或 db
+    pub(crate) fn written(&self, key: &str) {
+        if let Some(mut h) = self.keys.get_mut(key) {
+            h.written = true;
+        }
+    }
+
+    /// `take_written` 上次调用之后是否有 Modified 副本写回，并清除标记
+    pub(crate) fn take_written(&self, key: &str) -> bool {
+        self.keys
+            .get_mut(key)
+            .is_some_and(|mut h| std::mem::take(&mut h.written))
+    }
+
+    /// `observe` 记录一次访问并重新分类
+    pub(crate) fn observe(&self, key: &str, thread: ThreadID, write: bool) {
+        let mut h = self.keys.entry(key.to_string()).or_default();
+        h.accesses.push_back(Access { thread, write });
+        while h.accesses.len() > self.config.history.max(1) {
+            h.accesses.pop_front();
+        }
+        let pattern = classify(&h.accesses, &self.config);
+        if pattern != h.pattern {
+            h.pattern = pattern;
+            h.switches += 1;
+            inc(match pattern {
+                SharingPattern::Migratory => &self.stats.switches_to_migratory,
+                SharingPattern::ProducerConsumer => &self.stats.switches_to_update,
+                SharingPattern::Other => &self.stats.switches_to_default,
+            });
+        }
+    }
+
+    /// `infer_write` owner 的 Modified 副本写回了，它在历史中的最后一次访问不是写时补记一次写
+    pub(crate) fn infer_write(&self, key: &str, owner: ThreadID) {
+        let wrote = self.keys.get(key).is_some_and(|h| {
+            let last = h.accesses.iter().rev().find(|a| a.thread == owner);
+            last.is_some_and(|a| a.write)
+        });
+        if !wrote {
+            self.observe(key, owner, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(accesses: &[(ThreadID, bool)]) -> VecDeque<Access> {
+        accesses
+            .iter()
+            .map(|(thread, write)| Access {
+                thread: *thread,
+                write: *write,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        let config = AdaptiveConfig::default();
+        let migratory = history(&[
+            (0, false),
+            (0, true),
+            (1, false),
+            (1, true),
+            (2, false),
+            (2, true),
+        ]);
+        assert_eq!(classify(&migratory, &config), SharingPattern::Migratory);
+
+        let producer_consumer = history(&[
+            (0, true),
+            (1, false),
+            (2, false),
+            (0, true),
+            (1, false),
+            (2, false),
+        ]);
+        assert_eq!(
+            classify(&producer_consumer, &config),
+            SharingPattern::ProducerConsumer
+        );
+
+        // 只读共享、多个写者、访问太少
+        let read_shared = history(&[(0, false), (1, false), (2, false), (0, false)]);
+        assert_eq!(classify(&read_shared, &config), SharingPattern::Other);
+        let mixed = history(&[
+            (0, true),
+            (1, true),
+            (2, false),
+            (0, true),
+            (1, false),
+            (2, true),
+        ]);
+        assert_eq!(classify(&mixed, &config), SharingPattern::Other);
+        assert_eq!(
+            classify(&history(&[(0, false), (0, true)]), &config),
+            SharingPattern::Other
+        );
+    }
+
+    #[test]
+    fn test_switches() {
+        let adaptive = Adaptive::new(AdaptiveConfig {
+            history: 6,
+            ..AdaptiveConfig::default()
+        });
+        for t in 0..3 {
+            adaptive.observe("k", t, false);
+            adaptive.observe("k", t, true);
+        }
+        assert_eq!(adaptive.pattern("k"), SharingPattern::Migratory);
+
+        // 静默的写在写回时补记，已经记录过的写不重复记
+        adaptive.observe("k", 0, false);
+        adaptive.infer_write("k", 0);
+        adaptive.infer_write("k", 0);
+        assert_eq!(adaptive.keys.get("k").unwrap().accesses.len(), 6);
+        assert_eq!(adaptive.pattern("k"), SharingPattern::Migratory);
+
+        // 只读不写之后回到默认协议
+        for t in 0..6 {
+            adaptive.observe("k", t, false);
+        }
+        assert_eq!(adaptive.pattern("k"), SharingPattern::Other);
+        let stats = adaptive.stats();
+        assert_eq!(stats.switches_to_migratory, 1);
+        assert_eq!(stats.switches_to_default, 1);
+        assert_eq!(adaptive.patterns()[0].switches, 2);
+
+        adaptive.written("k");
+        assert!(adaptive.take_written("k"));
+        assert!(!adaptive.take_written("k"));
+    }
+}
@@ -0,0 +1,50 @@
+use mymesi::experiment::experiment::{self, ExperimentConfig, ExperimentResult};
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+/// 用法：experiment [config] [output]
+/// 按配置文件扫描线程数、读比例、key 分布和协议，结果写入 output 目录下的 results.csv、
+/// 每条链路流量的 links.csv 和 SVG 图
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut config = match args.get(1) {
+        None => ExperimentConfig::default(),
+        Some(path) => ExperimentConfig::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }),
+    };
+    if let Some(output) = args.get(2) {
+        config.output = PathBuf::from(output);
+    }
+
+    fs::create_dir_all(&config.output).unwrap_or_else(|e| {
+        eprintln!("{}: {}", config.output.display(), e);
+        process::exit(1);
+    });
+
+    println!("{}", ExperimentResult::CSV_HEADER);
+    let results = experiment::run(&config, |r| println!("{}", r.csv()));
+    if config.protocols.len() > 1 {
+        eprint!("{}", experiment::compare(&results));
+    }
+
+    let csv = config.output.join("results.csv");
+    let links = config.output.join("links.csv");
+    let written = experiment::write_csv(&results, &csv)
+        .and_then(|_| experiment::write_links(&results, &links))
+        .and_then(|_| experiment::render_charts(&results, &config.output));
+    match written {
+        Ok(charts) => {
+            eprintln!("wrote {}", csv.display());
+            eprintln!("wrote {}", links.display());
+            for chart in charts {
+                eprintln!("wrote {}", chart.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
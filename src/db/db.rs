@@ -3,25 +3,72 @@ use sled;
 use std::sync::Arc;
 use std::{thread, time};
 
+const LATENCY: time::Duration = time::Duration::from_micros(200);
+
 pub struct DbSession {
-    db: Arc<Mutex<sled::Db>>,
+    root: sled::Db,
+    db: Arc<Mutex<sled::Tree>>,
+    // 模拟的磁盘访问延迟
+    latency: time::Duration,
 }
 
 impl DbSession {
     pub fn new(path: &String) -> DbSession {
-        let db = sled::open(path).expect("open");
-        db.clear().unwrap();
-        let db = Arc::new(Mutex::new(db));
-        DbSession { db }
+        let root = sled::open(path).expect("open");
+        root.clear().unwrap();
+        let db = Arc::new(Mutex::new((*root).clone()));
+        DbSession {
+            root,
+            db,
+            latency: LATENCY,
+        }
+    }
+
+    /// `temporary` 使用临时目录，释放后删除
+    pub fn temporary() -> DbSession {
+        let root = sled::Config::new().temporary(true).open().expect("open");
+        let db = Arc::new(Mutex::new((*root).clone()));
+        DbSession {
+            root,
+            db,
+            latency: LATENCY,
+        }
+    }
+
+    /// `with_latency` 设置每次读写的模拟延迟，为 0 时不等待，只测量协议本身的开销
+    pub fn with_latency(mut self, latency: time::Duration) -> DbSession {
+        self.latency = latency;
+        self
+    }
+
+    /// `partition` 同一个 db 中编号为 i 的分区，打开时清空
+    /// 各分区的 key 互不可见，读写互不阻塞
+    pub fn partition(&self, i: usize) -> DbSession {
+        let tree = self
+            .root
+            .open_tree(format!("partition.{}", i))
+            .expect("open");
+        tree.clear().unwrap();
+        DbSession {
+            root: self.root.clone(),
+            db: Arc::new(Mutex::new(tree)),
+            latency: self.latency,
+        }
+    }
+
+    fn wait(&self) {
+        if !self.latency.is_zero() {
+            thread::sleep(self.latency);
+        }
     }
 
     pub fn set(&self, id: String, val: String) {
-        thread::sleep(time::Duration::from_micros(200));
+        self.wait();
         self.db.lock().insert(id, val.as_str()).unwrap();
     }
 
     pub fn get(&self, id: String) -> String {
-        thread::sleep(time::Duration::from_micros(200));
+        self.wait();
         let res = self.db.lock().get(id).unwrap();
 
         match res {
@@ -37,7 +84,9 @@ impl DbSession {
 impl Clone for DbSession {
     fn clone(&self) -> Self {
         DbSession {
+            root: self.root.clone(),
             db: self.db.clone(),
+            latency: self.latency,
         }
     }
 }
@@ -52,13 +101,34 @@ mod tests {
 
     #[test]
     fn test_string() {
-        let x: u32 = 10;
-
-        let session = crate::db::db::DbSession::new("./data/db_test");
+        let session = DbSession::new(&"./data/db_test".to_string());
         session.set("key".to_string(), "val_redrock".to_string());
         let val: String = session.get("key".to_string());
 
         println!("{val}");
         assert_eq!(val, "val_redrock")
     }
+
+    #[test]
+    fn test_partition() {
+        let session = DbSession::temporary();
+        let (p0, p1) = (session.partition(0), session.partition(1));
+        p0.set("key".to_string(), "0".to_string());
+        p1.set("key".to_string(), "1".to_string());
+        assert_eq!(p0.get("key".to_string()), "0");
+        assert_eq!(p1.get("key".to_string()), "1");
+        assert_eq!(session.get("key".to_string()), "");
+    }
+
+    #[test]
+    fn test_without_latency() {
+        let session = DbSession::temporary().with_latency(std::time::Duration::ZERO);
+        let start = std::time::Instant::now();
+        for i in 0..100 {
+            session.set(i.to_string(), i.to_string());
+        }
+        assert_eq!(session.get("42".to_string()), "42");
+        // 有延迟时至少需要 20ms
+        assert!(start.elapsed() < std::time::Duration::from_millis(20));
+    }
 }
@@ -0,0 +1,724 @@
+use crate::db::db::DbSession;
+use crate::experiment::chart::{line_chart, Series};
+use crate::prefetch::prefetch::PrefetchConfig;
+use crate::sim::cost::CostModel;
+use crate::stats::histogram::Histogram;
+use crate::stats::stats::{DirectoryStats, Stats};
+use crate::topology::network::{LinkStats, Network};
+use crate::topology::topology::{Link, Placement, Topology};
+use crate::trace::trace::{Side, TraceKind, TraceRecord};
+use crate::workload::generator::KeyDistribution;
+use crate::workload::workload::{Workload, WorkloadConfig};
+use crate::{
+    CacheController, ControllerConfig, Directory, DirectoryConfig, Event, Protocol, ThreadID,
+};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// `ExperimentConfig` 一组实验的参数，对每种分布、读比例、线程数和协议的组合各运行一次
+#[derive(Debug, Clone)]
+pub struct ExperimentConfig {
+    pub threads: Vec<usize>,
+    pub read_ratios: Vec<f64>,
+    pub distributions: Vec<KeyDistribution>,
+    pub protocols: Vec<Protocol>,
+    /// 每个线程的操作次数
+    pub ops: usize,
+    pub records: u64,
+    /// DbSession 每次读写的模拟延迟
+    pub db_latency: Duration,
+    pub cache_size: usize,
+    pub flush_size: usize,
+    /// 每个缓存行的 slot 数，见 `DirectoryConfig::line_size`
+    pub line_size: usize,
+    /// 每个线程的预取器，见 `ControllerConfig::prefetch`
+    pub prefetch: Option<PrefetchConfig>,
+    /// 每个线程的 victim buffer 容量，见 `ControllerConfig::victims`
+    pub victims: usize,
+    /// 统计链路流量时控制器和目录分片之间的互连方式，见 `link_traffic`
+    pub topology: Topology,
+    pub seed: u64,
+    pub output: PathBuf,
+}
+
+impl Default for ExperimentConfig {
+    fn default() -> Self {
+        let controller = ControllerConfig::default();
+        ExperimentConfig {
+            threads: vec![4, 8, 16],
+            read_ratios: vec![0.5, 0.9, 0.99],
+            distributions: vec![KeyDistribution::Zipfian(0.99)],
+            protocols: vec![Protocol::Invalidate],
+            ops: 2000,
+            records: 1000,
+            db_latency: Duration::ZERO,
+            cache_size: controller.cache_size,
+            flush_size: controller.flush_size,
+            line_size: 1,
+            prefetch: None,
+            victims: 0,
+            topology: Topology::Crossbar,
+            seed: 0,
+            output: PathBuf::from("results"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+fn parse_list<T>(
+    value: &str,
+    line: usize,
+    f: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, ParseError> {
+    let list = value
+        .split(',')
+        .map(|v| {
+            f(v.trim()).ok_or(ParseError {
+                line,
+                msg: format!("bad value {:?}", v.trim()),
+            })
+        })
+        .collect::<Result<Vec<T>, ParseError>>()?;
+    if list.is_empty() {
+        return Err(ParseError {
+            line,
+            msg: "empty list".to_string(),
+        });
+    }
+    Ok(list)
+}
+
+fn parse_one<T>(value: &str, line: usize, f: impl Fn(&str) -> Option<T>) -> Result<T, ParseError> {
+    f(value).ok_or(ParseError {
+        line,
+        msg: format!("bad value {:?}", value),
+    })
+}
+
+impl ExperimentConfig {
+    /// `parse` 解析 `key = value` 形式的配置，`#` 之后为注释，未出现的 key 取默认值
+    ///
+    /// ```text
+    /// threads = 4, 8, 16
+    /// read_ratios = 0.5, 0.9
+    /// distributions = uniform, zipfian:0.99, hotspot:0.01:0.9
+    /// protocols = invalidate, update, write-through
+    /// ```
+    pub fn parse(text: &str) -> Result<ExperimentConfig, ParseError> {
+        let mut config = ExperimentConfig::default();
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or(ParseError {
+                line: line_no,
+                msg: format!("expected key = value, got {:?}", line),
+            })?;
+            let value = value.trim();
+            match key.trim() {
+                "threads" => {
+                    config.threads =
+                        parse_list(value, line_no, |v| v.parse().ok().filter(|n| *n > 0))?
+                }
+                "read_ratios" => {
+                    config.read_ratios = parse_list(value, line_no, |v| {
+                        v.parse().ok().filter(|r| (0.0..=1.0).contains(r))
+                    })?
+                }
+                "distributions" => {
+                    config.distributions = parse_list(value, line_no, |v| v.parse().ok())?
+                }
+                "protocols" => config.protocols = parse_list(value, line_no, |v| v.parse().ok())?,
+                "ops" => config.ops = parse_one(value, line_no, |v| v.parse().ok())?,
+                "records" => {
+                    config.records =
+                        parse_one(value, line_no, |v| v.parse().ok().filter(|n| *n > 0))?
+                }
+                "db_latency_us" => {
+                    config.db_latency =
+                        Duration::from_micros(parse_one(value, line_no, |v| v.parse().ok())?)
+                }
+                "cache_size" => config.cache_size = parse_one(value, line_no, |v| v.parse().ok())?,
+                "flush_size" => config.flush_size = parse_one(value, line_no, |v| v.parse().ok())?,
+                "line_size" => {
+                    config.line_size =
+                        parse_one(value, line_no, |v| v.parse().ok().filter(|n| *n > 0))?
+                }
+                "prefetch" => {
+                    config.prefetch = match value {
+                        "none" => None,
+                        v => Some(parse_one(v, line_no, |v| v.parse().ok())?),
+                    }
+                }
+                "victims" => config.victims = parse_one(value, line_no, |v| v.parse().ok())?,
+                "topology" => config.topology = parse_one(value, line_no, |v| v.parse().ok())?,
+                "seed" => config.seed = parse_one(value, line_no, |v| v.parse().ok())?,
+                "output" => config.output = PathBuf::from(value),
+                key => {
+                    return Err(ParseError {
+                        line: line_no,
+                        msg: format!("unknown key {:?}", key),
+                    })
+                }
+            }
+        }
+        if config.flush_size >= config.cache_size {
+            return Err(ParseError {
+                line: 0,
+                msg: "flush_size must be less than cache_size".to_string(),
+            });
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<ExperimentConfig, ParseError> {
+        let text = fs::read_to_string(path.as_ref()).map_err(|e| ParseError {
+            line: 0,
+            msg: format!("{}: {}", path.as_ref().display(), e),
+        })?;
+        ExperimentConfig::parse(&text)
+    }
+}
+
+/// `ExperimentResult` 一次实验的结果
+#[derive(Debug, Clone)]
+pub struct ExperimentResult {
+    pub distribution: KeyDistribution,
+    pub read_ratio: f64,
+    pub threads: usize,
+    pub protocol: Protocol,
+    pub ops: u64,
+    pub elapsed: Duration,
+    pub stats: Stats,
+    /// Directory 发出和收到的消息数
+    pub messages: u64,
+    pub directory: DirectoryStats,
+    /// 所有操作的延迟
+    pub latency: Histogram,
+    pub topology: Topology,
+    /// 经过拓扑的消息数，每条消息经过一条或多条链路
+    pub network_messages: u64,
+    /// 每条有过流量的链路
+    pub links: Vec<(Link, LinkStats)>,
+}
+
+impl ExperimentResult {
+    pub fn throughput(&self) -> f64 {
+        self.ops as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// `traffic` 目录的消息数加上线程发往目录的请求及其回复
+    pub fn traffic(&self) -> u64 {
+        self.messages + 2 * (self.directory.read_requests + self.directory.write_requests)
+    }
+
+    fn per_op(&self, n: u64) -> f64 {
+        n as f64 / self.ops as f64
+    }
+
+    /// `hops_per_message` 每条消息平均经过的链路数
+    pub fn hops_per_message(&self) -> f64 {
+        if self.network_messages == 0 {
+            return 0.0;
+        }
+        let hops: u64 = self.links.iter().map(|(_, stats)| stats.messages).sum();
+        hops as f64 / self.network_messages as f64
+    }
+
+    /// `link_share` 一条链路的字节数占所有链路字节数的比例
+    pub fn link_share(&self, stats: &LinkStats) -> f64 {
+        let bytes: u64 = self.links.iter().map(|(_, stats)| stats.bytes).sum();
+        if bytes == 0 {
+            return 0.0;
+        }
+        stats.bytes as f64 / bytes as f64
+    }
+
+    /// `busiest_link` 字节数最多的链路
+    pub fn busiest_link(&self) -> Option<&(Link, LinkStats)> {
+        self.links.iter().max_by_key(|(_, stats)| stats.bytes)
+    }
+
+    /// `CSV_HEADER` 先是实验的配置，再是测得的结果
+    pub const CSV_HEADER: &'static str = "distribution,protocol,topology,read_ratio,threads,ops,seconds,throughput,hit_ratio,messages,messages_per_op,traffic_per_op,invalidations,updates,write_backs,db_writes,false_sharing,p50_us,p99_us,prefetch_accuracy,prefetch_coverage,victim_hits,links,hops_per_message,max_link_share";
+
+    pub fn csv(&self) -> String {
+        let us = |ns: u64| ns as f64 / 1000.0;
+        format!(
+            "{},{},{},{},{},{},{:.6},{:.1},{:.4},{},{:.4},{:.4},{},{},{},{},{},{:.1},{:.1},{:.4},{:.4},{},{},{:.3},{:.4}",
+            self.distribution,
+            self.protocol,
+            self.topology,
+            self.read_ratio,
+            self.threads,
+            self.ops,
+            self.elapsed.as_secs_f64(),
+            self.throughput(),
+            self.stats.hit_ratio(),
+            self.messages,
+            self.messages as f64 / self.ops as f64,
+            self.per_op(self.traffic()),
+            self.stats.invalidations_sent,
+            self.directory.updates,
+            self.stats.write_backs,
+            self.directory.db_writes,
+            self.stats.false_sharing_invalidations,
+            us(self.latency.percentile(50.0)),
+            us(self.latency.percentile(99.0)),
+            self.stats.prefetch_accuracy(),
+            self.stats.prefetch_coverage(),
+            self.stats.victim_hits,
+            self.links.len(),
+            self.hops_per_message(),
+            self.busiest_link()
+                .map_or(0.0, |(_, stats)| self.link_share(stats)),
+        )
+    }
+}
+
+/// `link_traffic` 把目录记录的消息按拓扑转发，得到每条链路的流量，返回消息数
+/// 消息大小与模拟器的 `CostModel` 相同；多线程运行没有虚拟时间，所有消息都在 0 时刻发出，
+/// 只统计每条链路的消息数和字节数
+fn link_traffic(
+    records: &[TraceRecord],
+    directory: &Directory,
+    placement: &Placement,
+    nodes: &HashMap<ThreadID, usize>,
+    network: &mut Network,
+) -> u64 {
+    let cost = CostModel::default();
+    let mut messages = 0;
+    for r in records.iter().filter(|r| r.side == Side::Directory) {
+        // 属于更早请求的确认不知道 key，也就不知道发往哪个分片，不计入
+        if r.key.is_empty() {
+            continue;
+        }
+        let (node, slice) = (nodes[&r.thread_id], placement.slice(directory.home(&r.key)));
+        match r.kind {
+            // 请求和回复，读请求的回复带数据
+            TraceKind::Transaction(_) => {
+                let bytes = match r.event {
+                    Event::RemoteRead(_) => cost.data_bytes,
+                    _ => cost.control_bytes,
+                };
+                network.send(0, node, slice, cost.control_bytes);
+                network.send(0, slice, node, bytes);
+                messages += 2;
+            }
+            // 写更新的 Update 带着新值
+            TraceKind::Send => {
+                let bytes = match r.event {
+                    Event::Update(_, _) => cost.data_bytes,
+                    _ => cost.control_bytes,
+                };
+                network.send(0, slice, node, bytes);
+                messages += 1;
+            }
+            TraceKind::Receive => {
+                network.send(0, node, slice, cost.control_bytes);
+                messages += 1;
+            }
+        }
+    }
+    messages
+}
+
+/// `run_one` 在新的 Directory 上运行一次实验
+/// 运行时目录记录所有消息，结束后按 `ExperimentConfig::topology` 统计链路流量
+pub fn run_one(
+    config: &ExperimentConfig,
+    distribution: &KeyDistribution,
+    read_ratio: f64,
+    threads: usize,
+    protocol: Protocol,
+) -> ExperimentResult {
+    let db = DbSession::temporary().with_latency(config.db_latency);
+    let directory = Arc::new(RwLock::new(Directory::with_db(
+        db,
+        DirectoryConfig {
+            protocol,
+            line_size: config.line_size,
+            trace: true,
+            ..DirectoryConfig::default()
+        },
+    )));
+    let workload = Workload::new(WorkloadConfig {
+        records: config.records,
+        distribution: distribution.clone(),
+        read: read_ratio,
+        update: 1.0 - read_ratio,
+        key_prefix: "".to_string(),
+        ..WorkloadConfig::default()
+    });
+    let controller = ControllerConfig {
+        cache_size: config.cache_size,
+        flush_size: config.flush_size,
+        prefetch: config.prefetch,
+        victims: config.victims,
+        ..ControllerConfig::default()
+    };
+    let controllers: Vec<CacheController<String>> = (0..threads)
+        .map(|_| CacheController::with_config(directory.clone(), controller.clone()))
+        .collect();
+    let placement = Placement::new(threads, directory.read().slices());
+    let nodes: HashMap<ThreadID, usize> = controllers
+        .iter()
+        .enumerate()
+        .map(|(i, ct)| (ct.thread_id, placement.controller(i)))
+        .collect();
+
+    let start = Instant::now();
+    let controllers = workload.run_threads(controllers, config.ops, config.seed);
+    let elapsed = start.elapsed();
+
+    let mut stats = Stats::default();
+    let mut latency = Histogram::new();
+    for ct in &controllers {
+        stats.merge(&ct.stats());
+        let l = ct.latencies();
+        latency.merge(&l.hit);
+        latency.merge(&l.miss);
+        latency.merge(&l.upgrade);
+    }
+    let mut network = Network::new(
+        config.topology,
+        placement.nodes(),
+        0,
+        CostModel::default().link_bandwidth,
+    );
+    let network_messages = {
+        let directory = directory.read();
+        let records = directory.tracer().unwrap().records();
+        link_traffic(&records, &directory, &placement, &nodes, &mut network)
+    };
+    let directory = directory.read().stats();
+    ExperimentResult {
+        distribution: distribution.clone(),
+        read_ratio,
+        threads,
+        protocol,
+        ops: (threads * config.ops) as u64,
+        elapsed,
+        stats,
+        messages: directory.messages(),
+        directory,
+        latency,
+        topology: config.topology,
+        network_messages,
+        links: network.links(),
+    }
+}
+
+/// `run` 依次运行所有组合，每完成一次调用 progress
+pub fn run(
+    config: &ExperimentConfig,
+    mut progress: impl FnMut(&ExperimentResult),
+) -> Vec<ExperimentResult> {
+    let mut results = Vec::new();
+    for distribution in &config.distributions {
+        for read_ratio in &config.read_ratios {
+            for threads in &config.threads {
+                for protocol in &config.protocols {
+                    let result = run_one(config, distribution, *read_ratio, *threads, *protocol);
+                    progress(&result);
+                    results.push(result);
+                }
+            }
+        }
+    }
+    results
+}
+
+pub fn write_csv(results: &[ExperimentResult], path: &Path) -> io::Result<()> {
+    let mut w = io::BufWriter::new(fs::File::create(path)?);
+    writeln!(w, "{}", ExperimentResult::CSV_HEADER)?;
+    for r in results {
+        writeln!(w, "{}", r.csv())?;
+    }
+    w.flush()
+}
+
+/// `write_links` 每次实验的每条链路一行
+pub fn write_links(results: &[ExperimentResult], path: &Path) -> io::Result<()> {
+    let mut w = io::BufWriter::new(fs::File::create(path)?);
+    writeln!(
+        w,
+        "distribution,protocol,topology,read_ratio,threads,from,to,messages,bytes,share"
+    )?;
+    for r in results {
+        for ((from, to), stats) in &r.links {
+            writeln!(
+                w,
+                "{},{},{},{},{},{},{},{},{},{:.4}",
+                r.distribution,
+                r.protocol,
+                r.topology,
+                r.read_ratio,
+                r.threads,
+                from,
+                to,
+                stats.messages,
+                stats.bytes,
+                r.link_share(stats),
+            )?;
+        }
+    }
+    w.flush()
+}
+
+/// `compare` 按分布、读比例和线程数分组，列出每种协议每次操作的消息数，以及相对写失效的比例
+pub fn compare(results: &[ExperimentResult]) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{:<20} {:>5} {:>7}  {:<14} {:>8} {:>10} {:>9} {:>8}",
+        "distribution", "read", "threads", "protocol", "msgs/op", "traffic/op", "db/op", "vs inv"
+    )
+    .unwrap();
+    for r in results {
+        let invalidate = results.iter().find(|i| {
+            i.protocol == Protocol::Invalidate
+                && i.distribution == r.distribution
+                && i.read_ratio == r.read_ratio
+                && i.threads == r.threads
+        });
+        let ratio = match invalidate {
+            Some(i) if i.traffic() > 0 => {
+                format!("{:.2}x", r.traffic() as f64 / i.traffic() as f64)
+            }
+            _ => "-".to_string(),
+        };
+        writeln!(
+            out,
+            "{:<20} {:>5} {:>7}  {:<14} {:>8.3} {:>10.3} {:>9.3} {:>8}",
+            r.distribution.to_string(),
+            r.read_ratio,
+            r.threads,
+            r.protocol.to_string(),
+            r.per_op(r.messages),
+            r.per_op(r.traffic()),
+            r.per_op(r.directory.db_writes),
+            ratio
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// `file_name` 将分布名转换为可以作为文件名的形式
+fn file_name(distribution: &KeyDistribution) -> String {
+    distribution
+        .to_string()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// `render_charts` 为每种分布画出吞吐量、命中率和每次操作的消息数随线程数变化的折线图
+/// 每个读比例一条线，有多种协议时每种协议和读比例的组合一条线
+pub fn render_charts(results: &[ExperimentResult], dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut distributions: Vec<&KeyDistribution> = Vec::new();
+    let mut protocols: Vec<Protocol> = Vec::new();
+    for r in results {
+        if !distributions.contains(&&r.distribution) {
+            distributions.push(&r.distribution);
+        }
+        if !protocols.contains(&r.protocol) {
+            protocols.push(r.protocol);
+        }
+    }
+
+    let mut files = Vec::new();
+    for d in distributions {
+        let mut ratios: Vec<f64> = Vec::new();
+        for r in results.iter().filter(|r| &r.distribution == d) {
+            if !ratios.contains(&r.read_ratio) {
+                ratios.push(r.read_ratio);
+            }
+        }
+        let series = |y: &dyn Fn(&ExperimentResult) -> f64| -> Vec<Series> {
+            protocols
+                .iter()
+                .flat_map(|p| ratios.iter().map(move |ratio| (p, ratio)))
+                .map(|(p, ratio)| Series {
+                    label: if protocols.len() > 1 {
+                        format!("{} read {}", p, ratio)
+                    } else {
+                        format!("read {}", ratio)
+                    },
+                    points: results
+                        .iter()
+                        .filter(|r| {
+                            &r.distribution == d && r.read_ratio == *ratio && r.protocol == *p
+                        })
+                        .map(|r| (r.threads as f64, y(r)))
+                        .collect(),
+                })
+                .collect()
+        };
+
+        let path = dir.join(format!("throughput_{}.svg", file_name(d)));
+        line_chart(
+            &path,
+            &format!("Throughput ({})", d),
+            "threads",
+            "ops/s",
+            &series(&|r| r.throughput()),
+        )?;
+        files.push(path);
+
+        let path = dir.join(format!("hit_rate_{}.svg", file_name(d)));
+        line_chart(
+            &path,
+            &format!("Hit rate ({})", d),
+            "threads",
+            "hit rate",
+            &series(&|r| r.stats.hit_ratio()),
+        )?;
+        files.push(path);
+
+        let path = dir.join(format!("messages_{}.svg", file_name(d)));
+        line_chart(
+            &path,
+            &format!("Messages per op ({})", d),
+            "threads",
+            "messages/op",
+            &series(&|r| r.per_op(r.traffic())),
+        )?;
+        files.push(path);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = ExperimentConfig::parse(
+            "# sweep\nthreads = 1, 2\nread_ratios = 0.5\n\ndistributions = uniform, hotspot:0.1:0.9 # skew\nops = 10\ndb_latency_us = 5\n",
+        )
+        .unwrap();
+        assert_eq!(config.threads, vec![1, 2]);
+        assert_eq!(config.read_ratios, vec![0.5]);
+        assert_eq!(config.distributions.len(), 2);
+        assert_eq!(config.ops, 10);
+        assert_eq!(config.db_latency, Duration::from_micros(5));
+        assert_eq!(config.records, ExperimentConfig::default().records);
+        assert_eq!(config.protocols, vec![Protocol::Invalidate]);
+        let config = ExperimentConfig::parse("protocols = mesi, write-through, dragon").unwrap();
+        assert_eq!(
+            config.protocols,
+            vec![
+                Protocol::Invalidate,
+                Protocol::WriteThrough,
+                Protocol::Update
+            ]
+        );
+        assert!(ExperimentConfig::parse("protocols = msi").is_err());
+        assert_eq!(
+            ExperimentConfig::parse("line_size = 8").unwrap().line_size,
+            8
+        );
+        assert!(ExperimentConfig::parse("line_size = 0").is_err());
+        let config = ExperimentConfig::parse("prefetch = stride:2").unwrap();
+        assert_eq!(config.prefetch, Some(PrefetchConfig::Stride(2)));
+        assert!(ExperimentConfig::parse("prefetch = none")
+            .unwrap()
+            .prefetch
+            .is_none());
+        assert!(ExperimentConfig::parse("prefetch = all").is_err());
+        assert_eq!(ExperimentConfig::parse("victims = 4").unwrap().victims, 4);
+        assert_eq!(
+            ExperimentConfig::parse("topology = mesh:4")
+                .unwrap()
+                .topology,
+            Topology::Mesh { width: 4 }
+        );
+        assert!(ExperimentConfig::parse("topology = torus").is_err());
+
+        let err = ExperimentConfig::parse("threads = 4\nread_ratios = 1.5\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(ExperimentConfig::parse("speed = 1").unwrap_err().line, 1);
+        assert!(ExperimentConfig::parse("threads").is_err());
+        assert!(ExperimentConfig::parse("cache_size = 8\nflush_size = 8").is_err());
+    }
+
+    #[test]
+    fn test_run_and_render() {
+        let config = ExperimentConfig {
+            threads: vec![1, 2],
+            read_ratios: vec![0.5, 0.9],
+            distributions: vec![KeyDistribution::Uniform],
+            ops: 50,
+            records: 20,
+            topology: Topology::Ring,
+            ..ExperimentConfig::default()
+        };
+        let mut n = 0;
+        let results = run(&config, |_| n += 1);
+        assert_eq!(n, 4);
+        assert!(results
+            .iter()
+            .all(|r| r.stats.hits() + r.stats.misses() >= r.ops));
+        // 每次未命中至少有请求和回复两条消息经过拓扑
+        for r in &results {
+            assert!(r.network_messages >= 2 * r.stats.misses());
+            assert!(!r.links.is_empty());
+            assert!(r.hops_per_message() >= 1.0);
+        }
+
+        let dir = std::env::temp_dir().join(format!("mymesi_experiment_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("results.csv");
+        write_csv(&results, &csv).unwrap();
+        let text = fs::read_to_string(&csv).unwrap();
+        assert_eq!(text.lines().count(), 5);
+        let row = text.lines().nth(1).unwrap();
+        assert!(row.starts_with("uniform,invalidate,ring,0.5,1,50,"));
+        assert_eq!(
+            row.split(',').count(),
+            ExperimentResult::CSV_HEADER.split(',').count()
+        );
+        let links = dir.join("links.csv");
+        write_links(&results, &links).unwrap();
+        let text = fs::read_to_string(&links).unwrap();
+        let rows = results.iter().map(|r| r.links.len()).sum::<usize>();
+        assert_eq!(text.lines().count(), rows + 1);
+        assert!(text.lines().nth(1).unwrap().contains(",ring,"));
+
+        let files = render_charts(&results, &dir).unwrap();
+        assert_eq!(files.len(), 3);
+        for f in &files {
+            let svg = fs::read_to_string(f).unwrap();
+            assert!(svg.starts_with("<svg") && svg.contains("read 0.9"));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
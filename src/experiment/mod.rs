@@ -0,0 +1,3 @@
+pub mod chart;
+#[allow(clippy::module_inception)]
+pub mod experiment;
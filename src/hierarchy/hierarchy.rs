@@ -0,0 +1,228 @@
+//! 两级一致性：每个集群有自己的目录，跟踪集群内的共享者；
+//! 全局目录把每个集群当作一个共享者，跟踪哪些集群持有副本、是否有集群独占。
+//!
+//! 集群内的线程读写 key 时，集群目录先向全局目录取得集群级的权限：
+//! 读需要集群在共享者中，写需要集群独占。权限不足时全局目录使其他集群的副本失效或降级，
+//! 这些集群再向自己的共享者广播。锁的顺序总是全局目录项、集群目录项、集群的 socket，
+//! 取得的全局目录项在集群内的事务结束前一直持有。
+use crate::db::db::DbSession;
+use crate::stats::stats::inc;
+use crate::{Directory, DirectoryConfig, Event, Protocol};
+use dashmap::mapref::one::RefMut;
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+/// `ClusterID` 集群在全局目录中的编号
+pub type ClusterID = usize;
+
+/// `ClusterEntry` 全局目录中一个 key 的共享集群
+#[derive(Debug, Clone, Default)]
+pub struct ClusterEntry {
+    pub sharers: Vec<ClusterID>,
+    /// 唯一的共享集群独占该 key，集群内可以有 Exclusive 或 Modified 副本
+    pub exclusive: bool,
+}
+
+/// `HierarchyStats` 全局目录的统计快照，只包含集群之间的消息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HierarchyStats {
+    /// 集群内已有足够权限、不需要全局目录参与的请求
+    pub local_requests: u64,
+    /// 集群向全局目录发出的读、写请求
+    pub read_requests: u64,
+    pub write_requests: u64,
+    /// 发往其他集群的 RemoteWrite，每个集群使自己的所有副本失效
+    pub invalidations: u64,
+    /// 发往独占集群的 RemoteRead，该集群的副本降级为 Shared
+    pub downgrades: u64,
+    /// 从独占（E/M）的集群取得数据的次数
+    pub transfers: u64,
+}
+
+impl HierarchyStats {
+    /// `messages` 集群之间的消息总数，每个请求和每次失效、降级各有一条回复
+    pub fn messages(&self) -> u64 {
+        2 * (self.read_requests + self.write_requests + self.invalidations + self.downgrades)
+    }
+
+    /// `locality` 在集群内完成的请求比例
+    pub fn locality(&self) -> f64 {
+        let total = self.local_requests + self.read_requests + self.write_requests;
+        if total == 0 {
+            return 0.0;
+        }
+        self.local_requests as f64 / total as f64
+    }
+}
+
+impl fmt::Display for HierarchyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "requests local {} global read {} write {} (locality {:.3})",
+            self.local_requests,
+            self.read_requests,
+            self.write_requests,
+            self.locality()
+        )?;
+        write!(
+            f,
+            "inter-cluster invalidations {} downgrades {} transfers {}",
+            self.invalidations, self.downgrades, self.transfers
+        )
+    }
+}
+
+/// `HierarchyCounters` GlobalDirectory 内部的计数器
+#[derive(Debug, Default)]
+struct HierarchyCounters {
+    pub local_requests: AtomicU64,
+    pub read_requests: AtomicU64,
+    pub write_requests: AtomicU64,
+    pub invalidations: AtomicU64,
+    pub downgrades: AtomicU64,
+    pub transfers: AtomicU64,
+}
+
+impl HierarchyCounters {
+    fn counters(&self) -> [&AtomicU64; 6] {
+        [
+            &self.local_requests,
+            &self.read_requests,
+            &self.write_requests,
+            &self.invalidations,
+            &self.downgrades,
+            &self.transfers,
+        ]
+    }
+
+    pub fn snapshot(&self) -> HierarchyStats {
+        let [local_requests, read_requests, write_requests, invalidations, downgrades, transfers] =
+            self.counters().map(|c| c.load(Ordering::Relaxed));
+        HierarchyStats {
+            local_requests,
+            read_requests,
+            write_requests,
+            invalidations,
+            downgrades,
+            transfers,
+        }
+    }
+
+    pub fn reset(&self) {
+        for c in self.counters() {
+            c.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// `GlobalDirectory` 全局目录，所有集群共享同一个 db
+pub struct GlobalDirectory {
+    map: DashMap<String, ClusterEntry>,
+    clusters: RwLock<Vec<Weak<RwLock<Directory>>>>,
+    db: DbSession,
+    stats: HierarchyCounters,
+}
+
+impl GlobalDirectory {
+    pub fn new(db: DbSession) -> Arc<GlobalDirectory> {
+        Arc::new(GlobalDirectory {
+            map: DashMap::with_shard_amount(1024),
+            clusters: RwLock::new(Vec::new()),
+            db,
+            stats: HierarchyCounters::default(),
+        })
+    }
+
+    /// `cluster` 创建一个新的集群目录，集群的 CacheController 使用它作为目录
+    /// 应在开始读写前创建所有集群
+    ///
+    /// 不支持租约协议：每个集群目录有自己的逻辑时间和租约记录，
+    /// 一个集群中的写无法使其他集群的租约过期
+    pub fn cluster(self: &Arc<Self>, config: DirectoryConfig) -> Arc<RwLock<Directory>> {
+        assert!(
+            config.protocol != Protocol::Lease,
+            "lease protocol is not supported in a cluster directory"
+        );
+        let mut clusters = self.clusters.write();
+        let mut directory = Directory::with_db(self.db.clone(), config);
+        directory.parent = Some((self.clone(), clusters.len()));
+        let directory = Arc::new(RwLock::new(directory));
+        clusters.push(Arc::downgrade(&directory));
+        directory
+    }
+
+    pub fn clusters(&self) -> usize {
+        self.clusters.read().len()
+    }
+
+    /// `entry` key 在全局目录中的共享集群
+    pub fn entry(&self, key: &str) -> Option<ClusterEntry> {
+        self.map.get(key).map(|e| e.clone())
+    }
+
+    pub fn stats(&self) -> HierarchyStats {
+        self.stats.snapshot()
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// `acquire` cluster 中的线程读写 key 前取得集群级的权限
+    /// 返回的目录项在集群内的事务结束前持有；读时第二项为其他集群是否也有副本
+    pub(crate) fn acquire(
+        &self,
+        cluster: ClusterID,
+        key: &str,
+        write: bool,
+    ) -> (RefMut<'_, String, ClusterEntry>, bool) {
+        let mut e = self.map.entry(key.to_string()).or_default();
+        if write {
+            if e.exclusive && e.sharers == [cluster] {
+                inc(&self.stats.local_requests);
+            } else {
+                inc(&self.stats.write_requests);
+                for c in e.sharers.clone() {
+                    if c == cluster {
+                        continue;
+                    }
+                    inc(&self.stats.invalidations);
+                    if e.exclusive {
+                        inc(&self.stats.transfers);
+                    }
+                    self.remote(c, Event::RemoteWrite(key.to_string()));
+                }
+                e.sharers = vec![cluster];
+                e.exclusive = true;
+            }
+            return (e, false);
+        }
+
+        if e.sharers.contains(&cluster) {
+            inc(&self.stats.local_requests);
+        } else {
+            inc(&self.stats.read_requests);
+            if e.exclusive {
+                // 独占的集群降级，其中 Modified 的副本以及 LLC 中的脏数据写回 db
+                inc(&self.stats.downgrades);
+                inc(&self.stats.transfers);
+                self.remote(e.sharers[0], Event::RemoteRead(key.to_string()));
+            }
+            e.sharers.push(cluster);
+            e.exclusive = e.sharers.len() == 1;
+        }
+        let shared = e.sharers.len() > 1;
+        (e, shared)
+    }
+
+    fn remote(&self, cluster: ClusterID, event: Event) {
+        let directory = self.clusters.read()[cluster].upgrade();
+        if let Some(directory) = directory {
+            directory.read().recall(event);
+        }
+    }
+}
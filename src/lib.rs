@@ -1,17 +1,66 @@
+pub mod adaptive;
 pub mod db;
+pub mod experiment;
+pub mod hierarchy;
+pub mod line;
+pub mod litmus;
+pub mod llc;
+pub mod metrics;
+pub mod mshr;
+pub mod oracle;
+pub mod prefetch;
+pub mod recording;
+pub mod sim;
+pub mod stats;
 pub mod thread_socket;
+pub mod topology;
+pub mod trace;
+pub mod victim;
+pub mod watchdog;
+pub mod workload;
 
+use crate::adaptive::adaptive::{
+    Adaptive, AdaptiveConfig, AdaptiveStats, KeyPattern, SharingPattern,
+};
 use crate::db::db::DbSession;
-use crate::thread_socket::thread_socket::{new_socket, ThreadSocket};
-use dashmap::DashMap;
+use crate::hierarchy::hierarchy::{ClusterID, GlobalDirectory};
+use crate::line::line::LineMap;
+use crate::llc::llc::{Effects, Llc, LlcConfig, LlcStats};
+use crate::mshr::mshr::{Allocation, Mshrs, Ticket};
+use crate::prefetch::prefetch::{PrefetchConfig, Prefetcher};
+use crate::recording::recording::{AccessOp, AccessRecord, Recorder};
+use crate::stats::histogram::{AtomicHistogram, Histogram};
+use crate::stats::stats::{
+    inc, ControllerStats, DirectoryCounters, DirectoryLatencies, DirectoryLatencyRecorder,
+    DirectoryStats, Latencies, LatencyRecorder, Stats,
+};
+use crate::thread_socket::faulty_transport::{
+    FaultConfig, FaultCount, FaultCounters, FaultyTransport,
+};
+use crate::thread_socket::thread_socket::{new_socket, Transport};
+use crate::trace::trace::{Side, TraceKind, TraceRecord, Tracer};
+use crate::victim::victim::VictimBuffer;
+use dashmap::mapref::entry::Entry;
+use dashmap::{DashMap, DashSet};
 use parking_lot::{Mutex, RwLock};
-use std::collections::VecDeque;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Weak;
+use std::time::{Duration, Instant};
 use std::{sync::Arc, thread};
 
 #[derive(Debug)]
 pub enum Event {
     RemoteRead(String),
     RemoteWrite(String),
+    /// 写更新协议下把 key 的新值推送给共享者
+    Update(String, String),
     Confirmed(bool),
 }
 
@@ -20,6 +69,7 @@ impl Event {
         match self {
             Event::RemoteRead(id) => id,
             Event::RemoteWrite(id) => id,
+            Event::Update(id, _) => id,
             Event::Confirmed(_) => panic!("Confirmed event has no id"),
         }
     }
@@ -30,11 +80,21 @@ impl Clone for Event {
         match self {
             Event::RemoteRead(id) => Event::RemoteRead(id.clone()),
             Event::RemoteWrite(id) => Event::RemoteWrite(id.clone()),
-            Event::Confirmed(b) => Event::Confirmed(b.clone()),
+            Event::Update(id, v) => Event::Update(id.clone(), v.clone()),
+            Event::Confirmed(b) => Event::Confirmed(*b),
         }
     }
 }
 
+/// `Message` socket 上传输的消息
+/// seq 由 Directory 为每个 socket 单调递增地分配，重传时保持不变，
+/// 用于丢弃过期、重复的消息
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub seq: u64,
+    pub event: Event,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Status {
     Modified,
@@ -54,16 +114,172 @@ impl Clone for Status {
     }
 }
 
-type ThreadID = usize;
+impl Status {
+    pub const ALL: [Status; 4] = [
+        Status::Modified,
+        Status::Exclusive,
+        Status::Shared,
+        Status::Invalid,
+    ];
+
+    /// `index` 状态在统计数组中的下标
+    pub fn index(&self) -> usize {
+        match self {
+            Status::Modified => 0,
+            Status::Exclusive => 1,
+            Status::Shared => 2,
+            Status::Invalid => 3,
+        }
+    }
+}
+
+pub type ThreadID = usize;
 
 const CACHE_SIZE: usize = 1 << 10;
 const FLUSH_SIZE: usize = 1 << 7;
 
+/// `ConsistencyModel` CacheController 提供的内存一致性模型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConsistencyModel {
+    /// 顺序一致性，写操作同步地全局可见
+    SC,
+    /// 写操作进入 FIFO 的 store buffer，允许写-读乱序（x86）
+    TSO,
+    /// 不同地址的写操作可以乱序离开 store buffer，允许写-写乱序（SPARC PSO）
+    PSO,
+}
+
+/// `Protocol` 写操作如何维护其他副本的一致性，由 Directory 统一配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// MESI 写失效，写之前使其他副本失效
+    Invalidate,
+    /// 写直达、写不分配：每次写都经过目录写入 LLC 或 db，使其他副本失效，
+    /// 写缺失时不装入缓存，缓存中不会有 Modified 的副本
+    WriteThrough,
+    /// 写更新（Dragon/Firefly）：写共享的 key 时把新值推送给其他共享者并写入 LLC 或 db，
+    /// 共享者的副本保持有效；没有其他共享者时与 MESI 相同，在本地修改
+    Update,
+    /// 时间戳租约：读向目录取得到某个逻辑时间为止的租约，目录不记录共享者；
+    /// 写经过目录写入 LLC 或 db，把逻辑时间推到该 key 所有未到期的租约之后，不发送失效消息，
+    /// 线程读时发现副本的租约已过期就自失效，重新取得租约。
+    /// 逻辑时间由目录的所有分片共用，写操作跳过的租约包括其他 key 上更早授予的租约。
+    /// 集群目录之间仍然按共享者写失效，不能使用租约
+    Lease,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 4] = [
+        Protocol::Invalidate,
+        Protocol::WriteThrough,
+        Protocol::Update,
+        Protocol::Lease,
+    ];
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Invalidate => write!(f, "invalidate"),
+            Protocol::WriteThrough => write!(f, "write-through"),
+            Protocol::Update => write!(f, "update"),
+            Protocol::Lease => write!(f, "lease"),
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "invalidate" | "mesi" => Ok(Protocol::Invalidate),
+            "write-through" => Ok(Protocol::WriteThrough),
+            "update" | "dragon" | "firefly" => Ok(Protocol::Update),
+            "lease" | "tardis" => Ok(Protocol::Lease),
+            _ => Err(format!("unknown protocol {:?}", s)),
+        }
+    }
+}
+
+/// `ControllerConfig` CacheController 配置
+#[derive(Debug, Clone)]
+pub struct ControllerConfig {
+    pub model: ConsistencyModel,
+    /// store buffer 的容量，写满后强制写回一项
+    pub store_buffer_size: usize,
+    /// 每次读写操作之前，store buffer 写回一项的概率
+    pub drain_probability: f64,
+    /// 缓存项数达到 cache_size 时批量淘汰，只保留 flush_size 项
+    pub cache_size: usize,
+    pub flush_size: usize,
+    /// store buffer 随机写回使用的种子，为 None 时每次运行都不同
+    pub seed: Option<u64>,
+    /// 读之后预取的方式，默认不预取，见 `CacheController::attach_prefetcher`
+    pub prefetch: Option<PrefetchConfig>,
+    /// 非阻塞读同时未完成的缺失数上限，见 `CacheController::issue`
+    pub mshrs: usize,
+    /// 被批量淘汰的项先放入的 victim buffer 的容量，为 0 时不使用，见 `victim` 模块
+    pub victims: usize,
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        ControllerConfig {
+            model: ConsistencyModel::SC,
+            store_buffer_size: 8,
+            drain_probability: 0.5,
+            cache_size: CACHE_SIZE,
+            flush_size: FLUSH_SIZE,
+            seed: None,
+            prefetch: None,
+            mshrs: 8,
+            victims: 0,
+        }
+    }
+}
+
+/// `Access` 一次读写操作的类型，决定延迟记入哪个直方图
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    Hit,
+    Miss,
+    Upgrade,
+}
+
+/// `Caches` 一个线程的缓存项
+/// 使用固定的哈希函数和分片数，批量淘汰时保留哪些项不随进程和机器变化
+pub type Caches<T> = DashMap<String, Cache<T>, BuildHasherDefault<DefaultHasher>>;
+
+const CACHE_SHARDS: usize = 8;
+
 #[derive(Clone)]
 pub struct CacheController<T: Clone + ToString + Sync> {
-    caches: Arc<DashMap<String, Cache<T>>>,
+    caches: Arc<Caches<T>>,
     directory: Arc<RwLock<Directory>>,
     pub thread_id: ThreadID,
+    config: ControllerConfig,
+    // 创建时从 Directory 读取
+    protocol: Protocol,
+    adaptive: bool,
+    // 租约协议下目录的逻辑时间，其他协议为 None
+    clock: Option<Arc<AtomicU64>>,
+    // 尚未全局可见的写操作，TSO 和 PSO 下使用
+    store_buffer: VecDeque<(String, T)>,
+    rng: StdRng,
+    // 与监听线程共享
+    stats: Arc<ControllerStats>,
+    latencies: Arc<LatencyRecorder>,
+    // 记录收到的每一次读写，用于重放
+    recorder: Option<Arc<Recorder>>,
+    // 与 clone 出的 controller 共享，和缓存项一样
+    prefetcher: Option<Arc<Mutex<Box<dyn Prefetcher>>>>,
+    // 预取装入、还没有被读过的 key
+    prefetched: HashSet<String>,
+    // 非阻塞读未完成的缺失，与完成缺失的后台线程共享
+    mshrs: Arc<Mshrs<T>>,
+    // 与监听线程共享，没有配置 victim buffer 时为 None
+    victims: Option<Arc<Mutex<VictimBuffer<T>>>>,
 
     // 一些测试指标
     op_cnt: u32,
@@ -73,261 +289,1801 @@ pub struct CacheController<T: Clone + ToString + Sync> {
 
 impl<T: Clone + ToString + Sync + From<String> + 'static> CacheController<T> {
     pub fn new(directory: Arc<RwLock<Directory>>) -> CacheController<T> {
-        let caches: Arc<DashMap<String, Cache<T>>> = Arc::new(DashMap::new());
+        CacheController::with_config(directory, ControllerConfig::default())
+    }
+
+    pub fn with_config(
+        directory: Arc<RwLock<Directory>>,
+        config: ControllerConfig,
+    ) -> CacheController<T> {
+        let caches: Arc<Caches<T>> = Arc::new(DashMap::with_hasher_and_shard_amount(
+            BuildHasherDefault::default(),
+            CACHE_SHARDS,
+        ));
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
 
-        let directory = directory.clone();
-        let (thread_id, socket) = directory.write().register();
+        let (thread_id, sockets) = directory.write().register();
+        let protocol = directory.read().protocol();
+        let adaptive = directory.read().adaptive();
+        let clock = (protocol == Protocol::Lease).then(|| directory.read().clock());
+        let prefetcher = config.prefetch.map(|p| Arc::new(Mutex::new(p.build())));
+        let mshrs = Arc::new(Mshrs::new(config.mshrs));
+        let victims =
+            (config.victims > 0).then(|| Arc::new(Mutex::new(VictimBuffer::new(config.victims))));
 
-        // 启动一个线程，监听来自 Directory 的消息
-        let t_id = thread_id.clone();
-        let mut _caches = caches.clone();
-        let _directory = directory.clone();
-        thread::spawn(move || {
-            loop {
-                let event = socket.receive();
-                // println!(
-                //     "thread {:?} receive message: {:?}",
-                //     t_id.clone(),
-                //     event.clone()
-                // );
-
-                let id = event.get_id();
-
-                let is_invalid = match _caches.get_mut(id) {
-                    None => true,
-                    Some(mut cache) => {
-                        if cache.status == Status::Modified {
-                            _directory
-                                .read()
-                                .write_back(cache.id.clone(), cache.value.clone());
-                        }
-                        cache.handle(&event)
+        // 供 watchdog 输出诊断信息时读取缓存状态
+        let probe = caches.clone();
+        directory.read().attach_probe(
+            thread_id,
+            Box::new(move || {
+                probe
+                    .iter()
+                    .map(|c| (c.key().clone(), c.status.clone()))
+                    .collect()
+            }),
+        );
+
+        // 为每个分片启动一个线程，监听来自该分片的消息，一个 key 的消息只来自它所在的分片
+        // 只持有 Directory 的弱引用，Directory 释放后 socket 关闭，线程退出
+        let stats = Arc::new(ControllerStats::default());
+        let tracer = directory.read().tracer();
+        let lines = directory.read().lines();
+        for socket in sockets {
+            let _caches = caches.clone();
+            let _victims = victims.clone();
+            let _stats = stats.clone();
+            let _directory: Weak<RwLock<Directory>> = Arc::downgrade(&directory);
+            let tracer = tracer.clone();
+            thread::spawn(move || {
+                let trace = |kind, key: &str, message: &Message| {
+                    if let Some(tracer) = &tracer {
+                        tracer.message(kind, Side::Listener, thread_id, None, key, message);
                     }
                 };
-                if is_invalid {
-                    _caches.remove(id);
-                }
 
-                socket.send(Event::Confirmed(is_invalid));
+                // 最近一次处理的请求及其回复，用于应答重传的请求
+                let mut last: Option<Message> = None;
+                while let Some(message) = socket.receive() {
+                    let event = &message.event;
+                    let id = event.get_id();
 
-                if _caches.len() < CACHE_SIZE {
-                    continue;
-                }
+                    if let Some(reply) = &last {
+                        if message.seq <= reply.seq {
+                            trace(TraceKind::Receive, id, &message);
+                            if message.seq == reply.seq {
+                                // 重复的请求，不再处理，直接重发回复
+                                trace(TraceKind::Send, id, reply);
+                                socket.send(reply.clone());
+                            }
+                            // 过期的请求
+                            continue;
+                        }
+                    }
 
-                // println!("thread {:?} flushed caches", t_id.clone());
+                    // 处理前的时刻和缓存项状态
+                    let traced = tracer.as_ref().map(|t| {
+                        let before = _caches
+                            .get(id)
+                            .map_or(Status::Invalid, |c| c.status.clone());
+                        (t.now(), before)
+                    });
 
-                let mut c = FLUSH_SIZE as i32;
-                _caches.retain(|_, _| {
-                    c -= 1;
-                    c > 0
-                });
-            }
-        });
+                    // 消息作用于 id 所在行的每一个 slot，整行都不再有效时回复已失效
+                    let mut is_invalid = true;
+                    let mut held = Vec::new();
+                    for slot in lines.slots(&lines.line(id)) {
+                        // 处理 slot 的缓存项，返回它是否已失效
+                        let mut handle = |cache: &mut Cache<T>| {
+                            let from = cache.status.clone();
+                            if from != Status::Invalid {
+                                held.push(slot.clone());
+                            }
+                            match event {
+                                Event::Update(_, v) if from != Status::Invalid && slot == *id => {
+                                    // 新值覆盖整个副本，Modified 的旧值不需要写回
+                                    cache.value = T::from(v.clone());
+                                    inc(&_stats.updates_received);
+                                }
+                                // 被隔离后目录已把副本视为失效，其他线程可能已经写入了更新的值，不再写回
+                                _ if from == Status::Modified => {
+                                    if let Some(directory) = _directory.upgrade() {
+                                        let directory = directory.read();
+                                        if !directory.is_fenced(thread_id) {
+                                            directory
+                                                .write_back(cache.id.clone(), cache.value.clone());
+                                            inc(&_stats.write_backs);
+                                        }
+                                    }
+                                }
+                                _ => {}
+                            }
+                            let slot_invalid = cache.handle(event);
+                            _stats.transit(&from, &cache.status);
+                            slot_invalid
+                        };
+                        let slot_invalid = match _caches.entry(slot.clone()) {
+                            Entry::Occupied(mut entry) => {
+                                let slot_invalid = handle(entry.get_mut());
+                                if slot_invalid {
+                                    entry.remove();
+                                }
+                                slot_invalid
+                            }
+                            // 持有分片的锁时查找 victim buffer，不会错过正在移入或移出的项
+                            Entry::Vacant(vacant) => {
+                                let slot_invalid = match &_victims {
+                                    None => true,
+                                    Some(victims) => {
+                                        let mut victims = victims.lock();
+                                        let slot_invalid =
+                                            victims.get_mut(&slot).is_none_or(handle);
+                                        if slot_invalid {
+                                            victims.remove(&slot);
+                                        }
+                                        slot_invalid
+                                    }
+                                };
+                                drop(vacant);
+                                slot_invalid
+                            }
+                        };
+                        is_invalid &= slot_invalid;
+                    }
+                    if let (Event::RemoteWrite(_), false) = (event, held.is_empty()) {
+                        inc(&_stats.invalidations_received);
+                        // 被写的 slot 不在本线程持有的 slot 中
+                        if !held.contains(id) {
+                            inc(&_stats.false_sharing_invalidations);
+                        }
+                    }
+
+                    let reply = Message {
+                        seq: message.seq,
+                        event: Event::Confirmed(is_invalid),
+                    };
+                    if let (Some(tracer), Some((ts, before))) = (&tracer, traced) {
+                        let after = _caches
+                            .get(id)
+                            .map_or(Status::Invalid, |c| c.status.clone());
+                        tracer.record(TraceRecord {
+                            ts,
+                            kind: TraceKind::Receive,
+                            side: Side::Listener,
+                            thread_id,
+                            requester: None,
+                            event: event.clone(),
+                            key: id.clone(),
+                            seq: message.seq,
+                            before: Some(before),
+                            after: Some(after),
+                        });
+                    }
+                    // 先记录再发送，记录的时间不晚于对方收到回复的时间
+                    trace(TraceKind::Send, id, &reply);
+                    socket.send(reply.clone());
+                    last = Some(reply);
+                }
+            });
+        }
 
         CacheController {
             caches,
             directory,
             thread_id,
+            config,
+            protocol,
+            adaptive,
+            clock,
+            store_buffer: VecDeque::new(),
+            rng,
+            stats,
+            latencies: Arc::new(LatencyRecorder::default()),
+            recorder: None,
+            prefetcher,
+            prefetched: HashSet::new(),
+            mshrs,
+            victims,
             op_cnt: 0,
             in_cache_cnt: 0,
         }
     }
 
     pub fn get(&mut self, id: String) -> T {
+        match self.recorder.clone() {
+            None => self.load(id),
+            Some(recorder) => {
+                let timestamp = recorder.now();
+                let value = self.load(id.clone());
+                recorder.record(AccessRecord {
+                    thread: self.thread_id,
+                    op: AccessOp::Get,
+                    key: id,
+                    value: value.to_string(),
+                    timestamp,
+                });
+                value
+            }
+        }
+    }
+
+    fn load(&mut self, id: String) -> T {
+        let start = Instant::now();
         self.op_cnt += 1;
+        self.rejoin();
+        self.mshrs.settle(&id);
+        self.maybe_drain();
 
-        {
+        // store-to-load forwarding
+        if let Some((_, v)) = self.store_buffer.iter().rev().find(|(k, _)| *k == id) {
+            self.in_cache_cnt += 1;
+            inc(&self.stats.read_hits);
+            let v = v.clone();
+            self.record(Access::Hit, start);
+            return v;
+        }
+
+        if let Some(v) = self.hit(&id) {
+            self.record(Access::Hit, start);
+            self.prefetch(&id, true);
+            return v;
+        }
+
+        inc(&self.stats.read_misses);
+        let v = Self::fetch(
+            &self.directory.read(),
+            self.thread_id,
+            self.protocol,
+            &self.caches,
+            &self.stats,
+            &id,
+        );
+        self.flush();
+        self.record(Access::Miss, start);
+        self.prefetch(&id, false);
+        v
+    }
+
+    /// `hit` 缓存中有 id 的有效副本时返回它的值，并记为一次读命中
+    fn hit(&mut self, id: &str) -> Option<T> {
+        self.promote(id);
+        let hit = {
             // 命中缓存
-            let cache = self.caches.get(&id);
-            if matches!(&cache, Some(c) if c.status != Status::Invalid) {
-                self.in_cache_cnt += 1;
-                return cache.unwrap().value.clone();
+            let cache = self.caches.get(id);
+            match cache.filter(|c| c.status != Status::Invalid) {
+                Some(c) if self.leased(&c) => Some(c.value.clone()),
+                Some(c) => {
+                    // 租约过期，自失效后重新取得租约
+                    inc(&self.stats.lease_expirations);
+                    self.stats.transit(&c.status, &Status::Invalid);
+                    None
+                }
+                None => None,
             }
             // 释放读锁
+        };
+        // 预取的项在被读到之前失效或被淘汰，没有用上
+        let prefetched = self.prefetched.remove(id);
+        if hit.is_some() {
+            self.in_cache_cnt += 1;
+            inc(&self.stats.read_hits);
+            if prefetched {
+                inc(&self.stats.prefetch_hits);
+            }
         }
+        hit
+    }
 
-        let (v, n): (T, usize) = self
-            .directory
-            .read()
-            .read(self.thread_id.clone(), id.clone());
+    /// `fetch` 读缺失：向目录取得 id 的数据，在目录项加锁期间装入缓存，避免装入前错过其他线程的广播
+    /// 阻塞的读和完成非阻塞读的后台线程共用
+    fn fetch(
+        directory: &Directory,
+        thread_id: ThreadID,
+        protocol: Protocol,
+        caches: &Caches<T>,
+        stats: &ControllerStats,
+        id: &str,
+    ) -> T {
+        let id = id.to_string();
+        if protocol == Protocol::Lease {
+            return directory.lease_read(thread_id, id.clone(), |v: &T, lease| {
+                stats.transit(&Status::Invalid, &Status::Shared);
+                let cache = Cache::new(id.clone(), v.clone(), Status::Shared);
+                caches.insert(id.clone(), Cache { lease, ..cache });
+            });
+        }
+        directory
+            .read(thread_id, id.clone(), |v: &T, n| {
+                let status = if n > 0 {
+                    Status::Shared
+                } else {
+                    Status::Exclusive
+                };
+                stats.transit(&Status::Invalid, &status);
+                caches.insert(id.clone(), Cache::new(id.clone(), v.clone(), status));
+            })
+            .0
+    }
 
-        let status = if n > 0 {
-            Status::Shared
-        } else {
-            Status::Exclusive
-        };
-        self.caches
-            .insert(id.clone(), Cache::new(id, v.clone(), status));
+    /// `poll` 取走已完成的非阻塞读，按完成的顺序，不等待
+    pub fn poll(&mut self) -> Vec<(Ticket, T)> {
+        self.flush();
+        self.mshrs.poll()
+    }
 
-        v
+    /// `wait` 取走最早完成的一个非阻塞读，没有已完成的读时等待，所有读都已取走时返回 None
+    pub fn wait(&mut self) -> Option<(Ticket, T)> {
+        let completed = self.mshrs.wait();
+        self.flush();
+        completed
+    }
+
+    /// `outstanding` 未完成的缺失数，合并的读不单独计数
+    pub fn outstanding(&self) -> usize {
+        self.mshrs.outstanding()
+    }
+
+    /// `mshr_occupancy` 每次分配 MSHR 之后被占用的 MSHR 数
+    pub fn mshr_occupancy(&self) -> Histogram {
+        self.mshrs.occupancy()
+    }
+
+    /// `prefetch` 把预取器给出的、不在缓存中的 key 以 Shared 装入缓存
+    /// 在读操作的延迟记录之后进行，不计入读的延迟
+    fn prefetch(&mut self, id: &str, hit: bool) {
+        let keys = match &self.prefetcher {
+            None => return,
+            Some(prefetcher) => prefetcher.lock().access(id, hit),
+        };
+        for key in keys {
+            let cached = self
+                .caches
+                .get(&key)
+                .is_some_and(|c| c.status != Status::Invalid && self.leased(&c));
+            let victim = self.victims.as_ref();
+            let victim = victim.is_some_and(|v| v.lock().contains(&key));
+            let buffered = self.store_buffer.iter().any(|(k, _)| *k == key);
+            if cached || victim || buffered || self.mshrs.pending(&key) {
+                continue;
+            }
+            inc(&self.stats.prefetches);
+            let caches = &self.caches;
+            let stats = &self.stats;
+            let install = |v: &T, lease| {
+                stats.transit(&Status::Invalid, &Status::Shared);
+                let cache = Cache::new(key.clone(), v.clone(), Status::Shared);
+                caches.insert(key.clone(), Cache { lease, ..cache });
+            };
+            let directory = self.directory.read();
+            let sent = if self.protocol == Protocol::Lease {
+                directory.lease_read(self.thread_id, key.clone(), install);
+                0
+            } else {
+                let install = |v: &T, _| install(v, 0);
+                directory.read(self.thread_id, key.clone(), install).1
+            };
+            drop(directory);
+            self.stats
+                .prefetch_messages
+                .fetch_add(sent as u64, Ordering::Relaxed);
+            self.prefetched.insert(key);
+        }
+        self.flush();
+        if self.prefetched.len() > self.config.cache_size {
+            self.prefetched.retain(|k| self.caches.contains_key(k));
+        }
     }
 
     pub fn set(&mut self, id: String, val: T) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(AccessRecord {
+                thread: self.thread_id,
+                op: AccessOp::Set,
+                key: id.clone(),
+                value: val.to_string(),
+                timestamp: recorder.now(),
+            });
+        }
+        let start = Instant::now();
+        self.op_cnt += 1;
+        if self.config.model == ConsistencyModel::SC {
+            let access = self.store(id, val);
+            return self.record(access, start);
+        }
+
+        self.maybe_drain();
+        self.store_buffer.push_back((id, val));
+        while self.store_buffer.len() > self.config.store_buffer_size {
+            self.drain_one();
+        }
+        self.record(Access::Hit, start);
+    }
+
+    pub fn collect(&self) -> (u32, u32) {
+        (self.in_cache_cnt, self.op_cnt)
+    }
+
+    /// `stats` 当前统计的快照，包括监听线程的计数
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// `attach_recorder` 之后的每一次 get/set 都记录到 recorder，多个 controller 可以共用一个
+    pub fn attach_recorder(&mut self, recorder: Arc<Recorder>) {
+        self.recorder = Some(recorder);
+    }
+
+    /// `attach_prefetcher` 使用自定义的预取器，替换 ControllerConfig::prefetch
+    pub fn attach_prefetcher(&mut self, prefetcher: Box<dyn Prefetcher>) {
+        self.prefetcher = Some(Arc::new(Mutex::new(prefetcher)));
+    }
+
+    /// `latencies` get/set 延迟直方图的快照
+    pub fn latencies(&self) -> Latencies {
+        self.latencies.snapshot()
+    }
+
+    /// `reset_stats` 清零统计和延迟直方图，不影响 collect 的结果
+    /// 之前预取的项不再计入之后的预取命中，预取命中数不会超过预取数
+    pub fn reset_stats(&mut self) {
+        self.prefetched.clear();
+        self.stats.reset();
+        self.latencies.reset();
+        self.mshrs.reset_stats();
+    }
+
+    pub fn collect_caches(&self) -> Arc<Caches<T>> {
+        self.caches.clone()
+    }
+
+    /// `collect_victims` victim buffer 中的项，按放入的顺序，没有配置 victim buffer 时为空
+    pub fn collect_victims(&self) -> Vec<Cache<T>> {
+        self.victims
+            .as_ref()
+            .map_or(Vec::new(), |v| v.lock().iter().cloned().collect())
+    }
+}
+
+impl<T: Clone + ToString + Send + Sync + From<String> + 'static> CacheController<T> {
+    /// `issue` 发出一次非阻塞的读，立即返回标识这次读的 Ticket，结果由 `poll`、`wait` 取得
+    /// 命中缓存或 store buffer 时立即完成；缺失由后台线程向目录请求，期间占用一个 MSHR，
+    /// 同一个 key 上未完成的缺失合并，MSHR 用完时等待任意一个缺失完成
+    ///
+    /// 非阻塞读之间、以及与之后的读写之间不保证顺序，读到的是发出到完成之间某一时刻的值；
+    /// 之后对同一个 key 的 get、set 等待该 key 的缺失完成。非阻塞读不记录到 recorder，也不触发预取
+    pub fn issue(&mut self, id: String) -> Ticket {
+        let start = Instant::now();
         self.op_cnt += 1;
+        self.rejoin();
+        self.maybe_drain();
+        let ticket = self.mshrs.ticket();
+
+        // store-to-load forwarding
+        let forwarded = self.store_buffer.iter().rev().find(|(k, _)| *k == id);
+        if let Some(v) = forwarded.map(|(_, v)| v.clone()) {
+            self.in_cache_cnt += 1;
+            inc(&self.stats.read_hits);
+            self.mshrs.complete(ticket, v);
+            self.record(Access::Hit, start);
+            return ticket;
+        }
+        // 已有缺失时缓存中的副本还没有装入，即使看到也是更早的值
+        if !self.mshrs.pending(&id) {
+            if let Some(v) = self.hit(&id) {
+                self.mshrs.complete(ticket, v);
+                self.record(Access::Hit, start);
+                return ticket;
+            }
+        }
+
+        match self.mshrs.allocate(&id, ticket) {
+            Allocation::Merged => {
+                inc(&self.stats.mshr_merges);
+                return ticket;
+            }
+            Allocation::Primary { stalled } => {
+                if stalled {
+                    inc(&self.stats.mshr_stalls);
+                }
+            }
+        }
+        inc(&self.stats.read_misses);
+        let directory = self.directory.clone();
+        let thread_id = self.thread_id;
+        let protocol = self.protocol;
+        let caches = self.caches.clone();
+        let stats = self.stats.clone();
+        let latencies = self.latencies.clone();
+        let mshrs = self.mshrs.clone();
+        thread::spawn(move || {
+            let directory = directory.read();
+            let v = Self::fetch(&directory, thread_id, protocol, &caches, &stats, &id);
+            drop(directory);
+            latencies.miss.record(start.elapsed());
+            mshrs.fill(&id, v);
+        });
+        ticket
+    }
+}
+
+impl<T: Clone + ToString + Sync> CacheController<T> {
+    /// `fence` 写回 store buffer 中的所有写操作，之后的读写在它们全局可见之后执行
+    pub fn fence(&mut self) {
+        while !self.store_buffer.is_empty() {
+            self.drain_one();
+        }
+    }
 
+    fn maybe_drain(&mut self) {
+        if !self.store_buffer.is_empty() && self.rng.gen_bool(self.config.drain_probability) {
+            self.drain_one();
+        }
+    }
+
+    /// `drain_one` 使 store buffer 中的一项写操作全局可见
+    /// TSO 按写入顺序；PSO 随机选择一个地址，写回该地址最早的一项
+    fn drain_one(&mut self) {
+        let idx = match self.config.model {
+            ConsistencyModel::PSO => {
+                let i = self.rng.gen_range(0..self.store_buffer.len());
+                let key = &self.store_buffer[i].0;
+                self.store_buffer
+                    .iter()
+                    .position(|(k, _)| k == key)
+                    .unwrap()
+            }
+            _ => 0,
+        };
+        if let Some((id, val)) = self.store_buffer.remove(idx) {
+            self.store(id, val);
+        }
+    }
+
+    fn record(&self, access: Access, start: Instant) {
+        let h = match access {
+            Access::Hit => &self.latencies.hit,
+            Access::Miss => &self.latencies.miss,
+            Access::Upgrade => &self.latencies.upgrade,
+        };
+        h.record(start.elapsed());
+    }
+
+    /// `store` 通过目录完成一次写操作，返回后写操作全局可见
+    fn store(&mut self, id: String, val: T) -> Access {
+        self.rejoin();
+        // 未完成的缺失装入的旧值不能覆盖这次写
+        self.mshrs.settle(&id);
+        // victim buffer 中的旧值不能在这次写之后写回
+        self.promote(&id);
+        match self.protocol {
+            Protocol::WriteThrough => return self.store_through(id, val),
+            Protocol::Lease => return self.store_lease(id, val),
+            _ => {}
+        }
         {
             // 命中缓存直接修改
             let cache = self.caches.get_mut(&id);
             if matches!(&cache, Some(c) if c.status == Status::Exclusive || c.status == Status::Modified)
             {
                 self.in_cache_cnt += 1;
+                inc(&self.stats.write_hits);
                 let mut cache = cache.unwrap();
+                self.stats.transit(&cache.status, &Status::Modified);
                 cache.status = Status::Modified;
                 cache.value = val.clone();
-                return;
+                return Access::Hit;
             }
             // 释放锁
         }
+        if self.protocol == Protocol::Update || self.adaptive {
+            return self.store_update(id, val);
+        }
+
+        let caches = &self.caches;
+        let stats = &self.stats;
+        let in_cache_cnt = &mut self.in_cache_cnt;
+        let mut access = Access::Miss;
+        let sent = self
+            .directory
+            .read()
+            .write_to_cache(self.thread_id, id.clone(), || {
+                caches
+                    .entry(id.clone())
+                    .and_modify(|v| {
+                        *in_cache_cnt += 1;
+                        access = Access::Upgrade;
+                        inc(&stats.upgrades);
+                        stats.transit(&v.status, &Status::Modified);
+                        v.status = Status::Modified;
+                        v.value = val.clone();
+                    })
+                    .or_insert_with(|| {
+                        inc(&stats.write_misses);
+                        stats.transit(&Status::Invalid, &Status::Modified);
+                        Cache::new(id.clone(), val.clone(), Status::Modified)
+                    });
+            });
+        self.stats
+            .invalidations_sent
+            .fetch_add(sent as u64, Ordering::Relaxed);
+        self.flush();
+        access
+    }
+
+    /// `store_through` 写直达：经过目录写入并使其他副本失效，只更新已有的副本，不分配缓存项
+    fn store_through(&mut self, id: String, val: T) -> Access {
+        let caches = &self.caches;
+        let stats = &self.stats;
+        let mut present = false;
+        let sent = self.directory.read().write_through(
+            self.thread_id,
+            id.clone(),
+            val.to_string(),
+            || {
+                if let Some(mut cache) = caches.get_mut(&id) {
+                    if cache.status != Status::Invalid {
+                        present = true;
+                        cache.value = val.clone();
+                    }
+                }
+            },
+        );
+        if present {
+            self.in_cache_cnt += 1;
+            inc(&stats.write_hits);
+        } else {
+            inc(&stats.write_misses);
+        }
+        stats
+            .invalidations_sent
+            .fetch_add(sent as u64, Ordering::Relaxed);
+        Access::Miss
+    }
+
+    /// `leased` 副本的租约是否仍未到期，其他协议下总是为 true
+    fn leased(&self, cache: &Cache<T>) -> bool {
+        let clock = self.clock.as_ref();
+        clock.is_none_or(|c| c.load(Ordering::SeqCst) <= cache.lease)
+    }
 
+    /// `store_lease` 租约协议：经过目录写入，不使其他副本失效，请求方取得新值的租约
+    /// 写缺失时装入缓存，副本总是 Shared
+    fn store_lease(&mut self, id: String, val: T) -> Access {
+        let present = self
+            .caches
+            .get(&id)
+            .is_some_and(|c| c.status != Status::Invalid && self.leased(&c));
+        let caches = &self.caches;
+        let stats = &self.stats;
         self.directory
             .read()
-            .write_to_cache(self.thread_id.clone(), id.clone());
+            .lease_write(self.thread_id, id.clone(), val.to_string(), |lease| {
+                let cache = Cache::new(id.clone(), val.clone(), Status::Shared);
+                let old = caches.insert(id.clone(), Cache { lease, ..cache });
+                let from = old.map_or(Status::Invalid, |c| c.status);
+                stats.transit(&from, &Status::Shared);
+            });
+        if present {
+            self.in_cache_cnt += 1;
+            inc(&stats.write_hits);
+        } else {
+            inc(&stats.write_misses);
+        }
+        self.flush();
+        Access::Miss
+    }
 
-        self.caches
-            .entry(id.clone())
-            .and_modify(|mut v| {
-                self.in_cache_cnt += 1;
-                v.status = Status::Modified;
-                v.value = val.clone();
-            })
-            .or_insert(Cache::new(id, val.clone(), Status::Modified));
+    /// `store_update` 写更新：没有 Exclusive 或 Modified 副本时经过目录把新值推送给其他共享者
+    /// 仍有其他共享者时本地副本为 Shared，否则为 Modified
+    /// 自适应协议选择时由目录决定写更新还是写失效
+    fn store_update(&mut self, id: String, val: T) -> Access {
+        let caches = &self.caches;
+        let stats = &self.stats;
+        let in_cache_cnt = &mut self.in_cache_cnt;
+        let mut access = Access::Miss;
+        let (sent, update) = self.directory.read().write_update(
+            self.thread_id,
+            id.clone(),
+            val.to_string(),
+            |others| {
+                let status = if others > 0 {
+                    Status::Shared
+                } else {
+                    Status::Modified
+                };
+                caches
+                    .entry(id.clone())
+                    .and_modify(|v| {
+                        *in_cache_cnt += 1;
+                        access = Access::Upgrade;
+                        inc(&stats.upgrades);
+                        stats.transit(&v.status, &status);
+                        v.status = status.clone();
+                        v.value = val.clone();
+                    })
+                    .or_insert_with(|| {
+                        inc(&stats.write_misses);
+                        stats.transit(&Status::Invalid, &status);
+                        Cache::new(id.clone(), val.clone(), status.clone())
+                    });
+            },
+        );
+        let counter = match update {
+            true => &self.stats.updates_sent,
+            false => &self.stats.invalidations_sent,
+        };
+        counter.fetch_add(sent as u64, Ordering::Relaxed);
+        self.flush();
+        access
     }
 
-    pub fn collect(&self) -> (u32, u32) {
-        (self.in_cache_cnt.clone(), self.op_cnt.clone())
+    /// `rejoin` 被目录隔离时，目录已把本线程的所有副本视为失效，之后的广播也不再发给本线程，
+    /// 缓存和 victim buffer 中的项都可能已经过期。丢弃所有项后解除隔离，之后的读写重新经过目录
+    /// Modified 的项不写回，其他线程可能已经写入了更新的值
+    fn rejoin(&mut self) {
+        if !self.directory.read().is_fenced(self.thread_id) {
+            return;
+        }
+        // 完成中的缺失可能在丢弃之后才装入
+        self.mshrs.settle_all();
+        self.caches.retain(|_, c| {
+            self.stats.transit(&c.status, &Status::Invalid);
+            false
+        });
+        if let Some(victims) = &self.victims {
+            for c in victims.lock().drain() {
+                self.stats.transit(&c.status, &Status::Invalid);
+            }
+        }
+        self.prefetched.clear();
+        inc(&self.stats.rejoins);
+        self.directory.read().unfence(self.thread_id);
     }
 
-    pub fn collect_caches(&self) -> Arc<DashMap<String, Cache<T>>> {
-        self.caches.clone()
+    /// `promote` 把 victim buffer 中 id 的项移回缓存，记为一次 victim 命中，返回是否移回
+    /// 租约已过期的项直接丢弃
+    fn promote(&self, id: &str) -> bool {
+        let Some(victims) = &self.victims else {
+            return false;
+        };
+        // 与监听线程相同，先取得缓存分片的锁再查找 victim buffer
+        let entry = self.caches.entry(id.to_string());
+        if matches!(&entry, Entry::Occupied(e) if e.get().status != Status::Invalid) {
+            return false;
+        }
+        let Some(cache) = victims.lock().remove(id) else {
+            return false;
+        };
+        if !self.leased(&cache) {
+            // 租约协议的副本总是 Shared，不需要写回
+            inc(&self.stats.lease_expirations);
+            self.stats.evict(&cache.status);
+            return false;
+        }
+        inc(&self.stats.victim_hits);
+        match entry {
+            Entry::Occupied(mut e) => {
+                e.insert(cache);
+            }
+            Entry::Vacant(e) => {
+                e.insert(cache);
+            }
+        }
+        true
+    }
+
+    /// `flush` 缓存项数达到 cache_size 时批量淘汰，只保留 flush_size 项
+    /// Modified 的缓存项在分片锁内写回，监听线程不会看到已淘汰但未写回的数据，其他有效项交给 LLC
+    /// 有 victim buffer 时有效项先放入 victim buffer，被挤出的项在 victim buffer 的锁内写回
+    fn flush(&self) {
+        if self.caches.len() < self.config.cache_size {
+            return;
+        }
+
+        let directory = self.directory.read();
+        let evict = |c: &Cache<T>| {
+            if c.status != Status::Invalid {
+                if self.clock.is_some() {
+                    // 租约协议的副本总是 Shared
+                    directory.evict_leased(c.id.clone(), c.value.clone(), c.lease);
+                } else {
+                    let dirty = c.status == Status::Modified;
+                    directory.evict(c.id.clone(), c.value.clone(), dirty, true);
+                }
+            }
+            self.stats.evict(&c.status);
+        };
+        let mut keep = self.config.flush_size;
+        self.caches.retain(|_, c| {
+            if keep > 0 {
+                keep -= 1;
+                return true;
+            }
+            match &self.victims {
+                Some(victims) if c.status != Status::Invalid => {
+                    let mut victims = victims.lock();
+                    if let Some(out) = victims.insert(c.clone()) {
+                        evict(&out);
+                    }
+                }
+                _ => evict(c),
+            }
+            false
+        });
     }
 }
 
 impl<T: Clone + Sync + ToString> Drop for CacheController<T> {
     fn drop(&mut self) {
-        // println!(
-        //     "线程 {:?} 总操作次数：{:?}，缓存命中次数：{:?}",
-        //     self.thread_id, self.op_cnt, self.in_cache_cnt
-        // );
+        self.fence();
+        self.mshrs.settle_all();
+    }
+}
+
+/// `DirectoryConfig` 目录配置
+#[derive(Debug, Clone)]
+pub struct DirectoryConfig {
+    /// 注入到每一条 socket 上的故障，默认不注入
+    pub faults: FaultConfig,
+    /// 等待确认消息的超时时间，超时后重传请求
+    pub retry_timeout: Duration,
+    /// 单条请求的最大重传次数，超过后认为该共享者已失效，将其隔离
+    pub max_retries: u32,
+    /// 是否记录每一条消息的收发，见 `Directory::tracer`
+    pub trace: bool,
+    /// 目录分片数，key 按哈希值分配到分片，见 `Directory::home`
+    pub slices: usize,
+    /// 私有缓存与 db 之间的共享末级缓存，容量由各分片平分，默认不使用
+    pub llc: Option<LlcConfig>,
+    pub protocol: Protocol,
+    /// 租约协议下每次读取得的租约长度，单位为逻辑时间，目录每处理一个请求前进 1
+    pub lease: u64,
+    /// 按 key 的共享模式选择迁移优化或写更新，默认不使用，写直达时不起作用
+    pub adaptive: Option<AdaptiveConfig>,
+    /// 每个缓存行的 slot 数，目录按行维护一致性，见 `LineMap`，默认每个 key 单独成行
+    pub line_size: usize,
+}
+
+impl Default for DirectoryConfig {
+    fn default() -> Self {
+        DirectoryConfig {
+            faults: FaultConfig::default(),
+            retry_timeout: Duration::from_millis(100),
+            max_retries: 100,
+            trace: false,
+            slices: 1,
+            llc: None,
+            protocol: Protocol::Invalidate,
+            lease: 64,
+            adaptive: None,
+            line_size: 1,
+        }
     }
 }
 
+/// `Endpoint` Directory 一侧的 socket，以及下一条请求的序号
+struct Endpoint {
+    socket: Box<dyn Transport<Message>>,
+    seq: u64,
+}
+
+/// `Slice` 目录的一个分片，负责哈希到它的 key
+/// 各分片有自己的目录项、与每个线程之间的 socket 和 db 分区，不同分片的广播互不阻塞
+/// 每个 socket 单独加锁，同一分片上发往不同共享者的广播也互不阻塞
+struct Slice {
+    map: DashMap<String, VecDeque<ThreadID>>,
+    sockets: RwLock<Vec<Arc<Mutex<Endpoint>>>>,
+    db: DbSession,
+    llc: Option<Mutex<Llc>>,
+    // 租约协议下每个 key 已授予的租约中最晚的到期时间
+    leases: DashMap<String, u64>,
+}
+
+/// `TransactionState` 事务当前在等待什么
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionState {
+    /// 等待目录项的锁，持有者是同一个 key 上处于 Holding 状态的事务
+    WaitingKey,
+    /// 持有目录项的锁，等待列表中的共享者确认
+    Holding(Vec<ThreadID>),
+}
+
+/// `Transaction` 目录中正在进行的一次事务
+/// 每个线程同一时刻在每个 key 上至多有一个事务，用于构建 waits-for 图
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub thread_id: ThreadID,
+    pub event: Event,
+    pub state: TransactionState,
+    pub started: Instant,
+    /// 进入当前状态的时间
+    pub since: Instant,
+    abort: Arc<AtomicBool>,
+}
+
+/// `CacheProbe` 读取某个线程缓存中每一项的状态
+pub type CacheProbe = Box<dyn Fn() -> Vec<(String, Status)> + Send + Sync>;
+
 /// `Directory` 缓存目录
 /// 使用实现了 shard 特性的 DashMap 提高系统并发度
 pub struct Directory {
-    map: DashMap<String, VecDeque<ThreadID>>,
-    sockets: Mutex<Vec<ThreadSocket<Event>>>,
-    db: DbSession,
+    slices: Vec<Slice>,
+    config: DirectoryConfig,
+    // 被隔离的共享者不再参与任何分片的广播，视为已失效
+    fenced: DashSet<ThreadID>,
+
+    // 按线程和 key 索引，一个线程有多个未完成的缺失时同时有多个事务
+    inflight: DashMap<(ThreadID, String), Transaction>,
+    probes: DashMap<ThreadID, CacheProbe>,
+    stats: DirectoryCounters,
+    // 所有 socket 上注入的故障，与 FaultyTransport 共享
+    faults: Arc<FaultCounters>,
+    latencies: DirectoryLatencyRecorder,
+    // 每次写请求发出的 RemoteWrite 数
+    fanout: AtomicHistogram,
+    tracer: Option<Arc<Tracer>>,
+    // 作为集群目录时所属的全局目录和集群编号，见 `GlobalDirectory::cluster`
+    parent: Option<(Arc<GlobalDirectory>, ClusterID)>,
+    adaptive: Option<Adaptive>,
+    // 租约协议的逻辑时间，与 CacheController 共享
+    clock: Arc<AtomicU64>,
+    lines: LineMap,
 }
 
 impl Directory {
     pub fn new(db_path: &String) -> Directory {
-        let map = DashMap::with_shard_amount(1024);
-        let sockets = Mutex::new(Vec::new());
-        let db = DbSession::new(db_path);
+        Directory::with_config(db_path, DirectoryConfig::default())
+    }
+
+    pub fn with_config(db_path: &String, config: DirectoryConfig) -> Directory {
+        Directory::with_db(DbSession::new(db_path), config)
+    }
+
+    /// `with_db` 只有一个分片时直接使用 db，否则每个分片使用 db 的一个分区
+    pub fn with_db(db: DbSession, config: DirectoryConfig) -> Directory {
+        let n = config.slices.max(1);
+        let slices = (0..n)
+            .map(|i| Slice {
+                map: DashMap::with_shard_amount(1024),
+                sockets: RwLock::new(Vec::new()),
+                db: if n == 1 { db.clone() } else { db.partition(i) },
+                llc: config.llc.as_ref().map(|c| {
+                    Mutex::new(Llc::new(LlcConfig {
+                        capacity: c.capacity.div_ceil(n),
+                        seed: c.seed + i as u64,
+                        ..c.clone()
+                    }))
+                }),
+                leases: DashMap::new(),
+            })
+            .collect();
+        let tracer = config.trace.then(|| Arc::new(Tracer::new()));
+        let lines = LineMap::new(config.line_size);
+        let adaptive = match config.protocol {
+            Protocol::WriteThrough | Protocol::Lease => None,
+            _ => config.adaptive.clone().map(Adaptive::new),
+        };
+
+        Directory {
+            slices,
+            config,
+            fenced: DashSet::new(),
+            inflight: DashMap::new(),
+            probes: DashMap::new(),
+            stats: DirectoryCounters::default(),
+            faults: Arc::new(FaultCounters::default()),
+            latencies: DirectoryLatencyRecorder::default(),
+            fanout: AtomicHistogram::default(),
+            tracer,
+            parent: None,
+            adaptive,
+            clock: Arc::new(AtomicU64::new(0)),
+            lines,
+        }
+    }
+
+    /// `register` 注册一个线程，返回线程号和每个分片到该线程的 socket，按分片编号排列
+    pub fn register(&mut self) -> (ThreadID, Vec<Box<dyn Transport<Message>>>) {
+        let id = self.slices[0].sockets.read().len() as ThreadID;
+        let n = self.slices.len();
+        let faults = &self.config.faults;
+        let mut ends = Vec::with_capacity(n);
+        for (i, slice) in self.slices.iter().enumerate() {
+            let (s1, s2) = new_socket();
+            let (s1, s2): (Box<dyn Transport<Message>>, Box<dyn Transport<Message>>) =
+                if faults.is_reliable() {
+                    (Box::new(s1), Box::new(s2))
+                } else {
+                    let stream = ((id * n + i) * 2) as u64;
+                    let faulty = |s, stream| {
+                        FaultyTransport::new(s, faults.clone(), stream)
+                            .with_counters(self.faults.clone())
+                    };
+                    (
+                        Box::new(faulty(s1, stream)),
+                        Box::new(faulty(s2, stream + 1)),
+                    )
+                };
+            let endpoint = Endpoint { socket: s1, seq: 0 };
+            slice.sockets.write().push(Arc::new(Mutex::new(endpoint)));
+            ends.push(s2);
+        }
+
+        (id, ends)
+    }
+
+    /// `cluster` 作为集群目录时的集群编号
+    pub fn cluster(&self) -> Option<ClusterID> {
+        self.parent.as_ref().map(|p| p.1)
+    }
+
+    /// `slices` 目录分片数
+    pub fn slices(&self) -> usize {
+        self.slices.len()
+    }
+
+    /// `lines` key 与缓存行之间的映射
+    pub fn lines(&self) -> LineMap {
+        self.lines
+    }
+
+    /// `home` 负责 key 的分片，同一行的 key 在同一个分片
+    pub fn home(&self, key: &str) -> usize {
+        if self.slices.len() == 1 {
+            return 0;
+        }
+        let mut hasher = DefaultHasher::new();
+        self.lines.line(key).hash(&mut hasher);
+        (hasher.finish() % self.slices.len() as u64) as usize
+    }
+
+    fn slice(&self, key: &str) -> &Slice {
+        &self.slices[self.home(key)]
+    }
 
-        Directory { map, sockets, db }
+    /// `attach_probe` 注册读取线程缓存状态的回调，用于诊断输出
+    pub fn attach_probe(&self, thread_id: ThreadID, probe: CacheProbe) {
+        self.probes.insert(thread_id, probe);
     }
 
-    pub fn register(&mut self) -> (ThreadID, ThreadSocket<Event>) {
-        let (s1, s2) = new_socket();
-        let mut sockets = self.sockets.lock();
-        sockets.push(s1);
+    /// `transactions` 当前正在进行的事务
+    pub fn transactions(&self) -> Vec<Transaction> {
+        self.inflight.iter().map(|t| t.value().clone()).collect()
+    }
 
-        let id = (sockets.len() - 1) as ThreadID;
+    /// `abort` 让 thread_id 的事务放弃等待当前未确认的共享者，并将其隔离
+    pub fn abort(&self, thread_id: ThreadID) -> bool {
+        let mut aborted = false;
+        for t in self.inflight.iter().filter(|t| t.thread_id == thread_id) {
+            t.abort.store(true, Ordering::SeqCst);
+            aborted = true;
+        }
+        aborted
+    }
 
-        (id, s2)
+    /// `fenced` 因超时或死锁被隔离的线程
+    pub fn fenced(&self) -> Vec<ThreadID> {
+        let mut fenced: Vec<ThreadID> = self.fenced.iter().map(|t| *t).collect();
+        fenced.sort();
+        fenced
     }
 
+    /// `is_fenced` thread_id 是否被隔离，被隔离的线程的副本都已被目录视为失效
+    pub fn is_fenced(&self, thread_id: ThreadID) -> bool {
+        self.fenced.contains(&thread_id)
+    }
+
+    /// `unfence` 解除 thread_id 的隔离，之后它重新参与广播
+    /// 调用方需要先丢弃该线程的所有副本，见 `CacheController::rejoin`
+    pub fn unfence(&self, thread_id: ThreadID) -> bool {
+        self.fenced.remove(&thread_id).is_some()
+    }
+
+    /// `timeouts` 等待确认超时的消息数
+    pub fn timeouts(&self) -> u64 {
+        self.stats.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// `fault_count` 所有 socket 上已注入的故障，包括监听线程发出的回复
+    pub fn fault_count(&self) -> FaultCount {
+        self.faults.snapshot()
+    }
+
+    /// `stats` 按消息类型统计的快照
+    pub fn stats(&self) -> DirectoryStats {
+        self.stats.snapshot()
+    }
+
+    /// `latencies` 广播延迟直方图的快照
+    pub fn latencies(&self) -> DirectoryLatencies {
+        self.latencies.snapshot()
+    }
+
+    /// `tracer` DirectoryConfig::trace 为 true 时返回记录消息收发的 Tracer
+    pub fn tracer(&self) -> Option<Arc<Tracer>> {
+        self.tracer.clone()
+    }
+
+    /// `fanout` 每次写请求使多少个其他线程的副本失效
+    pub fn fanout(&self) -> Histogram {
+        self.fanout.snapshot()
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+        self.faults.reset();
+        self.latencies.reset();
+        self.fanout.reset();
+        for llc in self.slices.iter().filter_map(|s| s.llc.as_ref()) {
+            llc.lock().reset_stats();
+        }
+        if let Some(adaptive) = &self.adaptive {
+            adaptive.reset_stats();
+        }
+    }
+
+    /// `sharers` 目录中每个 key 的共享者
+    /// 不等待被占用的分片，返回的第二项为跳过的分片数
+    pub fn sharers(&self) -> (Vec<(String, Vec<ThreadID>)>, usize) {
+        let mut sharers = Vec::new();
+        let mut locked = 0;
+        for shard in self.slices.iter().flat_map(|s| s.map.shards()) {
+            match shard.try_read() {
+                None => locked += 1,
+                Some(shard) => {
+                    for (k, v) in shard.iter() {
+                        sharers.push((k.clone(), v.get().iter().cloned().collect()));
+                    }
+                }
+            }
+        }
+        (sharers, locked)
+    }
+
+    /// `caches` 通过 probe 读取每个线程的缓存状态
+    pub fn caches(&self) -> Vec<(ThreadID, Vec<(String, Status)>)> {
+        let mut caches: Vec<_> = self.probes.iter().map(|p| (*p.key(), p())).collect();
+        caches.sort_by_key(|c| c.0);
+        caches
+    }
+
+    fn begin(&self, thread_id: ThreadID, event: Event) -> Arc<AtomicBool> {
+        let now = Instant::now();
+        let abort = Arc::new(AtomicBool::new(false));
+        self.inflight.insert(
+            (thread_id, event.get_id().clone()),
+            Transaction {
+                thread_id,
+                event,
+                state: TransactionState::WaitingKey,
+                started: now,
+                since: now,
+                abort: abort.clone(),
+            },
+        );
+        abort
+    }
+
+    fn transit(&self, thread_id: ThreadID, key: &str, state: TransactionState) {
+        if let Some(mut t) = self.inflight.get_mut(&(thread_id, key.to_string())) {
+            t.state = state;
+            t.since = Instant::now();
+        }
+    }
+
+    fn end(&self, thread_id: ThreadID, key: &str) {
+        if let Some((_, t)) = self.inflight.remove(&(thread_id, key.to_string())) {
+            self.finish(thread_id, t.event, t.started);
+        }
+    }
+
+    /// `finish` 把一次结束的事务记录到 tracer
+    fn finish(&self, thread_id: ThreadID, event: Event, started: Instant) {
+        if let Some(tracer) = &self.tracer {
+            tracer.record(TraceRecord {
+                ts: tracer.offset(started),
+                kind: TraceKind::Transaction(started.elapsed()),
+                side: Side::Directory,
+                thread_id,
+                requester: Some(thread_id),
+                key: event.get_id().clone(),
+                event,
+                seq: 0,
+                before: None,
+                after: None,
+            });
+        }
+    }
+
+    /// `broadcast` 向 ids 中除 thread_id 以外的共享者发送 event 并等待确认
+    /// 返回已失效的共享者，以及发出的消息数
     fn broadcast(
         &self,
+        slice: &Slice,
         thread_id: ThreadID,
         event: Event,
         ids: &VecDeque<ThreadID>,
-    ) -> Option<Vec<ThreadID>> {
-        if ids.len() == 0 {
-            return None;
+        abort: &AtomicBool,
+    ) -> (Option<Vec<ThreadID>>, usize) {
+        if ids.is_empty() {
+            return (None, 0);
         }
 
-        // println!("directory will broadcast event: {:?} from {:?}", event, thread_id);
-
-        let sockets = self.sockets.lock();
+        let start = Instant::now();
+        let mut invalid_ids = Vec::new();
+        let mut targets = Vec::with_capacity(ids.len());
         for i in ids {
             if *i == thread_id {
                 continue;
             }
-            sockets[*i].send(event.clone());
-        }
-        let mut invalid_ids = Vec::new();
-        for i in ids {
-            if *i == thread_id {
+            if self.fenced.contains(i) {
+                invalid_ids.push(*i);
                 continue;
             }
-            if matches!(sockets[*i].receive(), Event::Confirmed(b) if b) {
-                invalid_ids.push(i.clone());
+            targets.push(*i);
+        }
+        // 只在取出 socket 时持有分片的锁；确认到达前持有各共享者的 socket，
+        // 按线程号的顺序加锁，同时广播的事务之间不会死锁
+        targets.sort_unstable();
+        targets.dedup();
+        let endpoints: Vec<_> = {
+            let sockets = slice.sockets.read();
+            targets.iter().map(|i| sockets[*i].clone()).collect()
+        };
+        let mut messages = Vec::with_capacity(targets.len());
+        for (i, endpoint) in targets.iter().zip(&endpoints) {
+            let mut endpoint = endpoint.lock();
+            endpoint.seq += 1;
+            let message = Message {
+                seq: endpoint.seq,
+                event: event.clone(),
+            };
+            self.trace(TraceKind::Send, *i, thread_id, event.get_id(), &message);
+            endpoint.socket.send(message.clone());
+            inc(match event {
+                Event::RemoteRead(_) => &self.stats.remote_reads,
+                Event::Update(_, _) => &self.stats.updates,
+                _ => &self.stats.remote_writes,
+            });
+            messages.push((*i, endpoint, message));
+        }
+
+        let sent = messages.len();
+        let mut waiting: Vec<ThreadID> = messages.iter().map(|m| m.0).collect();
+        self.transit(
+            thread_id,
+            event.get_id(),
+            TransactionState::Holding(waiting.clone()),
+        );
+        for (i, endpoint, message) in messages {
+            let confirmed = self.wait_confirmed(&endpoint, i, thread_id, &message, abort);
+            drop(endpoint);
+            match confirmed {
+                Some(true) => invalid_ids.push(i),
+                Some(false) => {}
+                None => {
+                    // 超时或被 watchdog 中止，隔离该共享者，视为已失效
+                    self.fenced.insert(i);
+                    invalid_ids.push(i);
+                }
             }
+            waiting.retain(|t| *t != i);
+            self.transit(
+                thread_id,
+                event.get_id(),
+                TransactionState::Holding(waiting.clone()),
+            );
         }
-        if invalid_ids.len() == 0 {
-            return None;
+        if sent > 0 {
+            match event {
+                Event::RemoteRead(_) => self.latencies.remote_read.record(start.elapsed()),
+                Event::Update(_, _) => self.latencies.remote_update.record(start.elapsed()),
+                _ => self.latencies.remote_invalidate.record(start.elapsed()),
+            }
+        }
+        if invalid_ids.is_empty() {
+            return (None, sent);
         };
-        Some(invalid_ids)
-    }
-
-    // 从 db 读取数据
-    fn read<T: Clone + Sync + From<String>>(&self, thread_id: ThreadID, id: String) -> (T, usize) {
-        let mut v = self.map.entry(id.clone()).or_insert(VecDeque::new());
-        if v.len() > 0 {
-            let ids = self.broadcast(thread_id, Event::RemoteRead(id.clone()), v.value());
-            match ids {
-                None => {}
-                Some(ids) => {
-                    let v = v.value_mut();
-                    let mut idx = v.len();
-                    for id in ids {
-                        for i in 0..v.len() {
-                            if v[i] == id {
-                                idx = i;
-                                break;
-                            }
-                        }
-                        if idx < v.len() {
-                            v.remove(idx);
-                        }
+        (Some(invalid_ids), sent)
+    }
+
+    /// `wait_confirmed` 等待 message 对应的确认消息，超时则重传
+    /// 序号不匹配的回复是过期或重复的，直接丢弃
+    /// 超过最大重传次数或被中止时返回 None
+    fn wait_confirmed(
+        &self,
+        endpoint: &Endpoint,
+        target: ThreadID,
+        requester: ThreadID,
+        message: &Message,
+        abort: &AtomicBool,
+    ) -> Option<bool> {
+        let key = message.event.get_id();
+        let mut retries = 0;
+        loop {
+            match endpoint.socket.receive_timeout(self.config.retry_timeout) {
+                Some(reply) if reply.seq == message.seq => {
+                    if let Event::Confirmed(b) = reply.event {
+                        inc(&self.stats.confirmations);
+                        self.trace(TraceKind::Receive, target, requester, key, &reply);
+                        return Some(b);
+                    }
+                    inc(&self.stats.discarded);
+                }
+                Some(reply) => {
+                    // 属于更早的请求，不知道对应的 key
+                    inc(&self.stats.discarded);
+                    self.trace(TraceKind::Receive, target, requester, "", &reply);
+                }
+                None => {
+                    retries += 1;
+                    if retries > self.config.max_retries {
+                        inc(&self.stats.timeouts);
+                        return None;
                     }
+                    inc(&self.stats.retransmissions);
+                    self.trace(TraceKind::Send, target, requester, key, message);
+                    endpoint.socket.send(message.clone());
                 }
             }
+            if abort.swap(false, Ordering::SeqCst) {
+                return None;
+            }
+        }
+    }
+
+    fn trace(
+        &self,
+        kind: TraceKind,
+        target: ThreadID,
+        requester: ThreadID,
+        key: &str,
+        message: &Message,
+    ) {
+        if let Some(tracer) = &self.tracer {
+            tracer.message(kind, Side::Directory, target, Some(requester), key, message);
+        }
+    }
+
+    // 从 db 读取数据，install 在目录项加锁期间把数据和其他共享者数量交给请求方装入缓存
+    // 同时返回发出的 RemoteRead 或 RemoteWrite 数
+    fn read<T: Clone + Sync + From<String>>(
+        &self,
+        thread_id: ThreadID,
+        id: String,
+        install: impl FnOnce(&T, usize),
+    ) -> (T, usize) {
+        inc(&self.stats.read_requests);
+        let slice = self.slice(&id);
+        let abort = self.begin(thread_id, Event::RemoteRead(id.clone()));
+        // 集群目录先取得集群级的读权限，其他集群也有副本时不能装入 Exclusive
+        let global = self.parent.as_ref().map(|(g, c)| g.acquire(*c, &id, false));
+        let remote = global.as_ref().map_or(0, |g| g.1 as usize);
+        let mut v = slice.map.entry(self.lines.line(&id)).or_default();
+        self.transit(thread_id, &id, TransactionState::Holding(Vec::new()));
+        // 请求方已持有该行的其他 slot 时，其他线程不可能持有 Exclusive 或 Modified 的副本
+        let held = self.lines.size() > 1 && v.contains(&thread_id);
+        let owner = self.before_access(&id, &v, thread_id);
+        // 迁移的 key 使其他副本失效，请求方装入 Exclusive，随后的写不需要 upgrade
+        let migratory = remote == 0
+            && v.iter().any(|t| *t != thread_id)
+            && self.pattern(&id) == SharingPattern::Migratory;
+        let mut sent = 0;
+        if migratory {
+            let event = Event::RemoteWrite(id.clone());
+            sent = self.broadcast(slice, thread_id, event, v.value(), &abort).1;
+            v.value_mut().clear();
+            self.adaptive.as_ref().unwrap().migratory_grant();
+        } else if !v.is_empty() && !held {
+            let event = Event::RemoteRead(id.clone());
+            let (ids, n) = self.broadcast(slice, thread_id, event, v.value(), &abort);
+            sent = n;
+            if let Some(ids) = ids {
+                v.value_mut().retain(|t| !ids.contains(t));
+            }
         };
+        self.after_access(&id, owner, thread_id, false);
+
+        if !(held && v.contains(&thread_id)) {
+            v.value_mut().push_back(thread_id);
+        }
+        let (val, fx) = match &slice.llc {
+            None => {
+                inc(&self.stats.db_reads);
+                (slice.db.get(id.clone()), Effects::default())
+            }
+            Some(llc) => llc.lock().read(&id, &slice.db),
+        };
+        let val: T = val.into();
+        install(&val, v.len() - 1 + remote);
+        self.end(thread_id, &id);
+        drop(v);
+        drop(global);
+        self.apply(slice, fx);
+        (val, sent)
+    }
+
+    /// `pattern` key 当前的共享模式，没有配置自适应协议选择时为 Other
+    pub fn pattern(&self, key: &str) -> SharingPattern {
+        self.adaptive
+            .as_ref()
+            .map_or(SharingPattern::Other, |a| a.pattern(key))
+    }
+
+    /// `patterns` 每个 key 的共享模式和切换次数，没有配置自适应协议选择时为空
+    pub fn patterns(&self) -> Vec<KeyPattern> {
+        self.adaptive.as_ref().map_or(Vec::new(), |a| a.patterns())
+    }
+
+    /// `adaptive_stats` 自适应协议选择的统计，没有配置时返回 None
+    pub fn adaptive_stats(&self) -> Option<AdaptiveStats> {
+        self.adaptive.as_ref().map(|a| a.stats())
+    }
+
+    /// `adaptive` 写操作是否由目录按 key 选择写失效或写更新
+    pub fn adaptive(&self) -> bool {
+        self.adaptive.is_some()
+    }
+
+    /// `before_access` 广播之前清除写回标记，返回唯一的其他共享者
+    /// 只有它可能持有 Modified 的副本
+    fn before_access(
+        &self,
+        id: &str,
+        v: &VecDeque<ThreadID>,
+        thread_id: ThreadID,
+    ) -> Option<ThreadID> {
+        let adaptive = self.adaptive.as_ref()?;
+        adaptive.take_written(id);
+        match v.iter().filter(|t| **t != thread_id).collect::<Vec<_>>()[..] {
+            [owner] => Some(*owner),
+            _ => None,
+        }
+    }
+
+    /// `after_access` 广播使 owner 的副本写回时补记它静默的写，再记录本次访问
+    fn after_access(&self, id: &str, owner: Option<ThreadID>, thread_id: ThreadID, write: bool) {
+        if let Some(adaptive) = &self.adaptive {
+            if let (true, Some(owner)) = (adaptive.take_written(id), owner) {
+                adaptive.infer_write(id, owner);
+            }
+            adaptive.observe(id, thread_id, write);
+        }
+    }
+
+    /// `apply` 统计 LLC 操作访问 db 的次数，并回收 LLC 淘汰的项
+    /// 回收需要目录项的锁，调用方不能持有任何目录项
+    fn apply(&self, slice: &Slice, fx: Effects) {
+        self.stats
+            .db_reads
+            .fetch_add(fx.db_reads, Ordering::Relaxed);
+        self.stats
+            .db_writes
+            .fetch_add(fx.db_writes, Ordering::Relaxed);
+        for key in fx.back_invalidate {
+            let sent = self.remote(Event::RemoteWrite(key));
+            if let Some(llc) = &slice.llc {
+                llc.lock().back_invalidated(sent);
+            }
+        }
+    }
+
+    /// `llc_stats` 各分片 LLC 统计之和，没有配置 LLC 时返回 None
+    pub fn llc_stats(&self) -> Option<LlcStats> {
+        self.config.llc.as_ref()?;
+        let mut stats = LlcStats::default();
+        for slice in &self.slices {
+            if let Some(llc) = &slice.llc {
+                stats.merge(&llc.lock().stats());
+            }
+        }
+        Some(stats)
+    }
+
+    /// `llc_contains` key 是否在 LLC 中，没有配置 LLC 时返回 false
+    pub fn llc_contains(&self, key: &str) -> bool {
+        let llc = &self.slice(key).llc;
+        llc.as_ref().is_some_and(|l| l.lock().contains(key))
+    }
 
-        v.value_mut().push_back(thread_id.clone());
-        (self.db.get(id).into(), v.len() - 1)
+    /// `remote` 全局目录或 LLC 要求本目录的所有副本失效或降级，向共享者广播 event
+    /// 返回发出的消息数
+    fn remote(&self, event: Event) -> usize {
+        let id = event.get_id().clone();
+        let slice = self.slice(&id);
+        let mut v = slice.map.entry(self.lines.line(&id)).or_default();
+        if v.is_empty() {
+            return 0;
+        }
+        // 请求方不在本集群，没有需要跳过的线程
+        let abort = AtomicBool::new(false);
+        let write = matches!(event, Event::RemoteWrite(_));
+        let (ids, sent) = self.broadcast(slice, ThreadID::MAX, event, v.value(), &abort);
+        if write {
+            v.value_mut().clear();
+        } else if let Some(ids) = ids {
+            v.value_mut().retain(|t| !ids.contains(t));
+        }
+        sent
+    }
+
+    /// `recall` 全局目录使本集群的副本失效或降级，私有缓存写回之后 LLC 中的脏数据也写回 db，
+    /// 失效时 LLC 中的数据一并丢弃，之后其他集群从 db 读到最新的值
+    fn recall(&self, event: Event) {
+        let id = event.get_id().clone();
+        let write = matches!(event, Event::RemoteWrite(_));
+        self.remote(event);
+        let slice = self.slice(&id);
+        if let Some(llc) = &slice.llc {
+            let fx = llc.lock().recall(&id, write, &slice.db);
+            self.apply(slice, fx);
+        }
     }
 
-    // 将数据写回 db，
+    // 将数据写回 LLC 或 db，
     fn write_back<T: Clone + Sync + ToString>(&self, id: String, val: T) {
-        self.db.set(id, val.to_string());
+        self.evict(id, val, true, false);
     }
 
-    // 维护目录，并广播msg
-    fn write_to_cache(&self, thread_id: ThreadID, id: String) {
+    /// `evict` 私有缓存写回或淘汰一项，evicted 为 true 时该项已从私有缓存中淘汰
+    /// 干净的项只有 Exclusive 的 LLC 需要知道
+    fn evict<T: Clone + Sync + ToString>(&self, id: String, val: T, dirty: bool, evicted: bool) {
+        let slice = self.slice(&id);
+        if let (Some(adaptive), true) = (&self.adaptive, dirty) {
+            adaptive.written(&id);
+        }
+        let fx = match (&slice.llc, dirty) {
+            (None, false) => return,
+            (None, true) => {
+                inc(&self.stats.db_writes);
+                slice.db.set(id, val.to_string());
+                return;
+            }
+            (Some(llc), true) => llc
+                .lock()
+                .write_back(&id, val.to_string(), evicted, &slice.db),
+            (Some(llc), false) => llc.lock().evict_clean(&id, val.to_string(), &slice.db),
+        };
+        // 只有 Inclusive 的读和写会淘汰出需要回收的项
+        debug_assert!(fx.back_invalidate.is_empty());
+        self.apply(slice, fx);
+    }
+
+    /// `evict_leased` 租约协议下私有缓存淘汰干净的副本，租约已过期的副本可能是旧值，不交给 LLC
+    /// 在目录项加锁期间检查租约，与 `lease_write` 互斥
+    fn evict_leased<T: Clone + Sync + ToString>(&self, id: String, val: T, lease: u64) {
+        let slice = self.slice(&id);
+        let _v = slice.map.entry(self.lines.line(&id)).or_default();
+        if self.now() <= lease {
+            self.evict(id, val, false, true);
+        }
+    }
+
+    /// `protocol` 所有线程使用的写协议
+    pub fn protocol(&self) -> Protocol {
+        self.config.protocol
+    }
+
+    /// `write_through` 写直达：使请求方以外的副本失效，并在目录项加锁期间写入 LLC 或 db
+    /// install 在目录项加锁期间更新请求方已有的副本，返回发出的 RemoteWrite 数
+    fn write_through(
+        &self,
+        thread_id: ThreadID,
+        id: String,
+        val: String,
+        install: impl FnOnce(),
+    ) -> usize {
+        inc(&self.stats.write_requests);
+        let mut sent = 0;
+        let slice = self.slice(&id);
+        let abort = self.begin(thread_id, Event::RemoteWrite(id.clone()));
+        let _global = self.parent.as_ref().map(|(g, c)| g.acquire(*c, &id, true));
+        let mut v = slice.map.entry(self.lines.line(&id)).or_default();
+        self.transit(thread_id, &id, TransactionState::Holding(Vec::new()));
+        if !v.value().is_empty() {
+            let event = Event::RemoteWrite(id.clone());
+            sent = self.broadcast(slice, thread_id, event, v.value(), &abort).1;
+            v.value_mut().retain(|t| *t == thread_id);
+        };
+        install();
+        self.evict(id.clone(), val, true, false);
+        self.end(thread_id, &id);
+        self.fanout.record_value(sent as u64);
+        sent
+    }
+
+    /// `now` 租约协议的逻辑时间
+    pub fn now(&self) -> u64 {
+        self.clock.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn clock(&self) -> Arc<AtomicU64> {
+        self.clock.clone()
+    }
+
+    /// `tick` 逻辑时间前进 1，返回新的时间
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// `lease_read` 租约协议的读：不记录共享者，授予请求方到 now + lease 为止的租约
+    /// install 在目录项加锁期间装入请求方的副本，参数为租约到期的逻辑时间
+    ///
+    /// 租约协议的事务不等待其他线程，只短暂持有一个目录项，不会参与死锁，
+    /// 因此不登记到 watchdog 检查的事务中，只在结束时记录到 tracer
+    fn lease_read<T: Clone + Sync + From<String>>(
+        &self,
+        thread_id: ThreadID,
+        id: String,
+        install: impl FnOnce(&T, u64),
+    ) -> T {
+        inc(&self.stats.read_requests);
+        let slice = self.slice(&id);
+        let started = Instant::now();
+        // 目录项只用作 key 的锁，写操作持有它时读等待新值写入
+        let v = slice.map.entry(self.lines.line(&id)).or_default();
+        let lease = self.tick() + self.config.lease;
+        let mut rts = slice.leases.entry(self.lines.line(&id)).or_default();
+        *rts = lease.max(*rts);
+        drop(rts);
+        let (val, fx) = match &slice.llc {
+            None => {
+                inc(&self.stats.db_reads);
+                (slice.db.get(id.clone()), Effects::default())
+            }
+            Some(llc) => llc.lock().read(&id, &slice.db),
+        };
+        let val: T = val.into();
+        install(&val, lease);
+        self.finish(thread_id, Event::RemoteRead(id), started);
+        drop(v);
+        self.apply(slice, fx);
+        val
+    }
+
+    /// `lease_write` 租约协议的写：不发送失效消息，逻辑时间跳到该 key 最晚的租约之后，
+    /// 其他线程的副本随之过期。在目录项加锁期间写入 LLC 或 db，
+    /// install 装入请求方的副本，参数为请求方对新值的租约
+    /// 与 `lease_read` 相同，不登记到 watchdog 检查的事务中
+    fn lease_write(&self, thread_id: ThreadID, id: String, val: String, install: impl FnOnce(u64)) {
+        inc(&self.stats.write_requests);
+        let slice = self.slice(&id);
+        let started = Instant::now();
+        let v = slice.map.entry(self.lines.line(&id)).or_default();
+        let mut rts = slice.leases.entry(self.lines.line(&id)).or_default();
+        let mut wts = self.tick();
+        if *rts >= wts {
+            inc(&self.stats.lease_jumps);
+            wts = self
+                .clock
+                .fetch_max(*rts + 1, Ordering::SeqCst)
+                .max(*rts + 1);
+        }
+        *rts = wts + self.config.lease;
+        let lease = *rts;
+        drop(rts);
+        install(lease);
+        self.evict(id.clone(), val, true, false);
+        self.finish(thread_id, Event::RemoteWrite(id), started);
+        drop(v);
+    }
+
+    /// `write_update` 写更新：把新值推送给请求方以外的共享者，仍有共享者时写入 LLC 或 db
+    /// 使用自适应协议选择时，只有生产者-消费者模式的 key 写更新，其他 key 写失效
+    /// install 在目录项加锁期间装入请求方的副本，参数为其他仍持有副本的共享者数
+    /// 返回发出的消息数，以及是否为写更新
+    fn write_update(
+        &self,
+        thread_id: ThreadID,
+        id: String,
+        val: String,
+        install: impl FnOnce(usize),
+    ) -> (usize, bool) {
+        inc(&self.stats.write_requests);
+        let mut sent = 0;
+        let slice = self.slice(&id);
+        let update = self.config.protocol == Protocol::Update
+            || self.pattern(&id) == SharingPattern::ProducerConsumer;
+        let event = match update {
+            true => Event::Update(id.clone(), val.clone()),
+            false => Event::RemoteWrite(id.clone()),
+        };
+        let abort = self.begin(thread_id, event.clone());
+        // 集群之间仍然是写失效
+        let _global = self.parent.as_ref().map(|(g, c)| g.acquire(*c, &id, true));
+        let mut v = slice.map.entry(self.lines.line(&id)).or_default();
+        self.transit(thread_id, &id, TransactionState::Holding(Vec::new()));
+        let owner = self.before_access(&id, &v, thread_id);
+        if !v.value().is_empty() {
+            let (ids, n) = self.broadcast(slice, thread_id, event, v.value(), &abort);
+            sent = n;
+            match (update, ids) {
+                (false, _) => v.value_mut().clear(),
+                (true, Some(ids)) => v.value_mut().retain(|t| !ids.contains(t)),
+                (true, None) => {}
+            }
+        };
+        self.after_access(&id, owner, thread_id, true);
+        if !v.contains(&thread_id) {
+            v.value_mut().push_back(thread_id);
+        }
+        if let (Some(adaptive), true) = (&self.adaptive, update) {
+            adaptive.update_write();
+            // 仍持有副本的共享者读到了新值
+            for t in v.iter().filter(|t| **t != thread_id) {
+                adaptive.observe(&id, *t, false);
+            }
+        }
+        if !update {
+            self.fanout.record_value(sent as u64);
+        }
+        let others = v.len() - 1;
+        install(others);
+        // 没有其他共享者时请求方持有 Modified 的副本，之后再写回
+        let fx = if others > 0 {
+            self.evict(id.clone(), val, true, false);
+            None
+        } else {
+            slice.llc.as_ref().map(|l| l.lock().write(&id, &slice.db))
+        };
+        self.end(thread_id, &id);
+        drop(v);
+        drop(_global);
+        if let Some(fx) = fx {
+            self.apply(slice, fx);
+        }
+        (sent, update)
+    }
+
+    // 维护目录，并广播msg，install 在目录项加锁期间修改请求方的缓存
+    // 返回发出的 RemoteWrite 数
+    fn write_to_cache(&self, thread_id: ThreadID, id: String, install: impl FnOnce()) -> usize {
         // 更新目录
-        let mut v = self.map.entry(id.clone()).or_insert(VecDeque::new());
-        if v.value().len() > 0 {
-            self.broadcast(thread_id, Event::RemoteWrite(id.clone()), v.value());
+        inc(&self.stats.write_requests);
+        let mut sent = 0;
+        let slice = self.slice(&id);
+        let abort = self.begin(thread_id, Event::RemoteWrite(id.clone()));
+        let _global = self.parent.as_ref().map(|(g, c)| g.acquire(*c, &id, true));
+        let mut v = slice.map.entry(self.lines.line(&id)).or_default();
+        self.transit(thread_id, &id, TransactionState::Holding(Vec::new()));
+        if !v.value().is_empty() {
+            let event = Event::RemoteWrite(id.clone());
+            sent = self.broadcast(slice, thread_id, event, v.value(), &abort).1;
             v.value_mut().clear();
         };
-        v.value_mut().push_back(thread_id.clone());
+        v.value_mut().push_back(thread_id);
+        install();
+        let fx = slice.llc.as_ref().map(|l| l.lock().write(&id, &slice.db));
+        self.end(thread_id, &id);
+        drop(v);
+        drop(_global);
+        if let Some(fx) = fx {
+            self.apply(slice, fx);
+        }
+        self.fanout.record_value(sent as u64);
+        sent
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Cache<T: Clone + ToString + Sync> {
     pub id: String,
     pub value: T,
     pub status: Status,
+    /// 租约协议下副本有效的最后一个逻辑时间，其他协议不使用
+    pub lease: u64,
 }
 
 impl<T: Clone + ToString + Sync> Cache<T> {
     pub fn new(id: String, value: T, status: Status) -> Cache<T> {
-        Cache { id, value, status }
+        Cache {
+            id,
+            value,
+            status,
+            lease: 0,
+        }
     }
 
     pub fn is(&self, id: &String) -> bool {
@@ -335,7 +2091,7 @@ impl<T: Clone + ToString + Sync> Cache<T> {
     }
 
     pub fn get(&self) -> T {
-        return self.value.clone();
+        self.value.clone()
     }
 
     pub fn set(&mut self, val: T) {
@@ -356,6 +2112,10 @@ impl<T: Clone + ToString + Sync> Cache<T> {
                 self.status = Status::Invalid;
                 true
             }
+            Event::Update(_, _) => {
+                self.status = Status::Shared;
+                false
+            }
             Event::Confirmed(_) => panic!("Cache can not handle confirmed event"),
         }
     }
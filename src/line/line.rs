@@ -0,0 +1,112 @@
+//! 多个相邻 key 组成的缓存行
+//!
+//! 以十进制数字结尾的 key 按数字分组，相邻的 size 个 key 属于同一行，
+//! 例如 size 为 4 时 `user4` 到 `user7` 属于行 `user#1`。
+//! 数字有前导零或不以数字结尾的 key 单独成行，行名就是 key 本身；
+//! 数字接近 u64::MAX、所在的行超出 u64 范围的 key 也单独成行。
+//! 目录按行记录共享者，写任意一个 slot 都会使其他线程持有的整行失效。
+use std::ops::RangeInclusive;
+
+/// `LineMap` key 与缓存行之间的映射，size 为 1 时每个 key 单独成行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineMap {
+    size: usize,
+}
+
+/// `split` 把 key 拆成前缀和没有前导零的数字后缀，不以这样的数字结尾时返回 None
+pub fn split(key: &str) -> Option<(&str, u64)> {
+    let digits = key.len() - key.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (prefix, n) = key.split_at(key.len() - digits);
+    if n.is_empty() || (n.len() > 1 && n.starts_with('0')) {
+        return None;
+    }
+    n.parse().ok().map(|n| (prefix, n))
+}
+
+impl LineMap {
+    pub fn new(size: usize) -> LineMap {
+        LineMap { size: size.max(1) }
+    }
+
+    /// `size` 每行的 slot 数
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// `line` key 所在的行
+    pub fn line(&self, key: &str) -> String {
+        if self.size == 1 {
+            return key.to_string();
+        }
+        match split(key) {
+            Some((prefix, n)) if self.range(n / self.size as u64).is_some() => {
+                format!("{}#{}", prefix, n / self.size as u64)
+            }
+            _ => key.to_string(),
+        }
+    }
+
+    /// `slots` 行中所有 slot 的 key，按数字排列
+    pub fn slots(&self, line: &str) -> Vec<String> {
+        if self.size == 1 {
+            return vec![line.to_string()];
+        }
+        let grouped = line
+            .rsplit_once('#')
+            .and_then(|(prefix, n)| split(n).filter(|s| s.0.is_empty()).map(|s| (prefix, s.1)))
+            .and_then(|(prefix, n)| Some((prefix, self.range(n)?)));
+        match grouped {
+            Some((prefix, range)) => range.map(|i| format!("{}{}", prefix, i)).collect(),
+            None => vec![line.to_string()],
+        }
+    }
+
+    /// `range` 第 n 行中 slot 的数字，超出 u64 范围时为 None
+    fn range(&self, n: u64) -> Option<RangeInclusive<u64>> {
+        let size = self.size as u64;
+        let start = n.checked_mul(size)?;
+        Some(start..=start.checked_add(size - 1)?)
+    }
+}
+
+impl Default for LineMap {
+    fn default() -> Self {
+        LineMap::new(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line() {
+        let lines = LineMap::new(4);
+        assert_eq!(lines.line("user5"), "user#1");
+        assert_eq!(lines.line("17"), "#4");
+        assert_eq!(lines.line("a#3"), "a##0");
+        assert_eq!(lines.slots("user#1"), ["user4", "user5", "user6", "user7"]);
+        assert_eq!(lines.slots("a##0"), ["a#0", "a#1", "a#2", "a#3"]);
+
+        // 前导零和不以数字结尾的 key 单独成行
+        for key in ["user05", "x", "c#", "b#x", "007"] {
+            assert_eq!(lines.line(key), key);
+            assert_eq!(lines.slots(key), [key]);
+        }
+        assert_eq!(lines.line("user0"), "user#0");
+
+        // 最后一行恰好到 u64::MAX；行超出 u64 范围时 key 单独成行
+        let max = u64::MAX.to_string();
+        let key = format!("k{}", max);
+        let line = lines.line(&key);
+        assert_eq!(lines.slots(&line).last(), Some(&key));
+        let odd = LineMap::new(3);
+        assert_eq!(odd.line(&key), key);
+        assert_eq!(odd.slots(&key).len(), 1);
+        assert_eq!(lines.slots(&format!("k#{}", max)), [format!("k#{}", max)]);
+
+        let single = LineMap::default();
+        assert_eq!(single.line("user5"), "user5");
+        assert_eq!(single.slots("user5"), ["user5"]);
+    }
+}
@@ -0,0 +1,428 @@
+use crate::ConsistencyModel;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// `Instruction` litmus 程序中的一条指令
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    /// `x = 1`
+    Store { var: String, val: i64 },
+    /// `r0 = x`
+    Load { reg: String, var: String },
+    /// `fence`
+    Fence,
+}
+
+/// `Outcome` 一次执行的结果，形如 `P0:r0=0` 的寄存器值以及 exists 中引用的变量终值
+pub type Outcome = BTreeMap<String, i64>;
+
+/// `LitmusTest` 从文本解析得到的 litmus 测试
+///
+/// ```text
+/// # Store buffering
+/// name SB
+/// init x=0 y=0
+/// P0: x = 1; r0 = y
+/// P1: y = 1; r1 = x
+/// exists P0:r0=0 /\ P1:r1=0
+/// ```
+///
+/// `fence` 指令等待本线程之前的写操作全局可见
+#[derive(Debug, Clone, PartialEq)]
+pub struct LitmusTest {
+    pub name: String,
+    pub init: BTreeMap<String, i64>,
+    pub threads: Vec<Vec<Instruction>>,
+    /// 关注的结果，条件之间是合取关系
+    pub exists: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+fn is_reg(s: &str) -> bool {
+    s.len() > 1 && s.starts_with('r') && s[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_var(s: &str) -> bool {
+    !s.is_empty()
+        && !is_reg(s)
+        && s.chars().next().unwrap().is_ascii_alphabetic()
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(s: &str, line: usize) -> Result<i64, ParseError> {
+    s.trim().parse().map_err(|_| ParseError {
+        line,
+        msg: format!("invalid value {:?}", s),
+    })
+}
+
+fn parse_assign(s: &str, line: usize) -> Result<(String, i64), ParseError> {
+    match s.split_once('=') {
+        Some((k, v)) => Ok((k.trim().to_string(), parse_value(v, line)?)),
+        None => Err(ParseError {
+            line,
+            msg: format!("expected name=value, got {:?}", s),
+        }),
+    }
+}
+
+fn parse_instruction(s: &str, line: usize) -> Result<Instruction, ParseError> {
+    if s == "fence" {
+        return Ok(Instruction::Fence);
+    }
+    let (lhs, rhs) = match s.split_once('=') {
+        Some((l, r)) => (l.trim(), r.trim()),
+        None => {
+            return Err(ParseError {
+                line,
+                msg: format!("unknown instruction {:?}", s),
+            })
+        }
+    };
+    if is_reg(lhs) && is_var(rhs) {
+        return Ok(Instruction::Load {
+            reg: lhs.to_string(),
+            var: rhs.to_string(),
+        });
+    }
+    if is_var(lhs) {
+        return Ok(Instruction::Store {
+            var: lhs.to_string(),
+            val: parse_value(rhs, line)?,
+        });
+    }
+    Err(ParseError {
+        line,
+        msg: format!("unknown instruction {:?}", s),
+    })
+}
+
+impl LitmusTest {
+    /// `parse` 解析 litmus 文本，`#` 之后为注释
+    pub fn parse(text: &str) -> Result<LitmusTest, ParseError> {
+        let mut name = None;
+        let mut init = BTreeMap::new();
+        let mut threads: BTreeMap<usize, Vec<Instruction>> = BTreeMap::new();
+        let mut exists = Outcome::new();
+        let mut exists_line = 0;
+
+        for (i, raw) in text.lines().enumerate() {
+            let line = i + 1;
+            let content = raw.split('#').next().unwrap().trim();
+            if content.is_empty() {
+                continue;
+            }
+
+            if let Some(rest) = content.strip_prefix("name ") {
+                name = Some(rest.trim().to_string());
+            } else if let Some(rest) = content.strip_prefix("init ") {
+                for assign in rest.split_whitespace() {
+                    let (var, val) = parse_assign(assign, line)?;
+                    init.insert(var, val);
+                }
+            } else if let Some(rest) = content.strip_prefix("exists ") {
+                exists_line = line;
+                for cond in rest.split("/\\") {
+                    let (k, v) = parse_assign(cond, line)?;
+                    exists.insert(k, v);
+                }
+            } else if let Some((head, body)) = content.split_once(':') {
+                let tid: usize = head
+                    .trim()
+                    .strip_prefix('P')
+                    .and_then(|t| t.parse().ok())
+                    .ok_or(ParseError {
+                        line,
+                        msg: format!("invalid thread {:?}", head),
+                    })?;
+                let program = threads.entry(tid).or_default();
+                for s in body.split(';').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+                    program.push(parse_instruction(s, line)?);
+                }
+            } else {
+                return Err(ParseError {
+                    line,
+                    msg: format!("unknown directive {:?}", content),
+                });
+            }
+        }
+
+        let name = name.ok_or(ParseError {
+            line: 0,
+            msg: "missing name".to_string(),
+        })?;
+        if threads.keys().cloned().ne(0..threads.len()) {
+            return Err(ParseError {
+                line: 0,
+                msg: "threads must be numbered P0, P1, ...".to_string(),
+            });
+        }
+
+        let mut test = LitmusTest {
+            name,
+            init,
+            threads: threads.into_values().collect(),
+            exists,
+        };
+        // 未初始化的变量默认为 0
+        let vars = test.vars();
+        for var in &vars {
+            test.init.entry(var.clone()).or_insert(0);
+        }
+        // exists 只能引用程序中的变量以及某个线程读入的寄存器
+        for k in test.exists.keys() {
+            let known = match k.split_once(':') {
+                None => vars.contains(k),
+                Some((head, reg)) => head
+                    .strip_prefix('P')
+                    .and_then(|t| t.parse::<usize>().ok())
+                    .and_then(|t| test.threads.get(t))
+                    .is_some_and(|program| {
+                        program
+                            .iter()
+                            .any(|ins| matches!(ins, Instruction::Load { reg: r, .. } if r == reg))
+                    }),
+            };
+            if !known {
+                return Err(ParseError {
+                    line: exists_line,
+                    msg: format!("exists refers to unknown {:?}", k),
+                });
+            }
+        }
+        Ok(test)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<LitmusTest, ParseError> {
+        let text = fs::read_to_string(path).map_err(|e| ParseError {
+            line: 0,
+            msg: e.to_string(),
+        })?;
+        LitmusTest::parse(&text)
+    }
+
+    /// `vars` 程序中出现的所有变量
+    pub fn vars(&self) -> BTreeSet<String> {
+        let mut vars: BTreeSet<String> = self.init.keys().cloned().collect();
+        for program in &self.threads {
+            for ins in program {
+                match ins {
+                    Instruction::Store { var, .. } => vars.insert(var.clone()),
+                    Instruction::Load { var, .. } => vars.insert(var.clone()),
+                    Instruction::Fence => false,
+                };
+            }
+        }
+        vars
+    }
+
+    /// `observed_vars` 需要记录终值的变量，即 exists 中引用的变量
+    pub fn observed_vars(&self) -> Vec<String> {
+        self.exists
+            .keys()
+            .filter(|k| !k.contains(':'))
+            .cloned()
+            .collect()
+    }
+
+    /// `outcome` 由每个线程的寄存器和变量终值组成结果
+    pub fn outcome(
+        &self,
+        regs: &[BTreeMap<String, i64>],
+        memory: &BTreeMap<String, i64>,
+    ) -> Outcome {
+        let mut outcome = Outcome::new();
+        for (tid, regs) in regs.iter().enumerate() {
+            for (reg, val) in regs {
+                outcome.insert(format!("P{}:{}", tid, reg), *val);
+            }
+        }
+        for var in self.observed_vars() {
+            let val = memory.get(&var).copied().unwrap_or(0);
+            outcome.insert(var, val);
+        }
+        outcome
+    }
+
+    /// `sc_outcomes` 枚举所有交错执行，得到顺序一致性下允许的结果
+    pub fn sc_outcomes(&self) -> BTreeSet<Outcome> {
+        self.outcomes(ConsistencyModel::SC)
+    }
+
+    /// `outcomes` 按 model 的操作语义枚举所有执行，得到允许的结果
+    /// TSO 和 PSO 下每个线程有一个 store buffer，读操作优先从中转发，
+    /// TSO 按写入顺序写回，PSO 可以先写回任一地址最早的一项
+    pub fn outcomes(&self, model: ConsistencyModel) -> BTreeSet<Outcome> {
+        let n = self.threads.len();
+        let mut outcomes = BTreeSet::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![State {
+            pcs: vec![0; n],
+            regs: vec![BTreeMap::new(); n],
+            memory: self.init.clone(),
+            buffers: vec![VecDeque::new(); n],
+        }];
+
+        while let Some(state) = stack.pop() {
+            if !visited.insert(state.clone()) {
+                continue;
+            }
+
+            let mut finished = true;
+            for tid in 0..n {
+                let buffer = &state.buffers[tid];
+                if let Some(ins) = self.threads[tid].get(state.pcs[tid]) {
+                    finished = false;
+                    let mut next = state.clone();
+                    next.pcs[tid] += 1;
+                    match ins {
+                        Instruction::Store { var, val } => {
+                            if model == ConsistencyModel::SC {
+                                next.memory.insert(var.clone(), *val);
+                            } else {
+                                next.buffers[tid].push_back((var.clone(), *val));
+                            }
+                        }
+                        Instruction::Load { reg, var } => {
+                            let val = match buffer.iter().rev().find(|(k, _)| k == var) {
+                                Some((_, v)) => *v,
+                                None => state.memory.get(var).copied().unwrap_or(0),
+                            };
+                            next.regs[tid].insert(reg.clone(), val);
+                        }
+                        Instruction::Fence => {}
+                    }
+                    if *ins != Instruction::Fence || buffer.is_empty() {
+                        stack.push(next);
+                    }
+                }
+
+                let mut drains = Vec::new();
+                for (i, (var, _)) in buffer.iter().enumerate() {
+                    let first = buffer.iter().position(|(k, _)| k == var).unwrap();
+                    if (model == ConsistencyModel::PSO && first == i) || i == 0 {
+                        drains.push(i);
+                    }
+                }
+                for i in drains {
+                    finished = false;
+                    let mut next = state.clone();
+                    let (var, val) = next.buffers[tid].remove(i).unwrap();
+                    next.memory.insert(var, val);
+                    stack.push(next);
+                }
+            }
+            if finished {
+                outcomes.insert(self.outcome(&state.regs, &state.memory));
+            }
+        }
+        outcomes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct State {
+    pcs: Vec<usize>,
+    regs: Vec<BTreeMap<String, i64>>,
+    memory: BTreeMap<String, i64>,
+    buffers: Vec<VecDeque<(String, i64)>>,
+}
+
+/// `format_outcome` 以 `P0:r0=0 P1:r1=1 x=1` 的形式输出结果
+pub fn format_outcome(outcome: &Outcome) -> String {
+    outcome
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::litmus::litmus::*;
+
+    const SB: &str = "
+        # Store buffering
+        name SB
+        init x=0 y=0
+        P0: x = 1; r0 = y
+        P1: y = 1; r1 = x
+        exists P0:r0=0 /\\ P1:r1=0
+    ";
+
+    #[test]
+    fn test_parse() {
+        let test = LitmusTest::parse(SB).unwrap();
+        assert_eq!(test.name, "SB");
+        assert_eq!(test.threads.len(), 2);
+        assert_eq!(
+            test.threads[0],
+            vec![
+                Instruction::Store {
+                    var: "x".to_string(),
+                    val: 1
+                },
+                Instruction::Load {
+                    reg: "r0".to_string(),
+                    var: "y".to_string()
+                },
+            ]
+        );
+        assert_eq!(test.exists.len(), 2);
+
+        let err = LitmusTest::parse("name X\nP0: 1 = x").unwrap_err();
+        assert_eq!(err.line, 2);
+
+        // exists 引用了程序中没有的变量或寄存器
+        for exists in ["z=0", "P0:r1=0", "P2:r0=0"] {
+            let text = format!("name X\nP0: x = 1; r0 = y\nexists {}", exists);
+            let err = LitmusTest::parse(&text).unwrap_err();
+            assert_eq!(err.line, 3, "{}", exists);
+        }
+        let test = LitmusTest::parse("name X\nP0: x = 1; r0 = y\nexists x=1 /\\ y=0").unwrap();
+        assert_eq!(test.observed_vars(), ["x", "y"]);
+    }
+
+    #[test]
+    fn test_sc_outcomes() {
+        let test = LitmusTest::parse(SB).unwrap();
+        let outcomes = test.sc_outcomes();
+        assert_eq!(outcomes.len(), 3);
+        assert!(!outcomes.contains(&test.exists));
+    }
+
+    #[test]
+    fn test_relaxed_outcomes() {
+        let test = LitmusTest::parse(SB).unwrap();
+        assert!(test.outcomes(ConsistencyModel::TSO).contains(&test.exists));
+
+        let fenced = SB
+            .replace("x = 1;", "x = 1; fence;")
+            .replace("y = 1;", "y = 1; fence;");
+        let fenced = LitmusTest::parse(&fenced).unwrap();
+        assert_eq!(fenced.threads[1][1], Instruction::Fence);
+        assert!(!fenced
+            .outcomes(ConsistencyModel::TSO)
+            .contains(&fenced.exists));
+
+        let mp = LitmusTest::parse(
+            "name MP\nP0: x = 1; y = 1\nP1: r0 = y; r1 = x\nexists P1:r0=1 /\\ P1:r1=0",
+        )
+        .unwrap();
+        assert!(!mp.outcomes(ConsistencyModel::TSO).contains(&mp.exists));
+        assert!(mp.outcomes(ConsistencyModel::PSO).contains(&mp.exists));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::litmus::litmus::{format_outcome, Instruction, LitmusTest, Outcome};
+use crate::{CacheController, ConsistencyModel, ControllerConfig, Directory};
+use parking_lot::{Mutex, RwLock};
+use rand::Rng;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+/// `LitmusReport` 多次运行 litmus 测试得到的结果分布
+#[derive(Debug, Clone)]
+pub struct LitmusReport {
+    pub name: String,
+    pub model: ConsistencyModel,
+    pub iterations: usize,
+    pub histogram: BTreeMap<Outcome, usize>,
+    /// 顺序一致性下允许的结果
+    pub sc_outcomes: BTreeSet<Outcome>,
+    /// model 下允许的结果
+    pub allowed: BTreeSet<Outcome>,
+    pub exists: Outcome,
+}
+
+impl LitmusReport {
+    /// `forbidden` 被观测到、但顺序一致性下不允许的结果
+    pub fn forbidden(&self) -> Vec<(Outcome, usize)> {
+        self.histogram
+            .iter()
+            .filter(|(o, _)| !self.sc_outcomes.contains(*o))
+            .map(|(o, n)| (o.clone(), *n))
+            .collect()
+    }
+
+    /// `violations` 被观测到、但 model 下不允许的结果，说明实现有误
+    pub fn violations(&self) -> Vec<(Outcome, usize)> {
+        self.histogram
+            .iter()
+            .filter(|(o, _)| !self.allowed.contains(*o))
+            .map(|(o, n)| (o.clone(), *n))
+            .collect()
+    }
+
+    /// `exists_observed` 是否观测到满足 exists 条件的结果
+    pub fn exists_observed(&self) -> bool {
+        !self.exists.is_empty() && self.histogram.keys().any(|o| self.satisfies(o))
+    }
+
+    fn satisfies(&self, outcome: &Outcome) -> bool {
+        self.exists.iter().all(|(k, v)| outcome.get(k) == Some(v))
+    }
+}
+
+impl fmt::Display for LitmusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Test {} {:?} ({} iterations)",
+            self.name, self.model, self.iterations
+        )?;
+        // `*` 顺序一致性下不允许，`!` model 下不允许
+        for (outcome, n) in &self.histogram {
+            let mark = if !self.allowed.contains(outcome) {
+                "!"
+            } else if !self.sc_outcomes.contains(outcome) {
+                "*"
+            } else {
+                " "
+            };
+            writeln!(f, "{:<8}{} {}", n, mark, format_outcome(outcome))?;
+        }
+        if !self.exists.is_empty() {
+            writeln!(
+                f,
+                "exists {}: {}",
+                format_outcome(&self.exists),
+                if self.exists_observed() {
+                    "observed"
+                } else {
+                    "never"
+                }
+            )?;
+        }
+        let forbidden: usize = self.forbidden().iter().map(|f| f.1).sum();
+        write!(f, "forbidden under SC: {}", forbidden)
+    }
+}
+
+fn key(test: &LitmusTest, var: &str) -> String {
+    format!("{}.{}", test.name, var)
+}
+
+fn value(s: String) -> i64 {
+    if s.is_empty() {
+        0
+    } else {
+        s.parse().unwrap()
+    }
+}
+
+/// `run` 每个线程使用各自的、一致性模型为 model 的 `CacheController` 执行 iterations 次测试
+/// 每次执行前由额外的一个 controller 写入初值，执行后由它读出变量终值
+pub fn run(
+    test: &LitmusTest,
+    directory: Arc<RwLock<Directory>>,
+    model: ConsistencyModel,
+    iterations: usize,
+) -> LitmusReport {
+    let n = test.threads.len();
+    let barrier = Arc::new(Barrier::new(n + 1));
+    let regs = Arc::new(Mutex::new(vec![BTreeMap::new(); n]));
+
+    let mut handles = Vec::with_capacity(n);
+    for tid in 0..n {
+        let barrier = barrier.clone();
+        let regs = regs.clone();
+        let directory = directory.clone();
+        let test = test.clone();
+
+        handles.push(thread::spawn(move || {
+            let config = ControllerConfig {
+                model,
+                ..ControllerConfig::default()
+            };
+            let mut ct: CacheController<String> = CacheController::with_config(directory, config);
+            let mut rng = rand::thread_rng();
+            let program = &test.threads[tid];
+
+            for _ in 0..iterations {
+                barrier.wait();
+                // 随机错开各线程的起始时间，增加交错的可能
+                for _ in 0..rng.gen_range(0..200) {
+                    std::hint::spin_loop();
+                }
+
+                let mut local = BTreeMap::new();
+                for ins in program {
+                    match ins {
+                        Instruction::Store { var, val } => ct.set(key(&test, var), val.to_string()),
+                        Instruction::Load { reg, var } => {
+                            local.insert(reg.clone(), value(ct.get(key(&test, var))));
+                        }
+                        Instruction::Fence => ct.fence(),
+                    }
+                }
+                ct.fence();
+                regs.lock()[tid] = local;
+                barrier.wait();
+            }
+        }));
+    }
+
+    let mut ct: CacheController<String> = CacheController::new(directory);
+    let mut histogram = BTreeMap::new();
+    for _ in 0..iterations {
+        for (var, val) in &test.init {
+            ct.set(key(test, var), val.to_string());
+        }
+        barrier.wait();
+        barrier.wait();
+
+        let memory: BTreeMap<String, i64> = test
+            .observed_vars()
+            .into_iter()
+            .map(|var| {
+                let val = value(ct.get(key(test, &var)));
+                (var, val)
+            })
+            .collect();
+        let outcome = test.outcome(&regs.lock(), &memory);
+        *histogram.entry(outcome).or_insert(0) += 1;
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    LitmusReport {
+        name: test.name.clone(),
+        model,
+        iterations,
+        histogram,
+        sc_outcomes: test.sc_outcomes(),
+        allowed: test.outcomes(model),
+        exists: test.exists.clone(),
+    }
+}
@@ -0,0 +1,485 @@
+//! 目录持有的共享末级缓存（LLC），位于私有缓存和 db 之间
+//!
+//! - Inclusive：私有缓存中的每一项都在 LLC 中；读缺失时装入 LLC，写时装入一个没有数据的占位项，
+//!   LLC 淘汰一项时向持有它的私有缓存发出回收失效（back-invalidation）
+//! - Exclusive：LLC 只保存私有缓存淘汰的项，读命中时移出 LLC
+//! - NonInclusive：读缺失时装入 LLC，私有缓存淘汰的 Modified 项写回 LLC，LLC 淘汰时不回收
+//!
+//! LLC 中的脏数据在被淘汰时写回 db，写 db 期间持有 LLC 的锁，其他线程不会读到旧值。
+use crate::db::db::DbSession;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// `InclusionPolicy` LLC 与私有缓存之间的包含关系
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InclusionPolicy {
+    Inclusive,
+    Exclusive,
+    NonInclusive,
+}
+
+/// `Replacement` LLC 满时选择淘汰项的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replacement {
+    Lru,
+    Fifo,
+    Random,
+}
+
+impl fmt::Display for InclusionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InclusionPolicy::Inclusive => write!(f, "inclusive"),
+            InclusionPolicy::Exclusive => write!(f, "exclusive"),
+            InclusionPolicy::NonInclusive => write!(f, "nine"),
+        }
+    }
+}
+
+impl FromStr for InclusionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "inclusive" => Ok(InclusionPolicy::Inclusive),
+            "exclusive" => Ok(InclusionPolicy::Exclusive),
+            "nine" | "non-inclusive" => Ok(InclusionPolicy::NonInclusive),
+            _ => Err(format!("unknown inclusion policy {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Replacement::Lru => write!(f, "lru"),
+            Replacement::Fifo => write!(f, "fifo"),
+            Replacement::Random => write!(f, "random"),
+        }
+    }
+}
+
+impl FromStr for Replacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "lru" => Ok(Replacement::Lru),
+            "fifo" => Ok(Replacement::Fifo),
+            "random" => Ok(Replacement::Random),
+            _ => Err(format!("unknown replacement {:?}", s)),
+        }
+    }
+}
+
+/// `LlcConfig` LLC 配置，容量按目录分片平分
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlcConfig {
+    /// 能保存的项数
+    pub capacity: usize,
+    pub policy: InclusionPolicy,
+    pub replacement: Replacement,
+    /// Random 替换使用的种子
+    pub seed: u64,
+}
+
+impl Default for LlcConfig {
+    fn default() -> Self {
+        LlcConfig {
+            capacity: 1024,
+            policy: InclusionPolicy::Inclusive,
+            replacement: Replacement::Lru,
+            seed: 0,
+        }
+    }
+}
+
+/// `LlcStats` LLC 的统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LlcStats {
+    pub hits: u64,
+    /// 不在 LLC 中，或只有占位项
+    pub misses: u64,
+    pub fills: u64,
+    pub evictions: u64,
+    /// 淘汰脏数据时写回 db 的次数
+    pub write_backs: u64,
+    /// 淘汰时需要回收的项，只有 Inclusive 有
+    pub back_invalidations: u64,
+    /// 回收时发给私有缓存的 RemoteWrite
+    pub back_invalidation_messages: u64,
+}
+
+impl LlcStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+
+    pub fn merge(&mut self, other: &LlcStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.fills += other.fills;
+        self.evictions += other.evictions;
+        self.write_backs += other.write_backs;
+        self.back_invalidations += other.back_invalidations;
+        self.back_invalidation_messages += other.back_invalidation_messages;
+    }
+}
+
+impl fmt::Display for LlcStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "llc hits {} misses {} ({:.3}) fills {}",
+            self.hits,
+            self.misses,
+            self.hit_ratio(),
+            self.fills
+        )?;
+        write!(
+            f,
+            "llc evictions {} write backs {} back-invalidations {} ({} messages)",
+            self.evictions,
+            self.write_backs,
+            self.back_invalidations,
+            self.back_invalidation_messages
+        )
+    }
+}
+
+/// `Effects` 一次 LLC 操作对 db 和私有缓存的影响
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Effects {
+    pub db_reads: u64,
+    pub db_writes: u64,
+    /// 需要在私有缓存中失效的 key
+    pub back_invalidate: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    // Inclusive 写时的占位项没有数据，数据在私有缓存的 Modified 副本中
+    value: Option<String>,
+    dirty: bool,
+    // 在 order 中的位置
+    stamp: u64,
+}
+
+/// `Llc` 一个目录分片的 LLC
+pub struct Llc {
+    config: LlcConfig,
+    lines: HashMap<String, Line>,
+    // 按装入（FIFO）或最近访问（LRU）的顺序
+    order: BTreeMap<u64, String>,
+    clock: u64,
+    rng: StdRng,
+    stats: LlcStats,
+}
+
+impl Llc {
+    pub fn new(config: LlcConfig) -> Llc {
+        Llc {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            lines: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+            stats: LlcStats::default(),
+        }
+    }
+
+    pub fn policy(&self) -> InclusionPolicy {
+        self.config.policy
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.lines.contains_key(key)
+    }
+
+    pub fn stats(&self) -> LlcStats {
+        self.stats.clone()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = LlcStats::default();
+    }
+
+    /// `back_invalidated` 记录一次回收发出的消息数
+    pub fn back_invalidated(&mut self, messages: usize) {
+        self.stats.back_invalidation_messages += messages as u64;
+    }
+
+    fn touch(&mut self, key: &str) {
+        if self.config.replacement != Replacement::Lru {
+            return;
+        }
+        self.clock += 1;
+        let line = self.lines.get_mut(key).unwrap();
+        self.order.remove(&line.stamp);
+        line.stamp = self.clock;
+        self.order.insert(self.clock, key.to_string());
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Line> {
+        let line = self.lines.remove(key)?;
+        self.order.remove(&line.stamp);
+        Some(line)
+    }
+
+    fn victim(&mut self) -> String {
+        let i = match self.config.replacement {
+            Replacement::Random => self.rng.gen_range(0..self.order.len()),
+            _ => 0,
+        };
+        self.order.values().nth(i).unwrap().clone()
+    }
+
+    /// `insert` 装入或更新 key，满时先按替换策略淘汰
+    fn insert(
+        &mut self,
+        key: &str,
+        value: Option<String>,
+        dirty: bool,
+        db: &DbSession,
+        fx: &mut Effects,
+    ) {
+        if let Some(line) = self.lines.get_mut(key) {
+            line.value = value;
+            line.dirty = dirty;
+            self.touch(key);
+            return;
+        }
+        while self.lines.len() >= self.config.capacity.max(1) {
+            let victim = self.victim();
+            let line = self.remove(&victim).unwrap();
+            self.stats.evictions += 1;
+            if let (true, Some(value)) = (line.dirty, line.value) {
+                self.stats.write_backs += 1;
+                fx.db_writes += 1;
+                db.set(victim.clone(), value);
+            }
+            if self.config.policy == InclusionPolicy::Inclusive {
+                self.stats.back_invalidations += 1;
+                fx.back_invalidate.push(victim);
+            }
+        }
+        self.stats.fills += 1;
+        self.clock += 1;
+        self.order.insert(self.clock, key.to_string());
+        self.lines.insert(
+            key.to_string(),
+            Line {
+                value,
+                dirty,
+                stamp: self.clock,
+            },
+        );
+    }
+
+    /// `read` 读缺失的私有缓存读取 key，LLC 未命中时从 db 读取
+    pub fn read(&mut self, key: &str, db: &DbSession) -> (String, Effects) {
+        let mut fx = Effects::default();
+        let hit = self.lines.get(key).and_then(|l| l.value.clone());
+        if let Some(value) = hit {
+            self.stats.hits += 1;
+            if self.config.policy == InclusionPolicy::Exclusive {
+                // 移入私有缓存，私有缓存中的副本是干净的，脏数据先写回 db
+                if self.remove(key).unwrap().dirty {
+                    fx.db_writes += 1;
+                    db.set(key.to_string(), value.clone());
+                }
+            } else {
+                self.touch(key);
+            }
+            return (value, fx);
+        }
+
+        self.stats.misses += 1;
+        fx.db_reads += 1;
+        let value = db.get(key.to_string());
+        if self.config.policy != InclusionPolicy::Exclusive {
+            self.insert(key, Some(value.clone()), false, db, &mut fx);
+        }
+        (value, fx)
+    }
+
+    /// `write` 一个私有缓存取得 key 的 Modified 副本，LLC 中原有的数据已过期
+    pub fn write(&mut self, key: &str, db: &DbSession) -> Effects {
+        let mut fx = Effects::default();
+        match self.config.policy {
+            InclusionPolicy::Inclusive => self.insert(key, None, false, db, &mut fx),
+            _ => {
+                self.remove(key);
+            }
+        }
+        fx
+    }
+
+    /// `write_back` 私有缓存写回 key 的 Modified 副本，或写直达、写更新写入的新值
+    /// evicted 为 true 时该副本已被私有缓存淘汰，否则私有缓存仍保留干净的副本或已失效
+    pub fn write_back(
+        &mut self,
+        key: &str,
+        value: String,
+        evicted: bool,
+        db: &DbSession,
+    ) -> Effects {
+        let mut fx = Effects::default();
+        let allocate = match self.config.policy {
+            // 正在被回收的项不再装入
+            InclusionPolicy::Inclusive => self.contains(key),
+            InclusionPolicy::NonInclusive => true,
+            InclusionPolicy::Exclusive => evicted,
+        };
+        if allocate {
+            self.insert(key, Some(value), true, db, &mut fx);
+        } else {
+            // Exclusive 中可能还有写直达、写更新之前淘汰的旧值
+            self.remove(key);
+            fx.db_writes += 1;
+            db.set(key.to_string(), value);
+        }
+        fx
+    }
+
+    /// `evict_clean` 私有缓存淘汰了干净的副本，Exclusive 时装入 LLC
+    pub fn evict_clean(&mut self, key: &str, value: String, db: &DbSession) -> Effects {
+        let mut fx = Effects::default();
+        if self.config.policy == InclusionPolicy::Exclusive && !self.contains(key) {
+            self.insert(key, Some(value), false, db, &mut fx);
+        }
+        fx
+    }
+
+    /// `recall` 其他集群读写 key，脏数据写回 db 使其可见；写时 LLC 中的数据已过期，一并丢弃
+    pub fn recall(&mut self, key: &str, write: bool, db: &DbSession) -> Effects {
+        let mut fx = Effects::default();
+        let line = if write {
+            self.remove(key)
+        } else {
+            self.lines.get_mut(key).map(|l| {
+                let line = l.clone();
+                l.dirty = false;
+                line
+            })
+        };
+        if let Some(Line {
+            value: Some(value),
+            dirty: true,
+            ..
+        }) = line
+        {
+            self.stats.write_backs += 1;
+            fx.db_writes += 1;
+            db.set(key.to_string(), value);
+        }
+        fx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn llc(policy: InclusionPolicy, replacement: Replacement) -> (Llc, DbSession) {
+        let config = LlcConfig {
+            capacity: 2,
+            policy,
+            replacement,
+            seed: 0,
+        };
+        let db = DbSession::temporary().with_latency(std::time::Duration::ZERO);
+        (Llc::new(config), db)
+    }
+
+    #[test]
+    fn test_replacement() {
+        let (mut lru, db) = llc(InclusionPolicy::NonInclusive, Replacement::Lru);
+        lru.read("a", &db);
+        lru.read("b", &db);
+        lru.read("a", &db);
+        lru.read("c", &db);
+        assert!(lru.contains("a") && !lru.contains("b"));
+
+        let (mut fifo, db) = llc(InclusionPolicy::NonInclusive, Replacement::Fifo);
+        fifo.read("a", &db);
+        fifo.read("b", &db);
+        fifo.read("a", &db);
+        fifo.read("c", &db);
+        assert!(!fifo.contains("a") && fifo.contains("b"));
+        assert_eq!(fifo.stats().hits, 1);
+        assert_eq!(fifo.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_inclusive() {
+        let (mut l, db) = llc(InclusionPolicy::Inclusive, Replacement::Lru);
+        // 写时装入占位项，读占位项视为缺失
+        assert!(l.write("a", &db).back_invalidate.is_empty());
+        let fx = l.write_back("a", "1".to_string(), false, &db);
+        assert_eq!(fx.db_writes, 0);
+        assert_eq!(l.read("a", &db), ("1".to_string(), Effects::default()));
+
+        l.read("b", &db);
+        // 淘汰脏的 a：写回 db 并回收私有缓存中的副本
+        let (_, fx) = l.read("c", &db);
+        assert_eq!(fx.db_writes, 1);
+        assert_eq!(fx.back_invalidate, vec!["a".to_string()]);
+        assert_eq!(db.get("a".to_string()), "1");
+        // 回收时私有缓存写回的数据直接写 db
+        assert_eq!(l.write_back("a", "2".to_string(), false, &db).db_writes, 1);
+        assert!(!l.contains("a"));
+    }
+
+    #[test]
+    fn test_exclusive() {
+        let (mut l, db) = llc(InclusionPolicy::Exclusive, Replacement::Lru);
+        let (_, fx) = l.read("a", &db);
+        assert_eq!(fx.db_reads, 1);
+        assert!(l.is_empty());
+
+        // 私有缓存淘汰后进入 LLC，再次读取时移出
+        l.write_back("a", "1".to_string(), true, &db);
+        l.evict_clean("b", "".to_string(), &db);
+        assert_eq!(l.len(), 2);
+        let (value, fx) = l.read("a", &db);
+        assert_eq!(value, "1");
+        assert_eq!(fx.db_writes, 1);
+        assert!(!l.contains("a") && l.contains("b"));
+
+        // 写使 LLC 中的数据过期
+        l.write("b", &db);
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn test_recall() {
+        let (mut l, db) = llc(InclusionPolicy::NonInclusive, Replacement::Lru);
+        l.write_back("a", "1".to_string(), true, &db);
+        // 其他集群读：写回 db，保留干净的数据
+        assert_eq!(l.recall("a", false, &db).db_writes, 1);
+        assert_eq!(db.get("a".to_string()), "1");
+        assert_eq!(l.recall("a", false, &db).db_writes, 0);
+        assert!(l.contains("a"));
+        // 其他集群写：丢弃
+        l.write_back("a", "2".to_string(), true, &db);
+        assert_eq!(l.recall("a", true, &db).db_writes, 1);
+        assert!(!l.contains("a"));
+        assert_eq!(db.get("a".to_string()), "2");
+    }
+}
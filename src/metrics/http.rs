@@ -0,0 +1,134 @@
+use crate::metrics::metrics::Registry;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// 读写一个连接的超时，超时的请求当作客户端已断开，之后的连接不会被一直阻塞
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `MetricsServer` 在本机地址上以 Prometheus 文本格式提供 GET /metrics
+/// 逐个处理连接，只用于调试和抓取，释放时停止
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// `serve` 监听 127.0.0.1:port，port 为 0 时由系统分配
+    pub fn serve(registry: Arc<Registry>, port: u16) -> io::Result<MetricsServer> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let _stop = stop.clone();
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if _stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    // 客户端断开等错误只影响这一个连接
+                    let _ = respond(stream, &registry);
+                }
+            }
+        });
+
+        Ok(MetricsServer {
+            addr,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // 连接一次，唤醒阻塞在 accept 上的线程
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, registry: &Registry) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // 读完请求头
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            ("200 OK", "text/plain; version=0.0.4", registry.render())
+        }
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_serve() {
+        let server = MetricsServer::serve(Arc::new(Registry::new()), 0).unwrap();
+        let addr = server.local_addr();
+        assert!(addr.ip().is_loopback());
+
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404"));
+
+        drop(server);
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn test_silent_client() {
+        let server = MetricsServer::serve(Arc::new(Registry::new()), 0).unwrap();
+        let addr = server.local_addr();
+        // 连接之后不发送请求，超时后继续处理之后的连接
+        let _silent = TcpStream::connect(addr).unwrap();
+        assert!(get(addr, "/metrics").starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let _silent = TcpStream::connect(addr).unwrap();
+        let start = std::time::Instant::now();
+        drop(server);
+        assert!(start.elapsed() < TIMEOUT * 3);
+    }
+}
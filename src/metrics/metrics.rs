@@ -0,0 +1,473 @@
+use crate::stats::histogram::Histogram;
+use crate::stats::stats::{ControllerStats, LatencyRecorder, Stats};
+use crate::{CacheController, Directory, Status, ThreadID};
+use parking_lot::{Mutex, RwLock};
+use std::fmt::Write;
+use std::sync::{Arc, Weak};
+
+const FANOUT_BUCKETS: [u64; 8] = [0, 1, 2, 4, 8, 16, 32, 64];
+const LATENCY_QUANTILES: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+
+/// `Family` 同名的一组样本，对应 Prometheus 文本格式中的一个 metric
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<(String, String, f64)>,
+}
+
+impl Family {
+    fn new(name: &'static str, kind: &'static str, help: &'static str) -> Family {
+        Family {
+            name,
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, labels: &[(&str, String)], value: f64) {
+        self.add_suffixed("", labels, value);
+    }
+
+    fn add_suffixed(&mut self, suffix: &str, labels: &[(&str, String)], value: f64) {
+        let labels = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+            .collect::<Vec<_>>()
+            .join(",");
+        self.samples.push((suffix.to_string(), labels, value));
+    }
+
+    /// `histogram` 以 Prometheus histogram 的形式输出 h，bounds 为各桶的上界
+    fn histogram(&mut self, labels: &[(&str, String)], h: &Histogram, bounds: &[u64], scale: f64) {
+        let buckets = h.buckets();
+        for b in bounds {
+            let n: u64 = buckets.iter().filter(|(v, _)| v <= b).map(|(_, c)| c).sum();
+            let mut l = labels.to_vec();
+            l.push(("le", format_value(*b as f64 * scale)));
+            self.add_suffixed("_bucket", &l, n as f64);
+        }
+        let mut l = labels.to_vec();
+        l.push(("le", "+Inf".to_string()));
+        self.add_suffixed("_bucket", &l, h.count() as f64);
+        self.add_suffixed("_sum", labels, h.mean() * h.count() as f64 * scale);
+        self.add_suffixed("_count", labels, h.count() as f64);
+    }
+
+    fn encode(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        for (suffix, labels, value) in &self.samples {
+            if labels.is_empty() {
+                let _ = writeln!(out, "{}{} {}", self.name, suffix, format_value(*value));
+            } else {
+                let _ = writeln!(
+                    out,
+                    "{}{}{{{}}} {}",
+                    self.name,
+                    suffix,
+                    labels,
+                    format_value(*value)
+                );
+            }
+        }
+    }
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_value(v: f64) -> String {
+    if v.is_nan() {
+        "NaN".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+/// `ControllerSource` 注册到 Registry 的 CacheController
+/// 只持有统计，controller 释放后它的计数仍然保留
+struct ControllerSource {
+    thread_id: ThreadID,
+    stats: Arc<ControllerStats>,
+    latencies: Arc<LatencyRecorder>,
+}
+
+/// `Registry` 汇总 CacheController 和 Directory 的统计，输出 Prometheus 文本格式
+#[derive(Default)]
+pub struct Registry {
+    controllers: Mutex<Vec<ControllerSource>>,
+    directory: Mutex<Option<Weak<RwLock<Directory>>>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    pub fn register_controller<T: Clone + ToString + Sync>(&self, ct: &CacheController<T>) {
+        self.controllers.lock().push(ControllerSource {
+            thread_id: ct.thread_id,
+            stats: ct.stats.clone(),
+            latencies: ct.latencies.clone(),
+        });
+    }
+
+    /// `register_directory` 只保留最近一次注册的 Directory，不延长它的生命周期
+    pub fn register_directory(&self, directory: &Arc<RwLock<Directory>>) {
+        *self.directory.lock() = Some(Arc::downgrade(directory));
+    }
+
+    /// `render` 以 Prometheus 文本格式输出当前的所有指标
+    pub fn render(&self) -> String {
+        let mut families = self.controller_families();
+        let directory = self.directory.lock().as_ref().and_then(|d| d.upgrade());
+        if let Some(directory) = directory {
+            families.extend(directory_families(&directory.read()));
+        }
+
+        let mut out = String::new();
+        for f in families.iter().filter(|f| !f.samples.is_empty()) {
+            f.encode(&mut out);
+        }
+        out
+    }
+
+    fn controller_families(&self) -> Vec<Family> {
+        let mut hits = Family::new(
+            "mesi_cache_hits_total",
+            "counter",
+            "Accesses served from the local cache.",
+        );
+        let mut misses = Family::new(
+            "mesi_cache_misses_total",
+            "counter",
+            "Accesses that went through the directory, by miss type.",
+        );
+        let mut ratio = Family::new(
+            "mesi_cache_hit_ratio",
+            "gauge",
+            "Hits over all accesses since the last reset.",
+        );
+        let mut inv_received = Family::new(
+            "mesi_invalidations_received_total",
+            "counter",
+            "Valid lines invalidated by a RemoteWrite.",
+        );
+        let mut false_sharing = Family::new(
+            "mesi_false_sharing_invalidations_total",
+            "counter",
+            "Invalidations caused by a write to a slot this controller did not hold.",
+        );
+        let mut inv_sent = Family::new(
+            "mesi_invalidations_sent_total",
+            "counter",
+            "RemoteWrite messages caused by this controller's writes.",
+        );
+        let mut write_backs = Family::new(
+            "mesi_write_backs_total",
+            "counter",
+            "Modified lines written back to the database.",
+        );
+        let mut prefetches = Family::new(
+            "mesi_prefetches_total",
+            "counter",
+            "Prefetch GetS requests by outcome: used by a later read or not (yet).",
+        );
+        let mut mshr = Family::new(
+            "mesi_mshr_events_total",
+            "counter",
+            "Non-blocking reads merged into an outstanding miss, or stalled on a full MSHR table.",
+        );
+        let mut victim_hits = Family::new(
+            "mesi_victim_hits_total",
+            "counter",
+            "Reads and writes served from the victim buffer.",
+        );
+        let mut evictions = Family::new(
+            "mesi_evictions_total",
+            "counter",
+            "Lines evicted by flush, by state at eviction.",
+        );
+        let mut latency = Family::new(
+            "mesi_access_latency_seconds",
+            "summary",
+            "get/set latency by access type.",
+        );
+
+        let mut total = Stats::default();
+        for c in self.controllers.lock().iter() {
+            let s = c.stats.snapshot();
+            let thread = || ("thread", c.thread_id.to_string());
+            for (op, n) in [("read", s.read_hits), ("write", s.write_hits)] {
+                hits.add(&[thread(), ("op", op.to_string())], n as f64);
+            }
+            for (kind, n) in [
+                ("read", s.read_misses),
+                ("write", s.write_misses),
+                ("upgrade", s.upgrades),
+            ] {
+                misses.add(&[thread(), ("type", kind.to_string())], n as f64);
+            }
+            ratio.add(&[thread()], s.hit_ratio());
+            inv_received.add(&[thread()], s.invalidations_received as f64);
+            false_sharing.add(&[thread()], s.false_sharing_invalidations as f64);
+            inv_sent.add(&[thread()], s.invalidations_sent as f64);
+            write_backs.add(&[thread()], s.write_backs as f64);
+            for (outcome, n) in [
+                ("used", s.prefetch_hits),
+                ("unused", s.prefetches.saturating_sub(s.prefetch_hits)),
+            ] {
+                prefetches.add(&[thread(), ("outcome", outcome.to_string())], n as f64);
+            }
+            victim_hits.add(&[thread()], s.victim_hits as f64);
+            for (event, n) in [("merge", s.mshr_merges), ("stall", s.mshr_stalls)] {
+                mshr.add(&[thread(), ("event", event.to_string())], n as f64);
+            }
+            for status in Status::ALL.iter().take(3) {
+                evictions.add(
+                    &[thread(), ("state", format!("{:?}", status))],
+                    s.evicted(status) as f64,
+                );
+            }
+
+            let l = c.latencies.snapshot();
+            for (kind, h) in [("hit", &l.hit), ("miss", &l.miss), ("upgrade", &l.upgrade)] {
+                let labels = [thread(), ("type", kind.to_string())];
+                for q in LATENCY_QUANTILES {
+                    let mut ql = labels.to_vec();
+                    ql.push(("quantile", q.to_string()));
+                    latency.add(&ql, h.percentile(q * 100.0) as f64 / 1e9);
+                }
+                latency.add_suffixed("_sum", &labels, h.mean() * h.count() as f64 / 1e9);
+                latency.add_suffixed("_count", &labels, h.count() as f64);
+            }
+            total.merge(&s);
+        }
+        if !ratio.samples.is_empty() {
+            ratio.add(&[("thread", "all".to_string())], total.hit_ratio());
+        }
+
+        vec![
+            hits,
+            misses,
+            ratio,
+            inv_received,
+            false_sharing,
+            inv_sent,
+            write_backs,
+            prefetches,
+            mshr,
+            victim_hits,
+            evictions,
+            latency,
+        ]
+    }
+}
+
+fn directory_families(directory: &Directory) -> Vec<Family> {
+    let s = directory.stats();
+
+    let mut requests = Family::new(
+        "mesi_directory_requests_total",
+        "counter",
+        "Requests handled by the directory.",
+    );
+    requests.add(&[("type", "read".to_string())], s.read_requests as f64);
+    requests.add(&[("type", "write".to_string())], s.write_requests as f64);
+
+    let mut messages = Family::new(
+        "mesi_directory_messages_total",
+        "counter",
+        "Messages sent and received by the directory, by type.",
+    );
+    for (kind, n) in [
+        ("remote_read", s.remote_reads),
+        ("remote_write", s.remote_writes),
+        ("update", s.updates),
+        ("retransmission", s.retransmissions),
+        ("confirmed", s.confirmations),
+        ("discarded", s.discarded),
+    ] {
+        messages.add(&[("type", kind.to_string())], n as f64);
+    }
+
+    let mut timeouts = Family::new(
+        "mesi_directory_timeouts_total",
+        "counter",
+        "Requests that exceeded the retransmission limit.",
+    );
+    timeouts.add(&[], s.timeouts as f64);
+
+    let mut db = Family::new(
+        "mesi_db_operations_total",
+        "counter",
+        "Reads and writes against the backing store.",
+    );
+    db.add(&[("op", "read".to_string())], s.db_reads as f64);
+    db.add(&[("op", "write".to_string())], s.db_writes as f64);
+
+    let mut fanout = Family::new(
+        "mesi_invalidation_fanout",
+        "histogram",
+        "RemoteWrite messages sent per write request.",
+    );
+    fanout.histogram(&[], &directory.fanout(), &FANOUT_BUCKETS, 1.0);
+
+    // 不等待被占用的分片，占用期间的目录项不计入
+    let (sharers, _) = directory.sharers();
+    let mut occupancy = Family::new(
+        "mesi_directory_entries",
+        "gauge",
+        "Directory entries, and entries with at least one sharer.",
+    );
+    occupancy.add(&[("state", "all".to_string())], sharers.len() as f64);
+    occupancy.add(
+        &[("state", "shared".to_string())],
+        sharers.iter().filter(|(_, s)| !s.is_empty()).count() as f64,
+    );
+
+    let mut distribution = Histogram::new();
+    for (_, s) in &sharers {
+        distribution.record(s.len() as u64);
+    }
+    let mut sharer_count = Family::new(
+        "mesi_directory_sharers",
+        "histogram",
+        "Number of sharers per directory entry.",
+    );
+    sharer_count.histogram(&[], &distribution, &FANOUT_BUCKETS, 1.0);
+
+    let mut families = vec![
+        requests,
+        messages,
+        timeouts,
+        db,
+        fanout,
+        occupancy,
+        sharer_count,
+    ];
+    if let Some(a) = directory.adaptive_stats() {
+        let mut patterns = Family::new(
+            "mesi_sharing_pattern_keys",
+            "gauge",
+            "Keys currently classified as each sharing pattern.",
+        );
+        patterns.add(
+            &[("pattern", "migratory".to_string())],
+            a.migratory_keys as f64,
+        );
+        patterns.add(
+            &[("pattern", "producer-consumer".to_string())],
+            a.producer_consumer_keys as f64,
+        );
+        let mut switches = Family::new(
+            "mesi_protocol_switches_total",
+            "counter",
+            "Per-key protocol switches, by the protocol switched to.",
+        );
+        for (to, n) in [
+            ("migratory", a.switches_to_migratory),
+            ("update", a.switches_to_update),
+            ("default", a.switches_to_default),
+        ] {
+            switches.add(&[("to", to.to_string())], n as f64);
+        }
+        families.push(patterns);
+        families.push(switches);
+    }
+    families
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::db::DbSession;
+    use crate::DirectoryConfig;
+
+    fn sample(text: &str, line: &str) -> f64 {
+        text.lines()
+            .find(|l| l.starts_with(line) && l[line.len()..].starts_with(' '))
+            .unwrap_or_else(|| panic!("missing {}", line))[line.len() + 1..]
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let directory = Arc::new(RwLock::new(Directory::with_db(
+            DbSession::temporary(),
+            DirectoryConfig::default(),
+        )));
+        let registry = Registry::new();
+        registry.register_directory(&directory);
+
+        let mut c1: CacheController<String> = CacheController::new(directory.clone());
+        let mut c2: CacheController<String> = CacheController::new(directory.clone());
+        let mut c3: CacheController<String> = CacheController::new(directory.clone());
+        registry.register_controller(&c1);
+        registry.register_controller(&c2);
+
+        c1.get("m.x".to_string());
+        c2.get("m.x".to_string());
+        c3.get("m.x".to_string());
+        c1.get("m.x".to_string());
+        c1.set("m.x".to_string(), "1".to_string());
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE mesi_cache_hits_total counter\n"));
+        assert_eq!(
+            sample(&text, "mesi_cache_hits_total{thread=\"0\",op=\"read\"}"),
+            1.0
+        );
+        assert_eq!(
+            sample(
+                &text,
+                "mesi_cache_misses_total{thread=\"0\",type=\"upgrade\"}"
+            ),
+            1.0
+        );
+        assert_eq!(
+            sample(&text, "mesi_cache_hit_ratio{thread=\"all\"}"),
+            1.0 / 4.0
+        );
+        assert_eq!(
+            sample(&text, "mesi_invalidations_sent_total{thread=\"0\"}"),
+            2.0
+        );
+        assert_eq!(sample(&text, "mesi_db_operations_total{op=\"read\"}"), 3.0);
+        assert_eq!(
+            sample(&text, "mesi_invalidation_fanout_bucket{le=\"1\"}"),
+            0.0
+        );
+        assert_eq!(
+            sample(&text, "mesi_invalidation_fanout_bucket{le=\"2\"}"),
+            1.0
+        );
+        assert_eq!(
+            sample(&text, "mesi_directory_entries{state=\"shared\"}"),
+            1.0
+        );
+        assert_eq!(sample(&text, "mesi_directory_sharers_sum"), 1.0);
+        assert_eq!(
+            sample(
+                &text,
+                "mesi_access_latency_seconds_count{thread=\"1\",type=\"miss\"}"
+            ),
+            1.0
+        );
+
+        // Directory 释放后只输出 controller 的指标
+        drop((c1, c2, c3));
+        drop(directory);
+        let text = registry.render();
+        assert!(text.contains("mesi_cache_hits_total"));
+        assert!(!text.contains("mesi_directory"));
+    }
+}
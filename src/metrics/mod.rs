@@ -0,0 +1,4 @@
+#[cfg(feature = "metrics-http")]
+pub mod http;
+#[allow(clippy::module_inception)]
+pub mod metrics;
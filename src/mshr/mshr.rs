@@ -0,0 +1,200 @@
+//! 非阻塞的读缺失
+//!
+//! `CacheController::issue` 发出一次读后立即返回 `Ticket`，缺失由后台线程向目录请求数据，
+//! 期间占用一个 MSHR（miss status holding register）。
+//!
+//! - 同一个 key 已有未完成的缺失时不再请求目录，合并到该 MSHR，数据返回时一起完成
+//! - 所有 MSHR 都被占用时，新的缺失等待任意一个缺失完成
+//! - 缺失按数据返回的顺序完成，不一定是发出的顺序，结果由 `poll`、`wait` 取得
+use crate::stats::histogram::{AtomicHistogram, Histogram};
+use parking_lot::{Condvar, Mutex};
+use std::collections::{HashMap, VecDeque};
+
+/// `Ticket` 标识一次非阻塞读，按发出的顺序递增
+pub type Ticket = u64;
+
+/// `Allocation` 为一次缺失分配 MSHR 的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allocation {
+    /// 分配了新的 MSHR，需要请求目录；stalled 为是否等待过其他缺失完成
+    Primary { stalled: bool },
+    /// 合并到同一个 key 上未完成的缺失
+    Merged,
+}
+
+struct State<T> {
+    next: Ticket,
+    // 未完成的缺失，每个 key 一个 MSHR，记录等待它的读
+    entries: HashMap<String, Vec<Ticket>>,
+    // 已完成、还没有被取走的读，按完成的顺序
+    completed: VecDeque<(Ticket, T)>,
+}
+
+/// `Mshrs` 一个 CacheController 的 MSHR 表，与完成缺失的后台线程共享
+pub struct Mshrs<T> {
+    capacity: usize,
+    state: Mutex<State<T>>,
+    done: Condvar,
+    // 每次分配 MSHR 之后未完成的缺失数
+    occupancy: AtomicHistogram,
+}
+
+impl<T: Clone> Mshrs<T> {
+    /// `new` 至少有一个 MSHR
+    pub fn new(capacity: usize) -> Mshrs<T> {
+        Mshrs {
+            capacity: capacity.max(1),
+            state: Mutex::new(State {
+                next: 0,
+                entries: HashMap::new(),
+                completed: VecDeque::new(),
+            }),
+            done: Condvar::new(),
+            occupancy: AtomicHistogram::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// `ticket` 为一次读分配 Ticket
+    pub fn ticket(&self) -> Ticket {
+        let mut state = self.state.lock();
+        state.next += 1;
+        state.next
+    }
+
+    /// `allocate` 为 key 上的读分配 MSHR，已有该 key 的 MSHR 时合并
+    /// 没有空闲的 MSHR 时等待任意一个缺失完成
+    pub fn allocate(&self, key: &str, ticket: Ticket) -> Allocation {
+        let mut state = self.state.lock();
+        if let Some(tickets) = state.entries.get_mut(key) {
+            tickets.push(ticket);
+            return Allocation::Merged;
+        }
+        let stalled = state.entries.len() >= self.capacity;
+        while state.entries.len() >= self.capacity {
+            self.done.wait(&mut state);
+        }
+        state.entries.insert(key.to_string(), vec![ticket]);
+        self.occupancy.record_value(state.entries.len() as u64);
+        Allocation::Primary { stalled }
+    }
+
+    /// `complete` 不需要 MSHR 的读，例如命中缓存，立即完成
+    pub fn complete(&self, ticket: Ticket, value: T) {
+        self.state.lock().completed.push_back((ticket, value));
+        self.done.notify_all();
+    }
+
+    /// `fill` key 的数据返回，释放 MSHR，合并到其上的读一起完成
+    pub fn fill(&self, key: &str, value: T) {
+        let mut state = self.state.lock();
+        for ticket in state.entries.remove(key).unwrap_or_default() {
+            state.completed.push_back((ticket, value.clone()));
+        }
+        drop(state);
+        self.done.notify_all();
+    }
+
+    /// `pending` key 上是否有未完成的缺失
+    pub fn pending(&self, key: &str) -> bool {
+        self.state.lock().entries.contains_key(key)
+    }
+
+    /// `outstanding` 未完成的缺失数，合并的读不单独计数
+    pub fn outstanding(&self) -> usize {
+        self.state.lock().entries.len()
+    }
+
+    /// `settle` 等待 key 上的缺失完成，之后的读写不会与它同时修改缓存项
+    pub fn settle(&self, key: &str) {
+        let mut state = self.state.lock();
+        while state.entries.contains_key(key) {
+            self.done.wait(&mut state);
+        }
+    }
+
+    /// `settle_all` 等待所有缺失完成
+    pub fn settle_all(&self) {
+        let mut state = self.state.lock();
+        while !state.entries.is_empty() {
+            self.done.wait(&mut state);
+        }
+    }
+
+    /// `poll` 取走已完成的读，不等待
+    pub fn poll(&self) -> Vec<(Ticket, T)> {
+        self.state.lock().completed.drain(..).collect()
+    }
+
+    /// `wait` 取走最早完成的一个读，没有已完成的读时等待，没有未完成的读时返回 None
+    pub fn wait(&self) -> Option<(Ticket, T)> {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(c) = state.completed.pop_front() {
+                return Some(c);
+            }
+            if state.entries.is_empty() {
+                return None;
+            }
+            self.done.wait(&mut state);
+        }
+    }
+
+    /// `occupancy` 每次分配 MSHR 之后被占用的 MSHR 数
+    pub fn occupancy(&self) -> Histogram {
+        self.occupancy.snapshot()
+    }
+
+    pub fn reset_stats(&self) {
+        self.occupancy.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_mshrs() {
+        let mshrs: Mshrs<String> = Mshrs::new(2);
+        let (a, b, c) = (mshrs.ticket(), mshrs.ticket(), mshrs.ticket());
+        let primary = Allocation::Primary { stalled: false };
+        assert_eq!(mshrs.allocate("x", a), primary);
+        assert_eq!(mshrs.allocate("y", b), primary);
+        assert_eq!(mshrs.allocate("x", c), Allocation::Merged);
+        assert_eq!(mshrs.outstanding(), 2);
+
+        // 后发出的缺失先完成
+        mshrs.fill("y", "2".to_string());
+        assert_eq!(mshrs.poll(), [(b, "2".to_string())]);
+        mshrs.fill("x", "1".to_string());
+        assert_eq!(mshrs.wait(), Some((a, "1".to_string())));
+        assert_eq!(mshrs.wait(), Some((c, "1".to_string())));
+        assert_eq!(mshrs.wait(), None);
+        assert_eq!(mshrs.occupancy().max(), 2);
+    }
+
+    #[test]
+    fn test_stall() {
+        let mshrs: Arc<Mshrs<u64>> = Arc::new(Mshrs::new(1));
+        mshrs.allocate("x", mshrs.ticket());
+        let filler = mshrs.clone();
+        let handle = thread::spawn(move || filler.fill("x", 1));
+        let ticket = mshrs.ticket();
+        // 第一个缺失完成之后才能分配
+        assert!(matches!(
+            mshrs.allocate("y", ticket),
+            Allocation::Primary { .. }
+        ));
+        handle.join().unwrap();
+        assert!(!mshrs.pending("x"));
+        mshrs.fill("y", 2);
+        mshrs.settle_all();
+        assert_eq!(mshrs.poll().len(), 2);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::db::db::DbSession;
+use crate::hierarchy::hierarchy::GlobalDirectory;
+use crate::{CacheController, ConsistencyModel, ControllerConfig, Directory, DirectoryConfig};
+use parking_lot::RwLock;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Get,
+    Set(String),
+}
+
+/// `Step` 第 thread 个 controller 对 key 执行 op
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub thread: usize,
+    pub key: String,
+    pub op: Op,
+}
+
+/// `OracleConfig` 被检查的系统配置，每个 controller 使用相同的配置
+#[derive(Debug, Clone)]
+pub struct OracleConfig {
+    pub controllers: usize,
+    pub controller: ControllerConfig,
+    pub directory: DirectoryConfig,
+    /// 大于 0 时建立相同配置的多个集群目录，第 i 个 controller 属于第 i % clusters 个集群
+    pub clusters: usize,
+}
+
+impl Default for OracleConfig {
+    fn default() -> Self {
+        OracleConfig {
+            controllers: 4,
+            controller: ControllerConfig::default(),
+            directory: DirectoryConfig::default(),
+            clusters: 0,
+        }
+    }
+}
+
+/// `Mismatch` 读到的值与参照 HashMap 不一致
+/// step 为 None 时表示执行完所有步骤后的最终检查
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub step: Option<usize>,
+    pub thread: usize,
+    pub key: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.step {
+            Some(step) => write!(f, "step {}: ", step)?,
+            None => write!(f, "final check: ")?,
+        }
+        write!(
+            f,
+            "thread {} get key {:?}, expected {:?}, actual {:?}",
+            self.thread, self.key, self.expected, self.actual
+        )
+    }
+}
+
+/// `check` 在新建的 Directory 或集群目录上依次执行 steps，每次读到的值都应与参照 HashMap 一致，
+/// 执行完后每个 controller 对每个 key 的读也应一致
+/// 宽松一致性模型下每次写之后执行 fence，使写操作对其他线程可见
+pub fn check(config: &OracleConfig, steps: &[Step]) -> Result<(), Mismatch> {
+    let directories: Vec<Arc<RwLock<Directory>>> = if config.clusters == 0 {
+        vec![Arc::new(RwLock::new(Directory::with_db(
+            DbSession::temporary(),
+            config.directory.clone(),
+        )))]
+    } else {
+        let global = GlobalDirectory::new(DbSession::temporary());
+        (0..config.clusters)
+            .map(|_| global.cluster(config.directory.clone()))
+            .collect()
+    };
+    let mut controllers: Vec<CacheController<String>> = (0..config.controllers)
+        .map(|i| {
+            let directory = directories[i % directories.len()].clone();
+            CacheController::with_config(directory, config.controller.clone())
+        })
+        .collect();
+
+    let mut map: HashMap<String, String> = HashMap::new();
+    for (i, step) in steps.iter().enumerate() {
+        let ct = &mut controllers[step.thread];
+        match &step.op {
+            Op::Get => {
+                let actual = ct.get(step.key.clone());
+                let expected = map.get(&step.key).cloned().unwrap_or_default();
+                if actual != expected {
+                    return Err(Mismatch {
+                        step: Some(i),
+                        thread: step.thread,
+                        key: step.key.clone(),
+                        expected,
+                        actual,
+                    });
+                }
+            }
+            Op::Set(val) => {
+                ct.set(step.key.clone(), val.clone());
+                if config.controller.model != ConsistencyModel::SC {
+                    ct.fence();
+                }
+                map.insert(step.key.clone(), val.clone());
+            }
+        }
+    }
+
+    let keys: BTreeSet<&String> = steps.iter().map(|s| &s.key).collect();
+    for (thread, ct) in controllers.iter_mut().enumerate() {
+        for key in &keys {
+            let actual = ct.get((*key).clone());
+            let expected = map.get(*key).cloned().unwrap_or_default();
+            if actual != expected {
+                return Err(Mismatch {
+                    step: None,
+                    thread,
+                    key: (*key).clone(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,247 @@
+//! CacheController 的预取
+//!
+//! 每次读之后 `Prefetcher` 根据访问历史给出要预取的 key，controller 对其中不在缓存中的 key
+//! 向目录发出只要求 Shared 权限的读请求（GetS）。预取是非绑定的：装入的副本与普通副本一样
+//! 参与一致性，之后被失效或淘汰都不影响正确性，只是预取没有用上。
+//!
+//! - NextN：以数字结尾的 key 预取之后的 n 个 key
+//! - Stride：按 key 的前缀分别记录最近两次访问的数字之差，连续两次相同时沿该步长预取
+//! - Markov：记录每个 key 之后访问过的 key 及次数，预取次数最多的几个
+use crate::line::line::split;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// `Prefetcher` 根据读访问的历史选择要预取的 key
+pub trait Prefetcher: Send {
+    /// `access` 记录一次读，hit 为是否命中缓存，返回要预取的 key
+    fn access(&mut self, key: &str, hit: bool) -> Vec<String>;
+}
+
+/// `PrefetchConfig` ControllerConfig 中选择的预取器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchConfig {
+    /// 预取之后的 n 个 key
+    NextN(usize),
+    /// 步长确定后预取之后的 degree 个 key
+    Stride(usize),
+    /// 预取最常见的 width 个后继
+    Markov(usize),
+}
+
+impl PrefetchConfig {
+    pub fn build(&self) -> Box<dyn Prefetcher> {
+        match *self {
+            PrefetchConfig::NextN(n) => Box::new(NextN::new(n)),
+            PrefetchConfig::Stride(degree) => Box::new(Stride::new(degree)),
+            PrefetchConfig::Markov(width) => Box::new(Markov::new(width)),
+        }
+    }
+}
+
+impl fmt::Display for PrefetchConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefetchConfig::NextN(n) => write!(f, "next:{}", n),
+            PrefetchConfig::Stride(degree) => write!(f, "stride:{}", degree),
+            PrefetchConfig::Markov(width) => write!(f, "markov:{}", width),
+        }
+    }
+}
+
+impl FromStr for PrefetchConfig {
+    type Err = String;
+
+    /// `from_str` 解析 `next:2`、`stride:4`、`markov:1`，省略数字时为 1
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, n) = match s.trim().split_once(':') {
+            Some((name, n)) => (name, n.parse().map_err(|_| format!("bad degree {:?}", n))?),
+            None => (s.trim(), 1),
+        };
+        match name {
+            "next" => Ok(PrefetchConfig::NextN(n)),
+            "stride" => Ok(PrefetchConfig::Stride(n)),
+            "markov" => Ok(PrefetchConfig::Markov(n)),
+            _ => Err(format!("unknown prefetcher {:?}", s)),
+        }
+    }
+}
+
+/// `NextN` 顺序预取
+pub struct NextN {
+    n: usize,
+}
+
+impl NextN {
+    pub fn new(n: usize) -> NextN {
+        NextN { n }
+    }
+}
+
+impl Prefetcher for NextN {
+    fn access(&mut self, key: &str, _hit: bool) -> Vec<String> {
+        match split(key) {
+            None => Vec::new(),
+            Some((prefix, i)) => (1..=self.n as u64)
+                .map_while(|d| i.checked_add(d))
+                .map(|j| format!("{}{}", prefix, j))
+                .collect(),
+        }
+    }
+}
+
+/// `StrideEntry` 一个前缀最近的访问
+struct StrideEntry {
+    last: i64,
+    stride: i64,
+    confirmed: bool,
+}
+
+/// `Stride` 步长预取，按前缀区分访问流
+pub struct Stride {
+    degree: usize,
+    streams: HashMap<String, StrideEntry>,
+}
+
+impl Stride {
+    pub fn new(degree: usize) -> Stride {
+        Stride {
+            degree,
+            streams: HashMap::new(),
+        }
+    }
+}
+
+impl Prefetcher for Stride {
+    fn access(&mut self, key: &str, _hit: bool) -> Vec<String> {
+        let Some((prefix, i)) = split(key) else {
+            return Vec::new();
+        };
+        // 步长用 i64 表示，后缀超过 i64::MAX 的 key 不参与
+        let Ok(i) = i64::try_from(i) else {
+            return Vec::new();
+        };
+        let entry = self
+            .streams
+            .entry(prefix.to_string())
+            .or_insert(StrideEntry {
+                last: i,
+                stride: 0,
+                confirmed: false,
+            });
+        let stride = i - entry.last;
+        if stride == 0 {
+            return Vec::new();
+        }
+        entry.confirmed = stride == entry.stride;
+        entry.stride = stride;
+        entry.last = i;
+        if !entry.confirmed {
+            return Vec::new();
+        }
+        (1..=self.degree as i64)
+            .map_while(|d| stride.checked_mul(d).and_then(|s| i.checked_add(s)))
+            .take_while(|j| *j >= 0)
+            .map(|j| format!("{}{}", prefix, j))
+            .collect()
+    }
+}
+
+/// `Markov` 相关性预取，记录每个 key 的后继
+pub struct Markov {
+    width: usize,
+    successors: HashMap<String, HashMap<String, u64>>,
+    last: Option<String>,
+}
+
+impl Markov {
+    pub fn new(width: usize) -> Markov {
+        Markov {
+            width,
+            successors: HashMap::new(),
+            last: None,
+        }
+    }
+}
+
+impl Prefetcher for Markov {
+    fn access(&mut self, key: &str, _hit: bool) -> Vec<String> {
+        if let Some(last) = self.last.replace(key.to_string()) {
+            if last != key {
+                let next = self.successors.entry(last).or_default();
+                *next.entry(key.to_string()).or_default() += 1;
+            }
+        }
+        let Some(next) = self.successors.get(key) else {
+            return Vec::new();
+        };
+        // 次数相同时按 key 排序，结果不依赖 HashMap 的遍历顺序
+        let mut next: Vec<_> = next.iter().collect();
+        next.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        next.into_iter()
+            .take(self.width)
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_and_stride() {
+        let mut next = NextN::new(2);
+        assert_eq!(next.access("user7", false), ["user8", "user9"]);
+        assert!(next.access("user", false).is_empty());
+
+        let mut stride = Stride::new(2);
+        assert!(stride.access("a10", false).is_empty());
+        assert!(stride.access("a13", false).is_empty());
+        assert_eq!(stride.access("a16", false), ["a19", "a22"]);
+        // 其他前缀是另一个访问流，不打断 a 的步长
+        assert!(stride.access("b1", false).is_empty());
+        assert_eq!(stride.access("a19", true), ["a22", "a25"]);
+        // 反向的步长不预取负数
+        let mut down = Stride::new(3);
+        down.access("c5", false);
+        down.access("c3", false);
+        assert!(down.access("c1", false).is_empty());
+        assert_eq!(down.access("c7", false), Vec::<String>::new());
+        assert!(Stride::new(3).access("x", false).is_empty());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut next = NextN::new(2);
+        assert_eq!(
+            next.access("k18446744073709551614", false),
+            ["k18446744073709551615"]
+        );
+        assert!(next.access("k18446744073709551615", false).is_empty());
+
+        // 后缀超过 i64::MAX 的 key 不参与步长预取
+        let mut stride = Stride::new(2);
+        for key in ["k9223372036854775808", "k18446744073709551615"] {
+            assert!(stride.access(key, false).is_empty());
+        }
+        stride.access("k9223372036854775805", false);
+        stride.access("k9223372036854775806", false);
+        assert!(stride.access("k9223372036854775807", false).is_empty());
+    }
+
+    #[test]
+    fn test_markov() {
+        let mut markov = Markov::new(1);
+        for key in ["a", "b", "a", "c", "a", "b"] {
+            markov.access(key, false);
+        }
+        assert_eq!(markov.access("a", false), ["b"]);
+        assert_eq!(Markov::new(2).access("a", false), Vec::<String>::new());
+
+        assert_eq!("next:4".parse(), Ok(PrefetchConfig::NextN(4)));
+        assert_eq!("markov".parse(), Ok(PrefetchConfig::Markov(1)));
+        assert!("stream:2".parse::<PrefetchConfig>().is_err());
+        assert_eq!(PrefetchConfig::Stride(3).to_string(), "stride:3");
+    }
+}
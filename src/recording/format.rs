@@ -0,0 +1,180 @@
+//! 访问记录的二进制格式
+//!
+//! 文件以 `MESIACC1` 开头，之后为记录数和按时间排序的记录，整数均为 LEB128 变长编码：
+//! 线程号、操作（0 为 Get，1 为 Set）、与上一条记录的时间差（纳秒）、key、value。
+//! key 以编号表示，第一次出现的 key 编号等于已出现的 key 数，其后紧跟 key 的内容；
+//! 字符串编码为长度加 UTF-8 字节。
+use crate::recording::recording::{AccessOp, AccessRecord};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"MESIACC1";
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn write_varint(w: &mut impl Write, mut v: u64) -> io::Result<()> {
+    loop {
+        let b = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            return w.write_all(&[b]);
+        }
+        w.write_all(&[b | 0x80])?;
+    }
+}
+
+fn read_varint(r: &mut impl Read) -> io::Result<u64> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut b = [0u8];
+        r.read_exact(&mut b)?;
+        v |= ((b[0] & 0x7f) as u64) << shift;
+        if b[0] & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_varint(w, s.len() as u64)?;
+    w.write_all(s.as_bytes())
+}
+
+fn read_str(r: &mut impl Read) -> io::Result<String> {
+    let len = read_varint(r)?;
+    let mut buf = Vec::new();
+    r.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(buf).map_err(|e| invalid(e.to_string()))
+}
+
+/// `write` 写入 records，records 应按时间排序
+pub fn write(records: &[AccessRecord], w: &mut impl Write) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    write_varint(w, records.len() as u64)?;
+    let mut keys: HashMap<&str, u64> = HashMap::new();
+    let mut last = Duration::ZERO;
+    for r in records {
+        if r.timestamp < last {
+            return Err(invalid("records are not sorted by timestamp"));
+        }
+        write_varint(w, r.thread as u64)?;
+        w.write_all(&[match r.op {
+            AccessOp::Get => 0,
+            AccessOp::Set => 1,
+        }])?;
+        write_varint(w, (r.timestamp - last).as_nanos() as u64)?;
+        last = r.timestamp;
+
+        let next = keys.len() as u64;
+        let id = *keys.entry(r.key.as_str()).or_insert(next);
+        write_varint(w, id)?;
+        if id == next {
+            write_str(w, &r.key)?;
+        }
+        write_str(w, &r.value)?;
+    }
+    Ok(())
+}
+
+/// `read` 读出 `write` 写入的记录
+pub fn read(r: &mut impl Read) -> io::Result<Vec<AccessRecord>> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not an access trace"));
+    }
+    let n = read_varint(r)?;
+    // 长度来自文件，不据此预先分配
+    let mut records = Vec::new();
+    let mut keys: Vec<String> = Vec::new();
+    let mut timestamp = Duration::ZERO;
+    for _ in 0..n {
+        let thread = read_varint(r)? as usize;
+        let mut op = [0u8];
+        r.read_exact(&mut op)?;
+        let op = match op[0] {
+            0 => AccessOp::Get,
+            1 => AccessOp::Set,
+            b => return Err(invalid(format!("unknown op {}", b))),
+        };
+        timestamp += Duration::from_nanos(read_varint(r)?);
+        let id = read_varint(r)? as usize;
+        let key = match id.cmp(&keys.len()) {
+            std::cmp::Ordering::Less => keys[id].clone(),
+            std::cmp::Ordering::Equal => {
+                keys.push(read_str(r)?);
+                keys[id].clone()
+            }
+            std::cmp::Ordering::Greater => return Err(invalid(format!("bad key id {}", id))),
+        };
+        let value = read_str(r)?;
+        records.push(AccessRecord {
+            thread,
+            op,
+            key,
+            value,
+            timestamp,
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(thread: usize, op: AccessOp, key: &str, value: &str, us: u64) -> AccessRecord {
+        AccessRecord {
+            thread,
+            op,
+            key: key.to_string(),
+            value: value.to_string(),
+            timestamp: Duration::from_micros(us),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let records = vec![
+            record(0, AccessOp::Set, "x", "1", 0),
+            record(1, AccessOp::Get, "x", "1", 3),
+            record(300, AccessOp::Get, "y", "", 3),
+            record(1, AccessOp::Set, "x", "值", 1_000_000),
+        ];
+        let mut buf = Vec::new();
+        write(&records, &mut buf).unwrap();
+        assert_eq!(read(&mut buf.as_slice()).unwrap(), records);
+
+        // key 重复出现时只写编号：线程、操作、时间差、编号、value 共 6 字节
+        let mut once = Vec::new();
+        write(&records[..1], &mut once).unwrap();
+        let repeated = vec![records[0].clone(); 100];
+        let mut many = Vec::new();
+        write(&repeated, &mut many).unwrap();
+        assert_eq!(many.len(), once.len() + 99 * 6);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(read(&mut &b"NOTATRACE"[..]).is_err());
+
+        let records = vec![record(0, AccessOp::Set, "x", "1", 0)];
+        let mut buf = Vec::new();
+        write(&records, &mut buf).unwrap();
+        buf.pop();
+        assert!(read(&mut buf.as_slice()).is_err());
+
+        let unsorted = vec![
+            record(0, AccessOp::Get, "x", "", 5),
+            record(0, AccessOp::Get, "x", "", 1),
+        ];
+        assert!(write(&unsorted, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod format;
+#[allow(clippy::module_inception)]
+pub mod recording;
+pub mod replay;
@@ -0,0 +1,84 @@
+use crate::recording::format;
+use crate::ThreadID;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessOp {
+    Get,
+    Set,
+}
+
+/// `AccessRecord` 一次对 CacheController 的读写
+/// Get 的 value 为读到的值，Set 的 value 为写入的值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessRecord {
+    pub thread: ThreadID,
+    pub op: AccessOp,
+    pub key: String,
+    pub value: String,
+    /// 操作开始的时刻，相对于 Recorder 创建时刻
+    pub timestamp: Duration,
+}
+
+/// `Recorder` 记录多个 CacheController 收到的读写，见 `CacheController::attach_recorder`
+pub struct Recorder {
+    start: Instant,
+    records: Mutex<Vec<AccessRecord>>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new()
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            start: Instant::now(),
+            records: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn record(&self, record: AccessRecord) {
+        self.records.lock().push(record);
+    }
+
+    /// `records` 按时间排序的所有记录，同一时刻的记录保持记录顺序
+    pub fn records(&self) -> Vec<AccessRecord> {
+        let mut records = self.records.lock().clone();
+        records.sort_by_key(|r| r.timestamp);
+        records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `save` 以二进制格式写入 path，见 `format`
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        save(&self.records(), path)
+    }
+}
+
+pub fn save(records: &[AccessRecord], path: impl AsRef<Path>) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    format::write(records, &mut w)?;
+    w.flush()
+}
+
+pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<AccessRecord>> {
+    format::read(&mut io::BufReader::new(File::open(path)?))
+}
@@ -0,0 +1,121 @@
+use crate::recording::recording::{AccessOp, AccessRecord};
+use crate::{CacheController, ThreadID};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `ReplayReport` 重放的结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayReport {
+    pub ops: usize,
+    /// 读到的值与记录中不同的 Get，协议或交错顺序不同时可能出现
+    pub divergent_reads: usize,
+    pub elapsed: Duration,
+}
+
+/// `threads` 记录中出现的线程号，按第一次出现的顺序
+/// 第 i 个线程的操作由 controllers[i] 重放
+pub fn threads(records: &[AccessRecord]) -> Vec<ThreadID> {
+    let mut threads = Vec::new();
+    for r in records {
+        if !threads.contains(&r.thread) {
+            threads.push(r.thread);
+        }
+    }
+    threads
+}
+
+fn apply<T: Clone + ToString + Sync + From<String> + 'static>(
+    r: &AccessRecord,
+    ct: &mut CacheController<T>,
+) -> bool {
+    match r.op {
+        AccessOp::Get => ct.get(r.key.clone()).to_string() != r.value,
+        AccessOp::Set => {
+            ct.set(r.key.clone(), r.value.clone().into());
+            false
+        }
+    }
+}
+
+/// `replay` 在当前线程中按记录的时间顺序依次重放，交错顺序与记录完全相同
+pub fn replay<T: Clone + ToString + Sync + From<String> + 'static>(
+    records: &[AccessRecord],
+    controllers: &mut [CacheController<T>],
+) -> ReplayReport {
+    let threads = threads(records);
+    assert!(
+        controllers.len() >= threads.len(),
+        "trace has {} threads but only {} controllers",
+        threads.len(),
+        controllers.len()
+    );
+
+    let start = Instant::now();
+    let mut report = ReplayReport::default();
+    for r in records {
+        let i = threads.iter().position(|t| *t == r.thread).unwrap();
+        if apply(r, &mut controllers[i]) {
+            report.divergent_reads += 1;
+        }
+        report.ops += 1;
+    }
+    for ct in controllers.iter_mut() {
+        ct.fence();
+    }
+    report.elapsed = start.elapsed();
+    report
+}
+
+/// `replay_threads` 每个线程的操作在各自的线程中按原顺序重放，结束后归还 controllers
+/// pace 为 true 时按记录的时间间隔发出操作，否则尽快执行
+pub fn replay_threads<T: Clone + ToString + Sync + Send + From<String> + 'static>(
+    records: &[AccessRecord],
+    controllers: Vec<CacheController<T>>,
+    pace: bool,
+) -> (Vec<CacheController<T>>, ReplayReport) {
+    let threads = threads(records);
+    assert!(
+        controllers.len() >= threads.len(),
+        "trace has {} threads but only {} controllers",
+        threads.len(),
+        controllers.len()
+    );
+
+    let start = Instant::now();
+    let handles: Vec<_> = controllers
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut ct)| {
+            let stream: Vec<AccessRecord> = match threads.get(i) {
+                Some(t) => records.iter().filter(|r| r.thread == *t).cloned().collect(),
+                None => Vec::new(),
+            };
+            thread::spawn(move || {
+                let mut divergent = 0;
+                for r in &stream {
+                    if pace {
+                        if let Some(wait) = r.timestamp.checked_sub(start.elapsed()) {
+                            thread::sleep(wait);
+                        }
+                    }
+                    if apply(r, &mut ct) {
+                        divergent += 1;
+                    }
+                }
+                ct.fence();
+                (ct, stream.len(), divergent)
+            })
+        })
+        .collect();
+
+    let mut report = ReplayReport::default();
+    let mut controllers = Vec::with_capacity(handles.len());
+    for handle in handles {
+        let (ct, ops, divergent) = handle.join().unwrap();
+        controllers.push(ct);
+        report.ops += ops;
+        report.divergent_reads += divergent;
+    }
+    report.elapsed = start.elapsed();
+    (controllers, report)
+}
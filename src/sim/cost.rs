@@ -0,0 +1,34 @@
+use crate::sim::engine::Cycles;
+
+/// `CostModel` 模拟器中各类操作的代价
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostModel {
+    /// 访问本地缓存
+    pub l1_hit: Cycles,
+    /// 目录查找并更新目录项，期间目录不能处理其他请求
+    pub directory_lookup: Cycles,
+    /// 消息经过一条链路的延迟
+    pub network_hop: Cycles,
+    /// 每条链路每周期传输的字节数
+    pub link_bandwidth: u64,
+    /// 读写一次 db，同一时刻只能进行一次
+    pub dram_access: Cycles,
+    /// 请求、RemoteRead、RemoteWrite、Confirmed 等不带数据的消息的字节数
+    pub control_bytes: u64,
+    /// 带数据的消息的字节数
+    pub data_bytes: u64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            l1_hit: 4,
+            directory_lookup: 20,
+            network_hop: 30,
+            link_bandwidth: 16,
+            dram_access: 200,
+            control_bytes: 8,
+            data_bytes: 72,
+        }
+    }
+}
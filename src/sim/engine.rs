@@ -0,0 +1,105 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// `Cycles` 虚拟时间，单位为周期
+pub type Cycles = u64;
+
+struct Scheduled<E> {
+    at: Cycles,
+    seq: u64,
+    event: E,
+}
+
+impl<E> PartialEq for Scheduled<E> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl<E> Eq for Scheduled<E> {}
+
+impl<E> PartialOrd for Scheduled<E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E> Ord for Scheduled<E> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+/// `EventQueue` 按虚拟时间排序的事件队列，时间相同的事件按加入的顺序取出
+pub struct EventQueue<E> {
+    heap: BinaryHeap<Reverse<Scheduled<E>>>,
+    now: Cycles,
+    seq: u64,
+}
+
+impl<E> Default for EventQueue<E> {
+    fn default() -> Self {
+        EventQueue::new()
+    }
+}
+
+impl<E> EventQueue<E> {
+    pub fn new() -> EventQueue<E> {
+        EventQueue {
+            heap: BinaryHeap::new(),
+            now: 0,
+            seq: 0,
+        }
+    }
+
+    /// `now` 最近一次取出的事件的时间
+    pub fn now(&self) -> Cycles {
+        self.now
+    }
+
+    /// `schedule` 在 at 时刻触发 event，at 早于当前时间时视为当前时间
+    pub fn schedule(&mut self, at: Cycles, event: E) {
+        self.seq += 1;
+        self.heap.push(Reverse(Scheduled {
+            at: at.max(self.now),
+            seq: self.seq,
+            event,
+        }));
+    }
+
+    /// `pop` 取出最早的事件并把当前时间推进到该事件的时间
+    pub fn pop(&mut self) -> Option<(Cycles, E)> {
+        let Reverse(s) = self.heap.pop()?;
+        self.now = s.at;
+        Some((s.at, s.event))
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order() {
+        let mut q = EventQueue::new();
+        q.schedule(10, "b");
+        q.schedule(5, "a");
+        q.schedule(10, "c");
+        assert_eq!(q.pop(), Some((5, "a")));
+        // 早于当前时间的事件在当前时间触发
+        q.schedule(1, "d");
+        assert_eq!(q.pop(), Some((5, "d")));
+        assert_eq!(q.pop(), Some((10, "b")));
+        assert_eq!(q.pop(), Some((10, "c")));
+        assert_eq!(q.now(), 10);
+        assert!(q.pop().is_none() && q.is_empty());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod cost;
+pub mod engine;
+#[allow(clippy::module_inception)]
+pub mod sim;
@@ -0,0 +1,427 @@
+//! 虚拟时间的离散事件模拟
+//!
+//! 每个线程依次发出负载中的读写，事件队列总是推进虚拟时间最早的线程。
+//! 读写在发出时刻由真实的 `CacheController` 和 `Directory` 同步执行，
+//! 协议逻辑与多线程运行时相同；模拟器只根据这次读写期间目录记录的消息计算它的代价：
+//!
+//! - 不经过目录：`l1_hit`
+//! - 经过目录：请求经过拓扑到达 key 所在的目录分片，每个分片依次处理请求，
+//!   每个请求占用分片 `directory_lookup`；有共享者时并行发出 RemoteRead/RemoteWrite
+//!   并等待确认，回复再经过拓扑回到请求方
+//! - 读写 db（包括写回）：依次占用分片的 db 分区，每次 `dram_access`；命中 LLC 的读不访问 db
+//!
+//! 消息在每条链路上等待链路空闲，见 `Network::send`。
+//! db 不模拟延迟，结果只取决于负载、种子和代价模型，与运行的机器无关。
+use crate::db::db::DbSession;
+use crate::recording::recording::AccessOp;
+use crate::sim::cost::CostModel;
+use crate::sim::engine::{Cycles, EventQueue};
+use crate::stats::histogram::Histogram;
+use crate::stats::stats::{DirectoryStats, Stats};
+use crate::topology::network::{LinkStats, Network};
+use crate::topology::topology::{Link, Placement, Topology};
+use crate::trace::trace::{Side, TraceKind, Tracer};
+use crate::workload::workload::{Client, Workload};
+use crate::{
+    CacheController, ControllerConfig, Directory, DirectoryConfig, Event, Status, ThreadID,
+};
+use parking_lot::RwLock;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// `SimConfig` 模拟配置
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub threads: usize,
+    /// 每个线程执行的操作数
+    pub ops: usize,
+    pub seed: u64,
+    pub cost: CostModel,
+    /// 控制器和目录分片之间的互连，目录分片均匀地位于控制器之间
+    pub topology: Topology,
+    /// seed 为 None 时，线程 i 的 store buffer 使用种子 seed + i
+    pub controller: ControllerConfig,
+    pub directory: DirectoryConfig,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            threads: 4,
+            ops: 10_000,
+            seed: 0,
+            cost: CostModel::default(),
+            topology: Topology::Crossbar,
+            controller: ControllerConfig::default(),
+            directory: DirectoryConfig::default(),
+        }
+    }
+}
+
+/// `SimReport` 一次模拟的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimReport {
+    pub threads: usize,
+    pub ops: u64,
+    /// 操作分解出的读写次数，scan 和 read-modify-write 包含多次读写
+    pub accesses: u64,
+    /// 所有线程完成的时刻
+    pub cycles: Cycles,
+    /// 每次操作从发出到完成的周期数
+    pub latency: Histogram,
+    pub stats: Stats,
+    pub directory: DirectoryStats,
+    /// 线程与目录之间传递的字节数
+    pub network_bytes: u64,
+    /// db 读写的字节数
+    pub dram_bytes: u64,
+    pub topology: Topology,
+    /// 每条有过流量的链路
+    pub links: Vec<(Link, LinkStats)>,
+}
+
+impl SimReport {
+    /// `cycles_per_op` 每次操作的平均周期数
+    pub fn cycles_per_op(&self) -> f64 {
+        self.latency.mean()
+    }
+
+    /// `throughput` 所有线程每周期完成的操作数
+    pub fn throughput(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.ops as f64 / self.cycles as f64
+    }
+
+    /// `bandwidth` 平均每周期的网络字节数
+    pub fn bandwidth(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.network_bytes as f64 / self.cycles as f64
+    }
+
+    /// `busiest_link` 占用周期最多的链路
+    pub fn busiest_link(&self) -> Option<&(Link, LinkStats)> {
+        self.links.iter().max_by_key(|(_, stats)| stats.busy)
+    }
+
+    /// `dram_bandwidth` 平均每周期的 db 字节数
+    pub fn dram_bandwidth(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.dram_bytes as f64 / self.cycles as f64
+    }
+}
+
+impl fmt::Display for SimReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} threads, {} ops ({} accesses) in {} cycles",
+            self.threads, self.ops, self.accesses, self.cycles
+        )?;
+        writeln!(
+            f,
+            "cycles/op mean {:.1} p50 {} p99 {} max {}",
+            self.cycles_per_op(),
+            self.latency.percentile(50.0),
+            self.latency.percentile(99.0),
+            self.latency.max()
+        )?;
+        writeln!(f, "hit ratio {:.3}", self.stats.hit_ratio())?;
+        write!(
+            f,
+            "bandwidth network {:.3} B/cycle ({} B) dram {:.3} B/cycle ({} B)",
+            self.bandwidth(),
+            self.network_bytes,
+            self.dram_bandwidth(),
+            self.dram_bytes
+        )?;
+        if let Some(((from, to), stats)) = self.busiest_link() {
+            write!(
+                f,
+                "\n{} links {}, busiest {} -> {} utilization {:.3} ({} messages)",
+                self.topology,
+                self.links.len(),
+                from,
+                to,
+                stats.utilization(self.cycles),
+                stats.messages
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct SimThread {
+    ct: CacheController<String>,
+    client: Client,
+    pending: VecDeque<(AccessOp, String, String)>,
+    remaining: usize,
+    // 当前操作发出的时刻
+    started: Option<Cycles>,
+    fenced: bool,
+}
+
+/// `Timing` 根据目录记录的消息计算每次读写的完成时刻
+struct Timing {
+    cost: CostModel,
+    directory: Arc<RwLock<Directory>>,
+    tracer: Arc<Tracer>,
+    network: Network,
+    placement: Placement,
+    // 线程号所在的节点
+    nodes: HashMap<ThreadID, usize>,
+    // 每个目录分片和它的 db 分区空闲的时刻
+    directory_free: Vec<Cycles>,
+    memory_free: Vec<Cycles>,
+    // 下一次淘汰写回发往的分片
+    next_eviction: usize,
+    network_bytes: u64,
+    dram_bytes: u64,
+}
+
+impl Timing {
+    fn send(&mut self, at: Cycles, from: usize, to: usize, bytes: u64) -> Cycles {
+        self.network_bytes += bytes;
+        self.network.send(at, from, to, bytes)
+    }
+
+    /// `memory` 从 at 开始依次读写分片 home 的 db 分区 n 次，返回完成的时刻
+    fn memory(&mut self, home: usize, at: Cycles, n: u64) -> Cycles {
+        if n == 0 {
+            return at;
+        }
+        let start = at.max(self.memory_free[home]);
+        self.memory_free[home] = start + n * self.cost.dram_access;
+        self.dram_bytes += n * self.cost.data_bytes;
+        self.memory_free[home]
+    }
+
+    /// `execute` 在 now 时刻让 ct 执行 f，返回完成的时刻
+    /// 没有经过目录、也没有写回时，empty 为完成需要的周期数
+    fn execute(
+        &mut self,
+        now: Cycles,
+        ct: &mut CacheController<String>,
+        empty: Cycles,
+        f: impl FnOnce(&mut CacheController<String>),
+    ) -> Cycles {
+        let before = self.directory.read().stats();
+        f(ct);
+        let after = self.directory.read().stats();
+        let records = self.tracer.records();
+        self.tracer.clear();
+
+        let cost = self.cost.clone();
+        let node = self.nodes[&ct.thread_id];
+        let mut t = now + empty;
+        let mut write_backs = 0;
+        // 命中 LLC 的读不访问 db
+        let mut db_reads = after.db_reads - before.db_reads;
+        for tx in &records {
+            let elapsed = match tx.kind {
+                TraceKind::Transaction(elapsed) => elapsed,
+                _ => continue,
+            };
+            let read = matches!(tx.event, Event::RemoteRead(_));
+            let home = self.directory.read().home(&tx.key);
+            let slice = self.placement.slice(home);
+            let arrive = self.send(t, node, slice, cost.control_bytes);
+            let start = arrive.max(self.directory_free[home]);
+            self.directory_free[home] = start + cost.directory_lookup;
+
+            // 事务中发出的 RemoteRead/RemoteWrite 并行发出，等待全部确认
+            // 持有 Modified 的共享者在确认中带回数据并写回 db
+            let mut done = self.directory_free[home];
+            let sent = records.iter().filter(|r| {
+                r.side == Side::Directory
+                    && r.kind == TraceKind::Send
+                    && r.requester == Some(tx.thread_id)
+                    && r.ts >= tx.ts
+                    && r.ts <= tx.ts + elapsed
+            });
+            let mut confirmations = Vec::new();
+            for r in sent {
+                let target = self.nodes[&r.thread_id];
+                let modified = records.iter().any(|l| {
+                    l.side == Side::Listener
+                        && l.thread_id == r.thread_id
+                        && l.key == tx.key
+                        && l.before == Some(Status::Modified)
+                        && !matches!(l.event, Event::Update(_, _))
+                });
+                // 写更新的 Update 带着新值
+                let bytes = match r.event {
+                    Event::Update(_, _) => cost.data_bytes,
+                    _ => cost.control_bytes,
+                };
+                confirmations.push((target, modified, bytes));
+            }
+            let at = self.directory_free[home];
+            for (target, modified, bytes) in confirmations {
+                let arrive = self.send(at, slice, target, bytes);
+                let bytes = if modified {
+                    write_backs += 1;
+                    cost.data_bytes
+                } else {
+                    cost.control_bytes
+                };
+                let back = self.send(arrive, target, slice, bytes);
+                done = done.max(self.memory(home, back, modified as u64));
+            }
+            if read && db_reads > 0 {
+                db_reads -= 1;
+                done = self.memory(home, done, 1);
+            }
+            // 读请求的回复带数据，写请求的回复不带数据
+            let bytes = if read {
+                cost.data_bytes
+            } else {
+                cost.control_bytes
+            };
+            t = self.send(done, slice, node, bytes);
+        }
+
+        // 其余的写回是批量淘汰 Modified 缓存项，发出后不等待完成
+        // 不知道被淘汰的 key，轮流发往各分片
+        let evicted = (after.db_writes - before.db_writes).saturating_sub(write_backs);
+        for _ in 0..evicted {
+            let home = self.next_eviction;
+            self.next_eviction = (home + 1) % self.directory_free.len();
+            let arrive = self.send(t, node, self.placement.slice(home), cost.data_bytes);
+            self.memory(home, arrive, 1);
+        }
+        t
+    }
+}
+
+/// `simulate` 在新的 Directory 上以虚拟时间运行 workload
+pub fn simulate(workload: &Workload, config: &SimConfig) -> SimReport {
+    let db = DbSession::temporary().with_latency(Duration::ZERO);
+    // 由目录记录的消息得到每条消息的收发两端
+    let directory_config = DirectoryConfig {
+        trace: true,
+        ..config.directory.clone()
+    };
+    let directory = Arc::new(RwLock::new(Directory::with_db(db, directory_config)));
+    let tracer = directory.read().tracer().unwrap();
+    let mut threads: Vec<SimThread> = (0..config.threads)
+        .map(|t| {
+            let controller = ControllerConfig {
+                seed: Some(
+                    config
+                        .controller
+                        .seed
+                        .unwrap_or(config.seed.wrapping_add(t as u64)),
+                ),
+                ..config.controller.clone()
+            };
+            SimThread {
+                ct: CacheController::with_config(directory.clone(), controller),
+                client: workload.client(t, config.threads, config.seed),
+                pending: VecDeque::new(),
+                remaining: config.ops,
+                started: None,
+                fenced: false,
+            }
+        })
+        .collect();
+
+    let slices = directory.read().slices();
+    let placement = Placement::new(config.threads, slices);
+    let mut timing = Timing {
+        cost: config.cost.clone(),
+        directory: directory.clone(),
+        tracer,
+        network: Network::new(
+            config.topology,
+            placement.nodes(),
+            config.cost.network_hop,
+            config.cost.link_bandwidth,
+        ),
+        nodes: threads
+            .iter()
+            .enumerate()
+            .map(|(i, th)| (th.ct.thread_id, placement.controller(i)))
+            .collect(),
+        placement,
+        directory_free: vec![0; slices],
+        memory_free: vec![0; slices],
+        next_eviction: 0,
+        network_bytes: 0,
+        dram_bytes: 0,
+    };
+    let mut queue = EventQueue::new();
+    for t in 0..threads.len() {
+        queue.schedule(0, t);
+    }
+    let mut latency = Histogram::new();
+    let (mut ops, mut accesses, mut cycles) = (0, 0, 0);
+
+    while let Some((now, t)) = queue.pop() {
+        let th = &mut threads[t];
+        if th.pending.is_empty() {
+            if let Some(started) = th.started.take() {
+                latency.record(now - started);
+                th.client.complete();
+            }
+            if th.remaining == 0 {
+                if th.fenced {
+                    cycles = cycles.max(now);
+                    continue;
+                }
+                // 结束前写回 store buffer，写回的代价计入该线程，store buffer 为空时没有代价
+                th.fenced = true;
+                let done = timing.execute(now, &mut th.ct, 0, |ct| ct.fence());
+                queue.schedule(done, t);
+                continue;
+            }
+            th.remaining -= 1;
+            let op = th.client.next_operation();
+            th.pending = th.client.accesses(&op).into();
+            th.started = Some(now);
+            ops += 1;
+        }
+
+        let (access, key, value) = match th.pending.pop_front() {
+            Some(access) => access,
+            None => {
+                queue.schedule(now, t);
+                continue;
+            }
+        };
+        accesses += 1;
+        let done = timing.execute(now, &mut th.ct, config.cost.l1_hit, |ct| match access {
+            AccessOp::Get => {
+                ct.get(key);
+            }
+            AccessOp::Set => ct.set(key, value),
+        });
+        queue.schedule(done, t);
+    }
+
+    let mut stats = Stats::default();
+    for th in &threads {
+        stats.merge(&th.ct.stats());
+    }
+    let directory_stats = directory.read().stats();
+    SimReport {
+        threads: config.threads,
+        ops,
+        accesses,
+        cycles,
+        latency,
+        stats,
+        directory: directory_stats,
+        network_bytes: timing.network_bytes,
+        dram_bytes: timing.dram_bytes,
+        topology: config.topology,
+        links: timing.network.links(),
+    }
+}
@@ -0,0 +1,263 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// 每个 2 的幂区间划分为 HALF 个子桶，相对误差不超过 1/HALF
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const HALF: usize = SUB_BUCKETS / 2;
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * HALF + HALF;
+
+fn index(v: u64) -> usize {
+    if v < SUB_BUCKETS as u64 {
+        return v as usize;
+    }
+    let shift = 63 - v.leading_zeros() - (SUB_BUCKET_BITS - 1);
+    shift as usize * HALF + (v >> shift) as usize
+}
+
+/// `highest` 桶中的最大值
+fn highest(i: usize) -> u64 {
+    if i < SUB_BUCKETS {
+        return i as u64;
+    }
+    let shift = (i / HALF - 1) as u32;
+    let sub = (i % HALF + HALF) as u64;
+    ((sub + 1) << shift).wrapping_sub(1)
+}
+
+/// `Histogram` HDR 风格的对数-线性直方图，记录纳秒级延迟
+/// 每个值的相对误差不超过 1%，可以合并多个线程的直方图
+#[derive(Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    pub fn record(&mut self, v: u64) {
+        self.counts[index(v)] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(v);
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c += o;
+        }
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.count as f64
+    }
+
+    /// `percentile` 不小于 q（0 到 100）比例的记录值，结果不超过 max
+    pub fn percentile(&self, q: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((q / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (i, c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return highest(i).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// `buckets` 非空的桶，每项为桶中的最大值和记录数
+    pub fn buckets(&self) -> Vec<(u64, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c > 0)
+            .map(|(i, c)| (highest(i), *c))
+            .collect()
+    }
+}
+
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.count)
+            .field("min", &self.min())
+            .field("max", &self.max)
+            .field("p50", &self.percentile(50.0))
+            .finish()
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let us = |ns: u64| Duration::from_nanos(ns).as_secs_f64() * 1e6;
+        write!(
+            f,
+            "n {:<8} p50 {:>9.1}us p90 {:>9.1}us p99 {:>9.1}us p999 {:>9.1}us max {:>9.1}us",
+            self.count,
+            us(self.percentile(50.0)),
+            us(self.percentile(90.0)),
+            us(self.percentile(99.0)),
+            us(self.percentile(99.9)),
+            us(self.max),
+        )
+    }
+}
+
+/// `AtomicHistogram` 可以被多个线程同时记录的 Histogram
+pub(crate) struct AtomicHistogram {
+    counts: Box<[AtomicU64]>,
+    sum: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        AtomicHistogram {
+            counts: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl AtomicHistogram {
+    pub fn record(&self, d: Duration) {
+        self.record_value(d.as_nanos().min(u64::MAX as u128) as u64);
+    }
+
+    pub fn record_value(&self, v: u64) {
+        self.counts[index(v)].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(v, Ordering::Relaxed);
+        self.min.fetch_min(v, Ordering::Relaxed);
+        self.max.fetch_max(v, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Histogram {
+        let counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+        Histogram {
+            count: counts.iter().sum(),
+            counts,
+            sum: self.sum.load(Ordering::Relaxed),
+            min: self.min.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        for c in self.counts.iter() {
+            c.store(0, Ordering::Relaxed);
+        }
+        self.sum.store(0, Ordering::Relaxed);
+        self.min.store(u64::MAX, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index() {
+        for v in [0, 1, 127, 128, 129, 255, 256, 1000, 123_456_789, u64::MAX] {
+            let i = index(v);
+            assert!(i < BUCKETS);
+            assert!(highest(i) >= v);
+            // 相对误差不超过 1/HALF
+            assert!((highest(i) - v) as f64 <= v as f64 / HALF as f64);
+            if i > 0 {
+                assert!(highest(i - 1) < v);
+            }
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let mut h = Histogram::new();
+        for v in 1..=1000 {
+            h.record(v * 1000);
+        }
+        assert_eq!(h.count(), 1000);
+        assert_eq!(h.min(), 1000);
+        assert_eq!(h.max(), 1_000_000);
+        for (q, expect) in [(50.0, 500_000), (90.0, 900_000), (99.0, 990_000)] {
+            let p = h.percentile(q);
+            assert!(
+                p >= expect && p as f64 <= expect as f64 * 1.01,
+                "{} {}",
+                q,
+                p
+            );
+        }
+        assert_eq!(h.percentile(100.0), 1_000_000);
+
+        let mut merged = Histogram::new();
+        merged.merge(&h);
+        merged.merge(&h);
+        assert_eq!(merged.count(), 2000);
+        assert_eq!(merged.percentile(50.0), h.percentile(50.0));
+    }
+
+    #[test]
+    fn test_atomic() {
+        let h = AtomicHistogram::default();
+        h.record(Duration::from_micros(3));
+        h.record(Duration::from_micros(5));
+        let s = h.snapshot();
+        assert_eq!((s.count(), s.min(), s.max()), (2, 3000, 5000));
+        h.reset();
+        assert_eq!(h.snapshot(), Histogram::new());
+    }
+}
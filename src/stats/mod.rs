@@ -0,0 +1,3 @@
+pub mod histogram;
+#[allow(clippy::module_inception)]
+pub mod stats;
@@ -0,0 +1,537 @@
+use crate::stats::histogram::{AtomicHistogram, Histogram};
+use crate::Status;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// `Stats` 一个 CacheController 的统计快照
+/// 状态下标见 `Status::index`，不在缓存中的项视为 Invalid
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub read_hits: u64,
+    pub read_misses: u64,
+    pub write_hits: u64,
+    pub write_misses: u64,
+    /// 持有 Shared 副本时的写操作，需要目录使其他副本失效
+    pub upgrades: u64,
+    /// 监听线程收到、并使一个有效缓存项失效的 RemoteWrite
+    pub invalidations_received: u64,
+    /// 本线程的写操作使目录发出的 RemoteWrite
+    pub invalidations_sent: u64,
+    /// 收到的 RemoteWrite 中，被写的 slot 不在本线程持有的 slot 中的次数，即伪共享
+    pub false_sharing_invalidations: u64,
+    /// Modified 的缓存项写回 db 的次数，包括被淘汰和被其他线程读写时
+    pub write_backs: u64,
+    /// 写更新协议下本线程的写操作使目录发出的 Update
+    pub updates_sent: u64,
+    /// 监听线程收到、并更新了一个有效缓存项的 Update
+    pub updates_received: u64,
+    /// 租约协议下读到租约已过期的副本，自失效后重新向目录取得租约
+    pub lease_expirations: u64,
+    /// 预取发出的读请求，已在缓存中的 key 不预取
+    pub prefetches: u64,
+    /// 预取装入、并在失效或淘汰之前被读命中的项
+    pub prefetch_hits: u64,
+    /// 预取请求使目录发出的 RemoteRead 和 RemoteWrite
+    pub prefetch_messages: u64,
+    /// 非阻塞读合并到同一个 key 上未完成的缺失，不再请求目录
+    pub mshr_merges: u64,
+    /// 非阻塞读缺失时所有 MSHR 都被占用，等待其中一个完成
+    pub mshr_stalls: u64,
+    /// 读写命中 victim buffer，该项移回缓存，不需要经过目录
+    pub victim_hits: u64,
+    /// 发现自己被目录隔离后丢弃所有副本、重新加入广播的次数
+    pub rejoins: u64,
+    /// 按淘汰时的状态计数
+    pub evictions: [u64; 4],
+    /// transitions[from][to] 状态转换次数，不计状态不变的操作
+    pub transitions: [[u64; 4]; 4],
+}
+
+impl Stats {
+    pub fn hits(&self) -> u64 {
+        self.read_hits + self.write_hits
+    }
+
+    /// `misses` 需要经过目录的操作，包括 upgrade
+    pub fn misses(&self) -> u64 {
+        self.read_misses + self.write_misses + self.upgrades
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits() + self.misses();
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits() as f64 / total as f64
+    }
+
+    /// `prefetch_accuracy` 预取的项中被用上的比例
+    pub fn prefetch_accuracy(&self) -> f64 {
+        if self.prefetches == 0 {
+            return 0.0;
+        }
+        self.prefetch_hits as f64 / self.prefetches as f64
+    }
+
+    /// `prefetch_coverage` 没有预取时会读缺失的读中，由预取命中的比例
+    pub fn prefetch_coverage(&self) -> f64 {
+        let total = self.prefetch_hits + self.read_misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.prefetch_hits as f64 / total as f64
+    }
+
+    pub fn evicted(&self, status: &Status) -> u64 {
+        self.evictions[status.index()]
+    }
+
+    pub fn transition(&self, from: &Status, to: &Status) -> u64 {
+        self.transitions[from.index()][to.index()]
+    }
+
+    /// `merge` 累加另一个快照，用于汇总多个线程的统计
+    pub fn merge(&mut self, other: &Stats) {
+        self.read_hits += other.read_hits;
+        self.read_misses += other.read_misses;
+        self.write_hits += other.write_hits;
+        self.write_misses += other.write_misses;
+        self.upgrades += other.upgrades;
+        self.invalidations_received += other.invalidations_received;
+        self.invalidations_sent += other.invalidations_sent;
+        self.false_sharing_invalidations += other.false_sharing_invalidations;
+        self.write_backs += other.write_backs;
+        self.updates_sent += other.updates_sent;
+        self.updates_received += other.updates_received;
+        self.lease_expirations += other.lease_expirations;
+        self.prefetches += other.prefetches;
+        self.prefetch_hits += other.prefetch_hits;
+        self.prefetch_messages += other.prefetch_messages;
+        self.mshr_merges += other.mshr_merges;
+        self.mshr_stalls += other.mshr_stalls;
+        self.victim_hits += other.victim_hits;
+        self.rejoins += other.rejoins;
+        for i in 0..4 {
+            self.evictions[i] += other.evictions[i];
+            for j in 0..4 {
+                self.transitions[i][j] += other.transitions[i][j];
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "read  hits {:<10} misses {}",
+            self.read_hits, self.read_misses
+        )?;
+        writeln!(
+            f,
+            "write hits {:<10} misses {:<10} upgrades {}",
+            self.write_hits, self.write_misses, self.upgrades
+        )?;
+        writeln!(
+            f,
+            "hit ratio {:.4}, victim hits {}",
+            self.hit_ratio(),
+            self.victim_hits
+        )?;
+        writeln!(
+            f,
+            "invalidations received {} (false sharing {}) sent {}, write-backs {}",
+            self.invalidations_received,
+            self.false_sharing_invalidations,
+            self.invalidations_sent,
+            self.write_backs
+        )?;
+        writeln!(
+            f,
+            "updates received {} sent {}, lease expirations {}",
+            self.updates_received, self.updates_sent, self.lease_expirations
+        )?;
+        if self.prefetches > 0 {
+            writeln!(
+                f,
+                "prefetches {} hits {} accuracy {:.4} coverage {:.4} messages {}",
+                self.prefetches,
+                self.prefetch_hits,
+                self.prefetch_accuracy(),
+                self.prefetch_coverage(),
+                self.prefetch_messages
+            )?;
+        }
+        if self.mshr_merges + self.mshr_stalls > 0 {
+            writeln!(
+                f,
+                "mshr merges {} stalls {}",
+                self.mshr_merges, self.mshr_stalls
+            )?;
+        }
+        write!(f, "evictions")?;
+        for s in Status::ALL.iter() {
+            write!(f, " {:?} {}", s, self.evicted(s))?;
+        }
+        writeln!(f)?;
+        write!(f, "{:<10}", "from\\to")?;
+        for s in Status::ALL.iter() {
+            write!(f, "{:>10}", format!("{:?}", s))?;
+        }
+        for from in Status::ALL.iter() {
+            writeln!(f)?;
+            write!(f, "{:<10}", format!("{:?}", from))?;
+            for to in Status::ALL.iter() {
+                write!(f, "{:>10}", self.transition(from, to))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `inc` 计数器加一
+pub(crate) fn inc(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// `ControllerStats` CacheController 与其监听线程共享的计数器
+#[derive(Debug, Default)]
+pub(crate) struct ControllerStats {
+    pub read_hits: AtomicU64,
+    pub read_misses: AtomicU64,
+    pub write_hits: AtomicU64,
+    pub write_misses: AtomicU64,
+    pub upgrades: AtomicU64,
+    pub invalidations_received: AtomicU64,
+    pub invalidations_sent: AtomicU64,
+    pub false_sharing_invalidations: AtomicU64,
+    pub write_backs: AtomicU64,
+    pub updates_sent: AtomicU64,
+    pub updates_received: AtomicU64,
+    pub lease_expirations: AtomicU64,
+    pub prefetches: AtomicU64,
+    pub prefetch_hits: AtomicU64,
+    pub prefetch_messages: AtomicU64,
+    pub mshr_merges: AtomicU64,
+    pub mshr_stalls: AtomicU64,
+    pub victim_hits: AtomicU64,
+    pub rejoins: AtomicU64,
+    evictions: [AtomicU64; 4],
+    transitions: [[AtomicU64; 4]; 4],
+}
+
+impl ControllerStats {
+    pub fn transit(&self, from: &Status, to: &Status) {
+        if from != to {
+            inc(&self.transitions[from.index()][to.index()]);
+        }
+    }
+
+    /// `evict` 记录一次淘汰，Modified 的缓存项同时记一次写回
+    pub fn evict(&self, status: &Status) {
+        inc(&self.evictions[status.index()]);
+        if *status == Status::Modified {
+            inc(&self.write_backs);
+        }
+        self.transit(status, &Status::Invalid);
+    }
+
+    pub fn snapshot(&self) -> Stats {
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        Stats {
+            read_hits: load(&self.read_hits),
+            read_misses: load(&self.read_misses),
+            write_hits: load(&self.write_hits),
+            write_misses: load(&self.write_misses),
+            upgrades: load(&self.upgrades),
+            invalidations_received: load(&self.invalidations_received),
+            invalidations_sent: load(&self.invalidations_sent),
+            false_sharing_invalidations: load(&self.false_sharing_invalidations),
+            write_backs: load(&self.write_backs),
+            updates_sent: load(&self.updates_sent),
+            updates_received: load(&self.updates_received),
+            lease_expirations: load(&self.lease_expirations),
+            prefetches: load(&self.prefetches),
+            prefetch_hits: load(&self.prefetch_hits),
+            prefetch_messages: load(&self.prefetch_messages),
+            mshr_merges: load(&self.mshr_merges),
+            mshr_stalls: load(&self.mshr_stalls),
+            victim_hits: load(&self.victim_hits),
+            rejoins: load(&self.rejoins),
+            evictions: self.evictions.each_ref().map(load),
+            transitions: self.transitions.each_ref().map(|r| r.each_ref().map(load)),
+        }
+    }
+
+    pub fn reset(&self) {
+        let counters = [
+            &self.read_hits,
+            &self.read_misses,
+            &self.write_hits,
+            &self.write_misses,
+            &self.upgrades,
+            &self.invalidations_received,
+            &self.invalidations_sent,
+            &self.false_sharing_invalidations,
+            &self.write_backs,
+            &self.updates_sent,
+            &self.updates_received,
+            &self.lease_expirations,
+            &self.prefetches,
+            &self.prefetch_hits,
+            &self.prefetch_messages,
+            &self.mshr_merges,
+            &self.mshr_stalls,
+            &self.victim_hits,
+            &self.rejoins,
+        ];
+        counters
+            .into_iter()
+            .chain(self.evictions.iter())
+            .chain(self.transitions.iter().flatten())
+            .for_each(|c| c.store(0, Ordering::Relaxed));
+    }
+}
+
+/// `DirectoryStats` Directory 的统计快照，按消息类型计数
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryStats {
+    /// 处理的读、写请求
+    pub read_requests: u64,
+    pub write_requests: u64,
+    /// 发出的 RemoteRead、RemoteWrite，不含重传
+    pub remote_reads: u64,
+    pub remote_writes: u64,
+    /// 写更新协议下发出的 Update，不含重传
+    pub updates: u64,
+    /// 租约协议下越过其他线程未到期的租约的写，每次代替一轮失效广播
+    pub lease_jumps: u64,
+    /// 收到的与请求序号匹配的 Confirmed
+    pub confirmations: u64,
+    /// 序号不匹配而被丢弃的过期、重复回复
+    pub discarded: u64,
+    pub retransmissions: u64,
+    /// 超过最大重传次数的请求
+    pub timeouts: u64,
+    pub db_reads: u64,
+    pub db_writes: u64,
+}
+
+impl DirectoryStats {
+    /// `messages` 目录发出和收到的消息总数
+    pub fn messages(&self) -> u64 {
+        self.remote_reads
+            + self.remote_writes
+            + self.updates
+            + self.retransmissions
+            + self.confirmations
+            + self.discarded
+    }
+}
+
+impl fmt::Display for DirectoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "requests read {} write {}",
+            self.read_requests, self.write_requests
+        )?;
+        writeln!(
+            f,
+            "sent RemoteRead {} RemoteWrite {} Update {} retransmissions {}",
+            self.remote_reads, self.remote_writes, self.updates, self.retransmissions
+        )?;
+        if self.lease_jumps > 0 {
+            writeln!(f, "lease jumps {}", self.lease_jumps)?;
+        }
+        writeln!(
+            f,
+            "received Confirmed {} discarded {}, timeouts {}",
+            self.confirmations, self.discarded, self.timeouts
+        )?;
+        write!(f, "db reads {} writes {}", self.db_reads, self.db_writes)
+    }
+}
+
+/// `DirectoryCounters` Directory 内部的计数器
+#[derive(Debug, Default)]
+pub(crate) struct DirectoryCounters {
+    pub read_requests: AtomicU64,
+    pub write_requests: AtomicU64,
+    pub remote_reads: AtomicU64,
+    pub remote_writes: AtomicU64,
+    pub updates: AtomicU64,
+    pub lease_jumps: AtomicU64,
+    pub confirmations: AtomicU64,
+    pub discarded: AtomicU64,
+    pub retransmissions: AtomicU64,
+    pub timeouts: AtomicU64,
+    pub db_reads: AtomicU64,
+    pub db_writes: AtomicU64,
+}
+
+impl DirectoryCounters {
+    fn counters(&self) -> [&AtomicU64; 12] {
+        [
+            &self.read_requests,
+            &self.write_requests,
+            &self.remote_reads,
+            &self.remote_writes,
+            &self.updates,
+            &self.lease_jumps,
+            &self.confirmations,
+            &self.discarded,
+            &self.retransmissions,
+            &self.timeouts,
+            &self.db_reads,
+            &self.db_writes,
+        ]
+    }
+
+    pub fn snapshot(&self) -> DirectoryStats {
+        let [read_requests, write_requests, remote_reads, remote_writes, updates, lease_jumps, confirmations, discarded, retransmissions, timeouts, db_reads, db_writes] =
+            self.counters().map(|c| c.load(Ordering::Relaxed));
+        DirectoryStats {
+            read_requests,
+            write_requests,
+            remote_reads,
+            remote_writes,
+            updates,
+            lease_jumps,
+            confirmations,
+            discarded,
+            retransmissions,
+            timeouts,
+            db_reads,
+            db_writes,
+        }
+    }
+
+    pub fn reset(&self) {
+        for c in self.counters() {
+            c.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// `Latencies` CacheController 的 get/set 延迟，按访问类型划分
+/// TSO、PSO 下只进入 store buffer 的 set 记为 hit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Latencies {
+    pub hit: Histogram,
+    pub miss: Histogram,
+    /// 持有 Shared 副本时的写操作
+    pub upgrade: Histogram,
+}
+
+impl Latencies {
+    pub fn merge(&mut self, other: &Latencies) {
+        self.hit.merge(&other.hit);
+        self.miss.merge(&other.miss);
+        self.upgrade.merge(&other.upgrade);
+    }
+}
+
+impl fmt::Display for Latencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "hit     {}", self.hit)?;
+        writeln!(f, "miss    {}", self.miss)?;
+        write!(f, "upgrade {}", self.upgrade)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct LatencyRecorder {
+    pub hit: AtomicHistogram,
+    pub miss: AtomicHistogram,
+    pub upgrade: AtomicHistogram,
+}
+
+impl LatencyRecorder {
+    pub fn snapshot(&self) -> Latencies {
+        Latencies {
+            hit: self.hit.snapshot(),
+            miss: self.miss.snapshot(),
+            upgrade: self.upgrade.snapshot(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.hit.reset();
+        self.miss.reset();
+        self.upgrade.reset();
+    }
+}
+
+/// `DirectoryLatencies` Directory 每次广播从发出请求到收齐确认的延迟
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryLatencies {
+    /// RemoteRead，使其他线程的副本降级为 Shared
+    pub remote_read: Histogram,
+    /// RemoteWrite，使其他线程的副本失效
+    pub remote_invalidate: Histogram,
+    /// Update，把新值推送给其他线程的副本
+    pub remote_update: Histogram,
+}
+
+impl fmt::Display for DirectoryLatencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "remote read       {}", self.remote_read)?;
+        writeln!(f, "remote invalidate {}", self.remote_invalidate)?;
+        write!(f, "remote update     {}", self.remote_update)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct DirectoryLatencyRecorder {
+    pub remote_read: AtomicHistogram,
+    pub remote_invalidate: AtomicHistogram,
+    pub remote_update: AtomicHistogram,
+}
+
+impl DirectoryLatencyRecorder {
+    pub fn snapshot(&self) -> DirectoryLatencies {
+        DirectoryLatencies {
+            remote_read: self.remote_read.snapshot(),
+            remote_invalidate: self.remote_invalidate.snapshot(),
+            remote_update: self.remote_update.snapshot(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.remote_read.reset();
+        self.remote_invalidate.reset();
+        self.remote_update.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_and_reset() {
+        let stats = ControllerStats::default();
+        inc(&stats.read_hits);
+        stats.transit(&Status::Invalid, &Status::Exclusive);
+        stats.transit(&Status::Exclusive, &Status::Modified);
+        stats.transit(&Status::Modified, &Status::Modified);
+        stats.evict(&Status::Modified);
+
+        let s = stats.snapshot();
+        assert_eq!(s.read_hits, 1);
+        assert_eq!(s.transition(&Status::Invalid, &Status::Exclusive), 1);
+        assert_eq!(s.transition(&Status::Exclusive, &Status::Modified), 1);
+        assert_eq!(s.transition(&Status::Modified, &Status::Modified), 0);
+        assert_eq!(s.transition(&Status::Modified, &Status::Invalid), 1);
+        assert_eq!(s.evicted(&Status::Modified), 1);
+        assert_eq!(s.write_backs, 1);
+
+        let mut total = s.clone();
+        total.merge(&s);
+        assert_eq!(total.transition(&Status::Invalid, &Status::Exclusive), 2);
+
+        stats.reset();
+        assert_eq!(stats.snapshot(), Stats::default());
+    }
+}
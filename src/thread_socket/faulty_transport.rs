@@ -0,0 +1,244 @@
+use crate::thread_socket::thread_socket::{ThreadSocket, Transport};
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// `FaultConfig` 故障注入配置，各项均为每条消息触发该故障的概率
+#[derive(Debug, Clone)]
+pub struct FaultConfig {
+    /// 发送前延迟 `[0, max_delay)`
+    pub delay: f64,
+    pub max_delay: Duration,
+    /// 扣留该消息，让下一条消息先到达，模拟不同虚拟网络之间的乱序
+    pub reorder: f64,
+    /// 消息被发送两次
+    pub duplicate: f64,
+    /// 消息丢失
+    pub drop: f64,
+    /// 随机数种子，None 时每次运行结果不同
+    pub seed: Option<u64>,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        FaultConfig {
+            delay: 0.0,
+            max_delay: Duration::from_millis(1),
+            reorder: 0.0,
+            duplicate: 0.0,
+            drop: 0.0,
+            seed: None,
+        }
+    }
+}
+
+impl FaultConfig {
+    /// `is_reliable` 不注入任何故障
+    pub fn is_reliable(&self) -> bool {
+        self.delay == 0.0 && self.reorder == 0.0 && self.duplicate == 0.0 && self.drop == 0.0
+    }
+}
+
+/// `FaultCount` 已注入的故障次数
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultCount {
+    pub delayed: u64,
+    pub reordered: u64,
+    pub duplicated: u64,
+    pub dropped: u64,
+}
+
+/// `FaultCounters` 已注入的故障计数，可以由多个 FaultyTransport 共享
+#[derive(Debug, Default)]
+pub struct FaultCounters {
+    delayed: AtomicU64,
+    reordered: AtomicU64,
+    duplicated: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl FaultCounters {
+    fn counters(&self) -> [&AtomicU64; 4] {
+        [
+            &self.delayed,
+            &self.reordered,
+            &self.duplicated,
+            &self.dropped,
+        ]
+    }
+
+    pub fn snapshot(&self) -> FaultCount {
+        let [delayed, reordered, duplicated, dropped] =
+            self.counters().map(|c| c.load(Ordering::Relaxed));
+        FaultCount {
+            delayed,
+            reordered,
+            duplicated,
+            dropped,
+        }
+    }
+
+    pub fn reset(&self) {
+        for c in self.counters() {
+            c.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// `FaultyTransport` 包装 `ThreadSocket`，在发送端按 `FaultConfig` 注入延迟、乱序、重复和丢包
+pub struct FaultyTransport<T> {
+    inner: ThreadSocket<T>,
+    config: FaultConfig,
+    rng: Mutex<StdRng>,
+    // 被扣留、等待下一条消息发出后再发送的消息
+    held: Mutex<Option<T>>,
+    counters: Arc<FaultCounters>,
+}
+
+impl<T: Clone> FaultyTransport<T> {
+    /// `new` stream 用于区分同一个种子下不同的 socket
+    pub fn new(inner: ThreadSocket<T>, config: FaultConfig, stream: u64) -> FaultyTransport<T> {
+        for p in [config.delay, config.reorder, config.duplicate, config.drop] {
+            assert!((0.0..=1.0).contains(&p), "fault probability out of range");
+        }
+        let rng = match config.seed {
+            None => StdRng::from_entropy(),
+            Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(stream)),
+        };
+
+        FaultyTransport {
+            inner,
+            config,
+            rng: Mutex::new(rng),
+            held: Mutex::new(None),
+            counters: Arc::new(FaultCounters::default()),
+        }
+    }
+
+    /// `with_counters` 把注入的故障计入 counters，例如同一个 Directory 的所有 socket 共用一组计数
+    pub fn with_counters(self, counters: Arc<FaultCounters>) -> FaultyTransport<T> {
+        FaultyTransport { counters, ..self }
+    }
+
+    pub fn fault_count(&self) -> FaultCount {
+        self.counters.snapshot()
+    }
+
+    fn send(&self, data: T) {
+        let (drop, delay, reorder, duplicate) = {
+            let mut rng = self.rng.lock();
+            let delay = if rng.gen_bool(self.config.delay) && !self.config.max_delay.is_zero() {
+                Some(rng.gen_range(Duration::ZERO..self.config.max_delay))
+            } else {
+                None
+            };
+            (
+                rng.gen_bool(self.config.drop),
+                delay,
+                rng.gen_bool(self.config.reorder),
+                rng.gen_bool(self.config.duplicate),
+            )
+        };
+
+        if drop {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if let Some(d) = delay {
+            self.counters.delayed.fetch_add(1, Ordering::Relaxed);
+            thread::sleep(d);
+        }
+        if reorder {
+            self.counters.reordered.fetch_add(1, Ordering::Relaxed);
+            let prev = self.held.lock().replace(data);
+            if let Some(prev) = prev {
+                self.inner.send(prev);
+            }
+            return;
+        }
+
+        if duplicate {
+            self.counters.duplicated.fetch_add(1, Ordering::Relaxed);
+            self.inner.send(data.clone());
+        }
+        self.inner.send(data);
+
+        // 被扣留的消息在当前消息之后到达
+        let held = self.held.lock().take();
+        if let Some(held) = held {
+            self.inner.send(held);
+        }
+    }
+}
+
+impl<T: Clone + Send> Transport<T> for FaultyTransport<T> {
+    fn send(&self, data: T) {
+        FaultyTransport::send(self, data)
+    }
+
+    fn receive(&self) -> Option<T> {
+        Transport::receive(&self.inner)
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> Option<T> {
+        self.inner.receive_timeout(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::thread_socket::faulty_transport::*;
+    use crate::thread_socket::thread_socket::new_socket;
+
+    #[test]
+    fn test_reorder() {
+        let (sa, sb) = new_socket();
+        let config = FaultConfig {
+            reorder: 1.0,
+            ..FaultConfig::default()
+        };
+        let sa = FaultyTransport::new(sa, config, 0);
+
+        // 每条消息都会被扣留，直到下一条消息发出
+        sa.send(1);
+        assert_eq!(sb.receive_timeout(Duration::from_millis(10)), None);
+        sa.send(2);
+        sa.send(3);
+        assert_eq!(sb.receive(), 1);
+        assert_eq!(sb.receive(), 2);
+        assert_eq!(sa.fault_count().reordered, 3);
+    }
+
+    #[test]
+    fn test_duplicate_and_drop() {
+        let (sa, sb) = new_socket();
+        let sa = FaultyTransport::new(
+            sa,
+            FaultConfig {
+                duplicate: 1.0,
+                ..FaultConfig::default()
+            },
+            0,
+        );
+        sa.send("msg");
+        assert_eq!(sb.receive(), "msg");
+        assert_eq!(sb.receive(), "msg");
+
+        let (sa, sb) = new_socket();
+        let sa = FaultyTransport::new(
+            sa,
+            FaultConfig {
+                drop: 1.0,
+                ..FaultConfig::default()
+            },
+            0,
+        );
+        sa.send("msg");
+        assert_eq!(sb.receive_timeout(Duration::from_millis(10)), None);
+        assert_eq!(sa.fault_count().dropped, 1);
+    }
+}
@@ -1 +1,3 @@
+pub mod faulty_transport;
+#[allow(clippy::module_inception)]
 pub(crate) mod thread_socket;
@@ -1,4 +1,5 @@
 use std::sync::mpsc;
+use std::time::Duration;
 
 pub fn new_socket<T>() -> (ThreadSocket<T>, ThreadSocket<T>) {
     let (sender_a, receiver_b) = mpsc::channel();
@@ -10,6 +11,18 @@ pub fn new_socket<T>() -> (ThreadSocket<T>, ThreadSocket<T>) {
     (socket_a, socket_b)
 }
 
+/// `Transport` 线程间双向通信的抽象
+/// `ThreadSocket` 是可靠的 FIFO 实现，`FaultyTransport` 在其之上注入故障
+pub trait Transport<T>: Send + Sync {
+    fn send(&self, data: T);
+
+    /// `receive` 对端关闭后返回 None
+    fn receive(&self) -> Option<T>;
+
+    /// `receive_timeout` 超时返回 None
+    fn receive_timeout(&self, timeout: Duration) -> Option<T>;
+}
+
 pub struct ThreadSocket<T> {
     sender: mpsc::Sender<T>,
     receiver: mpsc::Receiver<T>,
@@ -24,20 +37,40 @@ impl<T> ThreadSocket<T> {
         self.receiver.recv().unwrap()
     }
 
+    pub fn receive_timeout(&self, timeout: Duration) -> Option<T> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(data) => Some(data),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+
     pub fn send(&self, data: T) {
         self.sender.send(data).unwrap();
     }
 }
 
+impl<T: Send> Transport<T> for ThreadSocket<T> {
+    fn send(&self, data: T) {
+        ThreadSocket::send(self, data)
+    }
+
+    fn receive(&self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> Option<T> {
+        ThreadSocket::receive_timeout(self, timeout)
+    }
+}
+
 unsafe impl<T> Sync for ThreadSocket<T> {}
 
 #[cfg(test)]
 mod tests {
-    use crate::thread_socket::thread_socket::ThreadSocket;
     use crate::thread_socket::thread_socket::*;
-    use std::sync::mpsc::Sender;
-    use std::time;
-    use std::{future, thread};
+    use std::thread;
+    use std::time::Duration;
 
     #[test]
     fn test_socket() {
@@ -66,4 +99,16 @@ mod tests {
         t2.join().unwrap();
         t1.join().unwrap();
     }
+
+    #[test]
+    fn test_receive_timeout() {
+        let (sa, sb): (ThreadSocket<String>, ThreadSocket<String>) = new_socket();
+
+        assert_eq!(sa.receive_timeout(Duration::from_millis(10)), None);
+        sb.send("msg".to_string());
+        assert_eq!(
+            sa.receive_timeout(Duration::from_millis(10)),
+            Some("msg".to_string())
+        );
+    }
 }
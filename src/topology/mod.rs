@@ -0,0 +1,3 @@
+pub mod network;
+#[allow(clippy::module_inception)]
+pub mod topology;
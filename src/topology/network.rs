@@ -0,0 +1,99 @@
+use crate::sim::engine::Cycles;
+use crate::topology::topology::{Link, Topology};
+use std::collections::BTreeMap;
+
+/// `LinkStats` 一条链路上的流量
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub messages: u64,
+    pub bytes: u64,
+    /// 链路被占用的周期数
+    pub busy: Cycles,
+}
+
+impl LinkStats {
+    /// `utilization` 在 cycles 个周期中链路被占用的比例
+    pub fn utilization(&self, cycles: Cycles) -> f64 {
+        if cycles == 0 {
+            return 0.0;
+        }
+        self.busy as f64 / cycles as f64
+    }
+}
+
+/// `Network` 按拓扑转发消息，每条链路同一时刻只传输一条消息
+pub struct Network {
+    topology: Topology,
+    nodes: usize,
+    /// 消息经过一条链路的延迟
+    latency: Cycles,
+    /// 每条链路每周期传输的字节数
+    bandwidth: u64,
+    // 链路空闲的时刻和流量
+    links: BTreeMap<Link, (Cycles, LinkStats)>,
+}
+
+impl Network {
+    pub fn new(topology: Topology, nodes: usize, latency: Cycles, bandwidth: u64) -> Network {
+        Network {
+            topology,
+            nodes,
+            latency,
+            bandwidth: bandwidth.max(1),
+            links: BTreeMap::new(),
+        }
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// `send` at 时刻从 from 发出 bytes 字节的消息，返回消息到达 to 的时刻
+    /// 每经过一条链路，等待链路空闲后占用 bytes / bandwidth 个周期，再经过 latency 到达下一个节点
+    pub fn send(&mut self, at: Cycles, from: usize, to: usize, bytes: u64) -> Cycles {
+        let occupancy = bytes.div_ceil(self.bandwidth);
+        let mut t = at;
+        for link in self.topology.route(self.nodes, from, to) {
+            let (free, stats) = self.links.entry(link).or_default();
+            let start = t.max(*free);
+            *free = start + occupancy;
+            stats.messages += 1;
+            stats.bytes += bytes;
+            stats.busy += occupancy;
+            t = start + occupancy + self.latency;
+        }
+        t
+    }
+
+    /// `links` 有过流量的链路，按链路排序
+    pub fn links(&self) -> Vec<(Link, LinkStats)> {
+        self.links
+            .iter()
+            .map(|(link, (_, stats))| (*link, stats.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contention() {
+        let mut net = Network::new(Topology::Ring, 4, 10, 8);
+        // 16 字节占用每条链路 2 个周期
+        assert_eq!(net.send(0, 0, 2, 16), 24);
+        // 与上一条消息同时经过 (0, 1)，等待 2 个周期
+        assert_eq!(net.send(0, 0, 1, 8), 13);
+        // 反方向的链路独立
+        assert_eq!(net.send(0, 1, 0, 8), 11);
+
+        let links = net.links();
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[0].0, (0, 1));
+        assert_eq!(links[0].1.messages, 2);
+        assert_eq!(links[0].1.bytes, 24);
+        assert_eq!(links[0].1.busy, 3);
+        assert_eq!(links[0].1.utilization(30), 0.1);
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// `Link` 从一个节点到相邻节点的有向链路
+pub type Link = (usize, usize);
+
+/// `Topology` 控制器与目录之间的互连方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// 任意两个节点之间都有独立的链路，与 ThreadSocket 相同
+    Crossbar,
+    /// 双向环，沿较短的方向转发，距离相同时沿编号增大的方向
+    Ring,
+    /// 二维网格，节点按行排列，先沿 X 再沿 Y 转发
+    /// width 为 0 时取不小于节点数平方根的最小整数
+    Mesh { width: usize },
+}
+
+impl Topology {
+    fn width(&self, nodes: usize) -> usize {
+        match self {
+            Topology::Mesh { width } if *width > 0 => *width,
+            _ => {
+                let mut w = 1;
+                while w * w < nodes {
+                    w += 1;
+                }
+                w
+            }
+        }
+    }
+
+    /// `route` 共 nodes 个节点时，消息从 from 到 to 依次经过的链路
+    pub fn route(&self, nodes: usize, from: usize, to: usize) -> Vec<Link> {
+        if from == to {
+            return Vec::new();
+        }
+        match self {
+            Topology::Crossbar => vec![(from, to)],
+            Topology::Ring => {
+                let forward = (to + nodes - from) % nodes;
+                let step = if forward <= nodes - forward {
+                    1
+                } else {
+                    nodes - 1
+                };
+                let mut links = Vec::new();
+                let mut at = from;
+                while at != to {
+                    let next = (at + step) % nodes;
+                    links.push((at, next));
+                    at = next;
+                }
+                links
+            }
+            Topology::Mesh { .. } => {
+                let w = self.width(nodes);
+                let mut links = Vec::new();
+                let mut at = from;
+                while at % w != to % w {
+                    let next = if at % w < to % w { at + 1 } else { at - 1 };
+                    links.push((at, next));
+                    at = next;
+                }
+                while at != to {
+                    let next = if at < to { at + w } else { at - w };
+                    links.push((at, next));
+                    at = next;
+                }
+                links
+            }
+        }
+    }
+
+    /// `hops` 从 from 到 to 经过的链路数
+    pub fn hops(&self, nodes: usize, from: usize, to: usize) -> usize {
+        self.route(nodes, from, to).len()
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Topology::Crossbar => write!(f, "crossbar"),
+            Topology::Ring => write!(f, "ring"),
+            Topology::Mesh { width: 0 } => write!(f, "mesh"),
+            Topology::Mesh { width } => write!(f, "mesh:{}", width),
+        }
+    }
+}
+
+/// 与 Display 的格式相同，如 `ring`、`mesh:4`
+impl FromStr for Topology {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            None => match s.trim() {
+                "crossbar" => Ok(Topology::Crossbar),
+                "ring" => Ok(Topology::Ring),
+                "mesh" => Ok(Topology::Mesh { width: 0 }),
+                _ => Err(format!("unknown topology {:?}", s)),
+            },
+            Some(("mesh", width)) => match width.trim().parse() {
+                Ok(width) if width > 0 => Ok(Topology::Mesh { width }),
+                _ => Err(format!("{:?} expects mesh:<width>", s)),
+            },
+            _ => Err(format!("unknown topology {:?}", s)),
+        }
+    }
+}
+
+/// `Placement` 控制器和目录分片所在的节点
+/// 目录分片均匀地插在控制器之间，其余节点按顺序分给控制器
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    controllers: Vec<usize>,
+    slices: Vec<usize>,
+}
+
+impl Placement {
+    pub fn new(controllers: usize, slices: usize) -> Placement {
+        let nodes = controllers + slices;
+        let slices: Vec<usize> = (0..slices)
+            .map(|s| (2 * s + 1) * nodes / (2 * slices))
+            .collect();
+        let controllers = (0..nodes).filter(|n| !slices.contains(n)).collect();
+        Placement {
+            controllers,
+            slices,
+        }
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.controllers.len() + self.slices.len()
+    }
+
+    /// `controller` 第 i 个控制器所在的节点
+    pub fn controller(&self, i: usize) -> usize {
+        self.controllers[i]
+    }
+
+    /// `slice` 第 s 个目录分片所在的节点
+    pub fn slice(&self, s: usize) -> usize {
+        self.slices[s]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        assert_eq!(Topology::Crossbar.route(8, 1, 6), vec![(1, 6)]);
+        assert_eq!(Topology::Ring.route(8, 1, 3), vec![(1, 2), (2, 3)]);
+        assert_eq!(Topology::Ring.route(8, 1, 6), vec![(1, 0), (0, 7), (7, 6)]);
+        assert_eq!(Topology::Ring.hops(8, 0, 4), 4);
+
+        // 3x3 网格，先沿 X 再沿 Y
+        let mesh = Topology::Mesh { width: 0 };
+        assert_eq!(mesh.route(9, 0, 8), vec![(0, 1), (1, 2), (2, 5), (5, 8)]);
+        assert_eq!(mesh.route(9, 7, 3), vec![(7, 6), (6, 3)]);
+        assert_eq!(mesh.hops(9, 4, 4), 0);
+    }
+
+    #[test]
+    fn test_parse() {
+        for t in [
+            Topology::Crossbar,
+            Topology::Ring,
+            Topology::Mesh { width: 0 },
+            Topology::Mesh { width: 4 },
+        ] {
+            assert_eq!(t.to_string().parse::<Topology>(), Ok(t));
+        }
+        assert!("mesh:0".parse::<Topology>().is_err());
+        assert!("torus".parse::<Topology>().is_err());
+    }
+
+    #[test]
+    fn test_placement() {
+        let p = Placement::new(4, 1);
+        assert_eq!(p.nodes(), 5);
+        assert_eq!(p.slice(0), 2);
+        assert_eq!(
+            (0..4).map(|i| p.controller(i)).collect::<Vec<_>>(),
+            vec![0, 1, 3, 4]
+        );
+        let p = Placement::new(6, 2);
+        assert_eq!((p.slice(0), p.slice(1)), (2, 6));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::trace::trace::{Side, TraceKind, TraceRecord};
+use crate::Event;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Chrome trace 中的进程，事务按发起线程排列，消息按监听线程排列
+const TRANSACTIONS_PID: u32 = 0;
+const LISTENERS_PID: u32 = 1;
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn event_name(event: &Event) -> String {
+    match event {
+        Event::RemoteRead(_) => "RemoteRead".to_string(),
+        Event::RemoteWrite(_) => "RemoteWrite".to_string(),
+        Event::Update(_, _) => "Update".to_string(),
+        Event::Confirmed(b) => format!("Confirmed({})", b),
+    }
+}
+
+fn metadata(pid: u32, name: &str) -> String {
+    format!(
+        r#"{{"name":"process_name","ph":"M","pid":{},"tid":0,"args":{{"name":"{}"}}}}"#,
+        pid, name
+    )
+}
+
+fn to_json(r: &TraceRecord) -> String {
+    let ts = r.ts.as_nanos() as f64 / 1000.0;
+    let mut args = format!(r#""key":"{}","seq":{}"#, escape(&r.key), r.seq);
+    if let Some(t) = r.requester {
+        let _ = write!(args, r#","requester":{}"#, t);
+    }
+    if let Some(s) = &r.before {
+        let _ = write!(args, r#","before":"{:?}""#, s);
+    }
+    if let Some(s) = &r.after {
+        let _ = write!(args, r#","after":"{:?}""#, s);
+    }
+
+    let name = event_name(&r.event);
+    match &r.kind {
+        TraceKind::Transaction(dur) => format!(
+            r#"{{"name":"{} {}","cat":"transaction","ph":"X","ts":{},"dur":{},"pid":{},"tid":{},"args":{{{}}}}}"#,
+            name,
+            escape(&r.key),
+            ts,
+            dur.as_nanos() as f64 / 1000.0,
+            TRANSACTIONS_PID,
+            r.thread_id,
+            args
+        ),
+        kind => {
+            let (action, pid) = match (kind, r.side) {
+                (TraceKind::Send, Side::Directory) => ("directory send", TRANSACTIONS_PID),
+                (_, Side::Directory) => ("directory receive", TRANSACTIONS_PID),
+                (TraceKind::Send, Side::Listener) => ("send", LISTENERS_PID),
+                (_, Side::Listener) => ("receive", LISTENERS_PID),
+            };
+            // 目录一侧的消息放在发起事务的线程上，与事务对齐
+            let tid = match r.side {
+                Side::Directory => r.requester.unwrap_or(r.thread_id),
+                Side::Listener => r.thread_id,
+            };
+            let _ = write!(args, r#","thread":{}"#, r.thread_id);
+            format!(
+                r#"{{"name":"{} {}","cat":"{}","ph":"i","s":"t","ts":{},"pid":{},"tid":{},"args":{{{}}}}}"#,
+                action, name, action, ts, pid, tid, args
+            )
+        }
+    }
+}
+
+/// `write_chrome_trace` 以 Chrome trace JSON 格式输出记录，可以在 Perfetto 中查看
+pub fn write_chrome_trace(records: &[TraceRecord], w: &mut impl Write) -> io::Result<()> {
+    write!(w, r#"{{"displayTimeUnit":"ns","traceEvents":["#)?;
+    writeln!(w, "{},", metadata(TRANSACTIONS_PID, "transactions"))?;
+    write!(w, "{}", metadata(LISTENERS_PID, "listeners"))?;
+    for r in records {
+        write!(w, ",\n{}", to_json(r))?;
+    }
+    writeln!(w, "]}}")
+}
+
+/// `save_chrome_trace` 将记录写入文件
+pub fn save_chrome_trace(records: &[TraceRecord], path: impl AsRef<Path>) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_chrome_trace(records, &mut w)?;
+    w.flush()
+}
@@ -0,0 +1,3 @@
+pub mod chrome;
+#[allow(clippy::module_inception)]
+pub mod trace;
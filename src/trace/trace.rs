@@ -0,0 +1,116 @@
+use crate::{Event, Message, Status, ThreadID};
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+/// `Side` 记录发生在 Directory 一侧还是 CacheController 的监听线程一侧
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Directory,
+    Listener,
+}
+
+/// `TraceKind` 一条记录的类型
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceKind {
+    Send,
+    Receive,
+    /// 目录中的一次完整事务，从等待目录项开始到装入请求方缓存为止
+    Transaction(Duration),
+}
+
+/// `TraceRecord` 一次消息的发送、接收，或一次事务
+#[derive(Debug, Clone)]
+pub struct TraceRecord {
+    /// 相对于 Tracer 创建时刻
+    pub ts: Duration,
+    pub kind: TraceKind,
+    pub side: Side,
+    /// 消息两端中 CacheController 一端的线程；事务为发起线程
+    pub thread_id: ThreadID,
+    /// 引起这条消息的事务的发起线程，监听线程一侧不知道时为 None
+    pub requester: Option<ThreadID>,
+    pub event: Event,
+    pub key: String,
+    pub seq: u64,
+    /// 监听线程处理请求前后缓存项的状态，不在缓存中为 Invalid；
+    /// 重复、过期的请求不处理，两者都为 None
+    pub before: Option<Status>,
+    pub after: Option<Status>,
+}
+
+/// `Tracer` 记录所有一致性消息，DirectoryConfig::trace 为 true 时由 Directory 创建
+pub struct Tracer {
+    start: Instant,
+    records: Mutex<Vec<TraceRecord>>,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Tracer::new()
+    }
+}
+
+impl Tracer {
+    pub fn new() -> Tracer {
+        Tracer {
+            start: Instant::now(),
+            records: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// `now` 当前时刻相对于 Tracer 创建时刻的偏移
+    pub fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn offset(&self, t: Instant) -> Duration {
+        t.saturating_duration_since(self.start)
+    }
+
+    pub fn record(&self, record: TraceRecord) {
+        self.records.lock().push(record);
+    }
+
+    /// `message` 记录一次不涉及缓存状态的消息收发
+    pub(crate) fn message(
+        &self,
+        kind: TraceKind,
+        side: Side,
+        thread_id: ThreadID,
+        requester: Option<ThreadID>,
+        key: &str,
+        message: &Message,
+    ) {
+        self.record(TraceRecord {
+            ts: self.now(),
+            kind,
+            side,
+            thread_id,
+            requester,
+            event: message.event.clone(),
+            key: key.to_string(),
+            seq: message.seq,
+            before: None,
+            after: None,
+        });
+    }
+
+    /// `records` 按时间排序的所有记录
+    pub fn records(&self) -> Vec<TraceRecord> {
+        let mut records = self.records.lock().clone();
+        records.sort_by_key(|r| r.ts);
+        records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().clear();
+    }
+}
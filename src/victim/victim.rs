@@ -0,0 +1,103 @@
+//! 批量淘汰的缓存项的 victim buffer
+//!
+//! 缓存项数达到 cache_size 时批量淘汰，被淘汰的有效项先放入一个容量很小的全相联 victim buffer，
+//! 其中的项仍被目录视为本线程持有：Modified 的项不写回，监听线程照常对它们处理 RemoteRead、
+//! RemoteWrite 和 Update。之后读写命中 victim buffer 时，该项移回缓存，不需要经过目录。
+//! victim buffer 写满时按放入的顺序真正淘汰最早的一项，Modified 的项这时才写回。
+//!
+//! 监听线程持有缓存分片的锁时查找 victim buffer，缓存项在两者之间移动时也持有该锁，
+//! 因此监听线程不会错过正在移动的项。
+use crate::Cache;
+use std::collections::VecDeque;
+
+/// `VictimBuffer` 按放入顺序排列的全相联 victim buffer
+pub struct VictimBuffer<T: Clone + ToString + Sync> {
+    capacity: usize,
+    lines: VecDeque<Cache<T>>,
+}
+
+impl<T: Clone + ToString + Sync> VictimBuffer<T> {
+    /// `new` 至少能放一项
+    pub fn new(capacity: usize) -> VictimBuffer<T> {
+        let capacity = capacity.max(1);
+        VictimBuffer {
+            capacity,
+            lines: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// `insert` 放入一个被淘汰的项，已满时返回被挤出的最早的一项
+    pub fn insert(&mut self, cache: Cache<T>) -> Option<Cache<T>> {
+        self.remove(&cache.id);
+        let out = (self.lines.len() >= self.capacity)
+            .then(|| self.lines.pop_front())
+            .flatten();
+        self.lines.push_back(cache);
+        out
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Cache<T>> {
+        self.lines.iter_mut().find(|c| c.id == key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.lines.iter().any(|c| c.id == key)
+    }
+
+    /// `remove` 取出 key 的项，命中时移回缓存，失效时丢弃
+    pub fn remove(&mut self, key: &str) -> Option<Cache<T>> {
+        let i = self.lines.iter().position(|c| c.id == key)?;
+        self.lines.remove(i)
+    }
+
+    /// `drain` 按放入的顺序取出所有项
+    pub fn drain(&mut self) -> impl Iterator<Item = Cache<T>> + '_ {
+        self.lines.drain(..)
+    }
+
+    /// `iter` 按放入的顺序
+    pub fn iter(&self) -> impl Iterator<Item = &Cache<T>> {
+        self.lines.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Status;
+
+    #[test]
+    fn test_victim_buffer() {
+        let line = |k: &str| Cache::new(k.to_string(), k.to_string(), Status::Shared);
+        let mut victims = VictimBuffer::new(2);
+        assert!(victims.insert(line("a")).is_none());
+        assert!(victims.insert(line("b")).is_none());
+        // 重新放入的项排到最后
+        assert!(victims.insert(line("a")).is_none());
+        assert_eq!(
+            victims.insert(line("c")).map(|c| c.id),
+            Some("b".to_string())
+        );
+        assert_eq!(victims.len(), 2);
+
+        victims.get_mut("a").unwrap().status = Status::Modified;
+        let a = victims.remove("a").unwrap();
+        assert_eq!(a.status, Status::Modified);
+        assert!(!victims.contains("a"));
+        assert!(victims.remove("a").is_none());
+        let keys: Vec<_> = victims.iter().map(|c| c.id.clone()).collect();
+        assert_eq!(keys, ["c"]);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::{Directory, Status, ThreadID, Transaction, TransactionState};
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// `WatchdogConfig` 死锁检测配置
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    /// 检测周期
+    pub interval: Duration,
+    /// 事务在同一状态停留超过该时间才被视为阻塞
+    pub threshold: Duration,
+    /// 是否中止死锁中的一个事务，被等待的共享者会被隔离
+    pub break_deadlocks: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            interval: Duration::from_millis(100),
+            threshold: Duration::from_secs(1),
+            break_deadlocks: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeadlockKind {
+    /// waits-for 图中的环
+    Cycle(Vec<ThreadID>),
+    /// 长时间等待某个共享者确认，但不在环上
+    Stall(ThreadID),
+}
+
+/// `DeadlockReport` 一次检测到的死锁及当时目录和缓存的状态
+#[derive(Debug, Clone)]
+pub struct DeadlockReport {
+    pub kind: DeadlockKind,
+    /// 被中止的事务
+    pub victim: Option<ThreadID>,
+    pub transactions: Vec<Transaction>,
+    pub sharers: Vec<(String, Vec<ThreadID>)>,
+    /// 被占用而未能读取的目录分片数
+    pub locked_shards: usize,
+    pub caches: Vec<(ThreadID, Vec<(String, Status)>)>,
+}
+
+impl fmt::Display for DeadlockReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            DeadlockKind::Cycle(cycle) => writeln!(f, "deadlock detected: cycle {:?}", cycle)?,
+            DeadlockKind::Stall(t) => writeln!(f, "deadlock detected: thread {} stalled", t)?,
+        }
+        if let Some(victim) = self.victim {
+            writeln!(f, "aborted transaction of thread {}", victim)?;
+        }
+
+        writeln!(f, "transactions:")?;
+        for t in &self.transactions {
+            writeln!(
+                f,
+                "  thread {} {:?} {:?} for {:?}",
+                t.thread_id,
+                t.event,
+                t.state,
+                t.since.elapsed()
+            )?;
+        }
+        writeln!(f, "directory ({} shards locked):", self.locked_shards)?;
+        for (key, sharers) in &self.sharers {
+            writeln!(f, "  {}: {:?}", key, sharers)?;
+        }
+        writeln!(f, "caches:")?;
+        for (t, caches) in &self.caches {
+            writeln!(f, "  thread {}: {:?}", t, caches)?;
+        }
+        Ok(())
+    }
+}
+
+/// `waits_for` 由事务构建 waits-for 图
+/// 持有锁的事务指向尚未确认的共享者，等待锁的事务指向同一个 key 上持有锁的事务
+pub fn waits_for(transactions: &[Transaction]) -> HashMap<ThreadID, Vec<ThreadID>> {
+    let mut holders = HashMap::new();
+    for t in transactions {
+        if matches!(t.state, TransactionState::Holding(_)) {
+            holders.insert(t.event.get_id().clone(), t.thread_id);
+        }
+    }
+
+    let mut graph: HashMap<ThreadID, Vec<ThreadID>> = HashMap::new();
+    for t in transactions {
+        let edges = match &t.state {
+            TransactionState::Holding(waiting) => waiting.clone(),
+            TransactionState::WaitingKey => holders
+                .get(t.event.get_id())
+                .map(|h| vec![*h])
+                .unwrap_or_default(),
+        };
+        // 有多个未完成缺失的线程同时有多个事务，合并它们的边
+        graph.entry(t.thread_id).or_default().extend(edges);
+    }
+    graph
+}
+
+/// `find_cycle` 返回图中任意一个环
+pub fn find_cycle(graph: &HashMap<ThreadID, Vec<ThreadID>>) -> Option<Vec<ThreadID>> {
+    let mut nodes: Vec<&ThreadID> = graph.keys().collect();
+    nodes.sort();
+
+    let mut done = HashSet::new();
+    for start in nodes {
+        let mut path = vec![*start];
+        let mut on_path = HashSet::from([*start]);
+        let mut iters = vec![graph[start].iter()];
+
+        while let Some(it) = iters.last_mut() {
+            match it.next() {
+                None => {
+                    let node = path.pop().unwrap();
+                    on_path.remove(&node);
+                    done.insert(node);
+                    iters.pop();
+                }
+                Some(next) => {
+                    if on_path.contains(next) {
+                        let idx = path.iter().position(|n| n == next).unwrap();
+                        return Some(path[idx..].to_vec());
+                    }
+                    if done.contains(next) || !graph.contains_key(next) {
+                        continue;
+                    }
+                    path.push(*next);
+                    on_path.insert(*next);
+                    iters.push(graph[next].iter());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// `Watchdog` 周期性检查 Directory 中阻塞的事务，报告（并可选地打破）死锁
+pub struct Watchdog {
+    reports: Arc<Mutex<Vec<DeadlockReport>>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn spawn(directory: Arc<RwLock<Directory>>, config: WatchdogConfig) -> Watchdog {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let _reports = reports.clone();
+        let _stop = stop.clone();
+        let handle = thread::spawn(move || {
+            // 已报告过的 (线程, 进入状态的时间)，同一次阻塞只报告一次
+            let mut reported = HashSet::new();
+            while !_stop.load(Ordering::Relaxed) {
+                thread::sleep(config.interval);
+                let found = check(&directory.read(), &config, &mut reported);
+                _reports.lock().extend(found);
+            }
+        });
+
+        Watchdog {
+            reports,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// `reports` 目前为止检测到的死锁，按检测的顺序
+    pub fn reports(&self) -> Vec<DeadlockReport> {
+        self.reports.lock().clone()
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// `check` 进行一次检测，只考虑停留时间超过阈值的事务
+pub fn check(
+    directory: &Directory,
+    config: &WatchdogConfig,
+    reported: &mut HashSet<(ThreadID, Instant)>,
+) -> Vec<DeadlockReport> {
+    let transactions: Vec<Transaction> = directory
+        .transactions()
+        .into_iter()
+        .filter(|t| !reported.contains(&(t.thread_id, t.since)))
+        .collect();
+    let is_stuck = |t: &Transaction| t.since.elapsed() >= config.threshold;
+    let stuck: Vec<Transaction> = transactions
+        .iter()
+        .filter(|t| is_stuck(t))
+        .cloned()
+        .collect();
+    if stuck.is_empty() {
+        return Vec::new();
+    }
+
+    // 环上所有事务都超过阈值才报告，否则等到下一次检测，期间也不作为单独的阻塞报告
+    let mut kinds = Vec::new();
+    let mut in_cycle = HashSet::new();
+    let mut graph = waits_for(&transactions);
+    while let Some(cycle) = find_cycle(&graph) {
+        for t in &cycle {
+            graph.remove(t);
+            in_cycle.insert(*t);
+        }
+        if stuck
+            .iter()
+            .filter(|t| cycle.contains(&t.thread_id))
+            .count()
+            == cycle.len()
+        {
+            kinds.push(DeadlockKind::Cycle(cycle));
+        }
+    }
+    for t in &stuck {
+        if !in_cycle.contains(&t.thread_id)
+            && matches!(&t.state, TransactionState::Holding(w) if !w.is_empty())
+        {
+            kinds.push(DeadlockKind::Stall(t.thread_id));
+        }
+    }
+
+    let mut reports = Vec::new();
+    for kind in kinds {
+        let members = match &kind {
+            DeadlockKind::Cycle(cycle) => cycle.clone(),
+            DeadlockKind::Stall(t) => vec![*t],
+        };
+        // 中止环上最年轻的、正在等待共享者确认的事务
+        let victim = stuck
+            .iter()
+            .filter(|t| members.contains(&t.thread_id))
+            .filter(|t| matches!(&t.state, TransactionState::Holding(w) if !w.is_empty()))
+            .max_by_key(|t| t.started)
+            .map(|t| t.thread_id);
+        for t in stuck.iter().filter(|t| members.contains(&t.thread_id)) {
+            reported.insert((t.thread_id, t.since));
+        }
+
+        let victim = match victim {
+            Some(v) if config.break_deadlocks && directory.abort(v) => Some(v),
+            _ => None,
+        };
+        let (sharers, locked_shards) = directory.sharers();
+        reports.push(DeadlockReport {
+            kind,
+            victim,
+            transactions: directory.transactions(),
+            sharers,
+            locked_shards,
+            caches: directory.caches(),
+        });
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::watchdog::watchdog::*;
+    use crate::{CacheController, DirectoryConfig};
+
+    #[test]
+    fn test_find_cycle() {
+        let graph = HashMap::from([(0, vec![1]), (1, vec![2]), (2, vec![0]), (3, vec![0])]);
+        let mut cycle = find_cycle(&graph).unwrap();
+        cycle.sort();
+        assert_eq!(cycle, vec![0, 1, 2]);
+
+        let graph = HashMap::from([(0, vec![1, 2]), (1, vec![2]), (2, vec![])]);
+        assert_eq!(find_cycle(&graph), None);
+    }
+
+    /// 线程 1 的监听者不再应答：线程 0 持有 key 等待线程 1 确认，线程 1 等待同一个 key，
+    /// watchdog 应检测到环并中止线程 0 的事务
+    #[test]
+    fn test_break_deadlock() {
+        let config = DirectoryConfig {
+            retry_timeout: Duration::from_millis(5),
+            max_retries: u32::MAX,
+            ..DirectoryConfig::default()
+        };
+        let directory = Arc::new(RwLock::new(Directory::with_config(
+            &"./data/db_watchdog_break".to_string(),
+            config,
+        )));
+        let mut ct: CacheController<String> = CacheController::new(directory.clone());
+        let (rogue, _socket) = directory.write().register();
+        directory
+            .read()
+            .read::<String>(rogue, "k".to_string(), |_, _| {});
+
+        let watchdog = Watchdog::spawn(
+            directory.clone(),
+            WatchdogConfig {
+                interval: Duration::from_millis(10),
+                threshold: Duration::from_millis(50),
+                break_deadlocks: true,
+            },
+        );
+
+        let d = directory.clone();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            d.read().write_to_cache(rogue, "k".to_string(), || {});
+        });
+        ct.get("k".to_string());
+        t.join().unwrap();
+
+        let reports = watchdog.reports();
+        assert_eq!(reports.len(), 1);
+        assert!(matches!(&reports[0].kind, DeadlockKind::Cycle(c) if c.len() == 2));
+        assert_eq!(reports[0].victim, Some(ct.thread_id));
+        assert_eq!(directory.read().fenced(), vec![rogue]);
+    }
+
+    /// 没有环时报告阻塞的事务，消息超时后隔离不应答的共享者，系统继续运行
+    #[test]
+    fn test_stall_timeout() {
+        let config = DirectoryConfig {
+            retry_timeout: Duration::from_millis(5),
+            max_retries: 40,
+            ..DirectoryConfig::default()
+        };
+        let directory = Arc::new(RwLock::new(Directory::with_config(
+            &"./data/db_watchdog_stall".to_string(),
+            config,
+        )));
+        let mut ct: CacheController<String> = CacheController::new(directory.clone());
+        let (rogue, _socket) = directory.write().register();
+        directory
+            .read()
+            .read::<String>(rogue, "k".to_string(), |_, _| {});
+
+        let watchdog = Watchdog::spawn(
+            directory.clone(),
+            WatchdogConfig {
+                interval: Duration::from_millis(10),
+                threshold: Duration::from_millis(50),
+                break_deadlocks: false,
+            },
+        );
+        ct.set("k".to_string(), "v".to_string());
+        assert_eq!(ct.get("k".to_string()), "v");
+
+        let reports = watchdog.reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].kind, DeadlockKind::Stall(ct.thread_id));
+        assert_eq!(reports[0].victim, None);
+        assert_eq!(directory.read().timeouts(), 1);
+        assert_eq!(directory.read().fenced(), vec![rogue]);
+    }
+}
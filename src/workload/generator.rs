@@ -0,0 +1,344 @@
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/// `KeyDistribution` 从 [0, records) 中选择 key 的分布
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyDistribution {
+    Uniform,
+    /// 参数为偏斜程度 theta，key 0 最热，YCSB 默认 0.99
+    Zipfian(f64),
+    /// Zipfian 的结果经过哈希打散，热点不再集中在小编号的 key 上
+    ScrambledZipfian(f64),
+    /// 最近插入的 key 最热
+    Latest(f64),
+    /// hot_probability 的访问落在前 hot_fraction 的 key 上，其余均匀访问剩下的 key，两者都在 [0, 1] 中
+    Hotspot {
+        hot_fraction: f64,
+        hot_probability: f64,
+    },
+    /// 每个线程均匀访问自己的一段 key，相邻线程的区间有 overlap 比例的重叠，overlap 在 [0, 1] 中
+    Partitioned {
+        overlap: f64,
+    },
+}
+
+impl fmt::Display for KeyDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyDistribution::Uniform => write!(f, "uniform"),
+            KeyDistribution::Zipfian(theta) => write!(f, "zipfian:{}", theta),
+            KeyDistribution::ScrambledZipfian(theta) => write!(f, "scrambled:{}", theta),
+            KeyDistribution::Latest(theta) => write!(f, "latest:{}", theta),
+            KeyDistribution::Hotspot {
+                hot_fraction,
+                hot_probability,
+            } => write!(f, "hotspot:{}:{}", hot_fraction, hot_probability),
+            KeyDistribution::Partitioned { overlap } => write!(f, "partitioned:{}", overlap),
+        }
+    }
+}
+
+/// 与 Display 的格式相同，如 `zipfian:0.99`、`hotspot:0.01:0.9`
+impl FromStr for KeyDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(':');
+        let name = parts.next().unwrap_or_default();
+        let params = parts
+            .map(|p| {
+                p.trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("bad parameter {:?} in {:?}", p, s))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        let zipfian = |params: &[f64]| match params {
+            [] => Ok(0.99),
+            [theta] if *theta > 0.0 && *theta != 1.0 => Ok(*theta),
+            _ => Err(format!("{:?} expects one theta, positive and not 1", s)),
+        };
+        let fraction = |p: f64| match (0.0..=1.0).contains(&p) {
+            true => Ok(p),
+            false => Err(format!("{:?} expects parameters in [0, 1]", s)),
+        };
+        match name {
+            "uniform" if params.is_empty() => Ok(KeyDistribution::Uniform),
+            "zipfian" => Ok(KeyDistribution::Zipfian(zipfian(&params)?)),
+            "scrambled" => Ok(KeyDistribution::ScrambledZipfian(zipfian(&params)?)),
+            "latest" => Ok(KeyDistribution::Latest(zipfian(&params)?)),
+            "hotspot" => match params[..] {
+                [hot_fraction, hot_probability] => Ok(KeyDistribution::Hotspot {
+                    hot_fraction: fraction(hot_fraction)?,
+                    hot_probability: fraction(hot_probability)?,
+                }),
+                _ => Err(format!("{:?} expects hotspot:<fraction>:<probability>", s)),
+            },
+            "partitioned" => match params[..] {
+                [overlap] => Ok(KeyDistribution::Partitioned {
+                    overlap: fraction(overlap)?,
+                }),
+                _ => Err(format!("{:?} expects partitioned:<overlap>", s)),
+            },
+            _ => Err(format!("unknown distribution {:?}", s)),
+        }
+    }
+}
+
+/// `Zipfian` YCSB 中 ZipfianGenerator 的实现（Gray et al., Quickly Generating Billion-Record Synthetic Databases）
+/// 支持 key 数增长，增长时增量计算 zeta
+#[derive(Debug, Clone)]
+pub struct Zipfian {
+    items: u64,
+    theta: f64,
+    alpha: f64,
+    zeta2: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+fn zeta(from: u64, to: u64, theta: f64) -> f64 {
+    (from + 1..=to).map(|i| 1.0 / (i as f64).powf(theta)).sum()
+}
+
+impl Zipfian {
+    pub fn new(items: u64, theta: f64) -> Zipfian {
+        assert!(items > 0, "zipfian needs at least one item");
+        assert!(
+            theta > 0.0 && theta != 1.0,
+            "theta must be positive and not 1"
+        );
+        let zeta2 = zeta(0, 2, theta);
+        let mut z = Zipfian {
+            items: 0,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zeta2,
+            zetan: 0.0,
+            eta: 0.0,
+        };
+        z.grow(items);
+        z
+    }
+
+    /// `grow` 将 key 数增加到 items，已有的 key 保持原来的排名
+    pub fn grow(&mut self, items: u64) {
+        if items <= self.items {
+            return;
+        }
+        self.zetan += zeta(self.items, items, self.theta);
+        self.items = items;
+        self.eta =
+            (1.0 - (2.0 / items as f64).powf(1.0 - self.theta)) / (1.0 - self.zeta2 / self.zetan);
+    }
+
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    /// `sample` 返回 [0, items) 中的一个排名，0 最热
+    pub fn sample(&self, rng: &mut impl Rng) -> u64 {
+        let u: f64 = rng.gen();
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.items - 1);
+        }
+        let v = (self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64;
+        v.min(self.items - 1)
+    }
+}
+
+/// `fnv` 64 位 FNV-1a 哈希，用于打散 Zipfian 的结果
+fn fnv(v: u64) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in v.to_le_bytes() {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
+/// `KeyGenerator` 某个线程按 KeyDistribution 生成 key
+#[derive(Debug, Clone)]
+pub struct KeyGenerator {
+    distribution: KeyDistribution,
+    zipfian: Option<Zipfian>,
+    thread: usize,
+    threads: usize,
+}
+
+impl KeyGenerator {
+    /// `new` records 为初始 key 数，thread 为线程在 threads 个线程中的编号
+    pub fn new(
+        distribution: KeyDistribution,
+        records: u64,
+        thread: usize,
+        threads: usize,
+    ) -> KeyGenerator {
+        assert!(records > 0, "key space is empty");
+        assert!(thread < threads, "thread {} out of {}", thread, threads);
+        match distribution {
+            KeyDistribution::Hotspot {
+                hot_fraction,
+                hot_probability,
+            } => assert!(
+                (0.0..=1.0).contains(&hot_fraction) && (0.0..=1.0).contains(&hot_probability),
+                "hotspot parameters must be in [0, 1]"
+            ),
+            KeyDistribution::Partitioned { overlap } => assert!(
+                (0.0..=1.0).contains(&overlap),
+                "partitioned overlap must be in [0, 1]"
+            ),
+            _ => {}
+        }
+        let zipfian = match distribution {
+            KeyDistribution::Zipfian(theta)
+            | KeyDistribution::ScrambledZipfian(theta)
+            | KeyDistribution::Latest(theta) => Some(Zipfian::new(records, theta)),
+            _ => None,
+        };
+        KeyGenerator {
+            distribution,
+            zipfian,
+            thread,
+            threads,
+        }
+    }
+
+    /// `next` records 为当前的 key 数，插入新 key 后随之增长
+    pub fn next(&mut self, rng: &mut impl Rng, records: u64) -> u64 {
+        if let Some(z) = &mut self.zipfian {
+            z.grow(records);
+        }
+        match self.distribution {
+            KeyDistribution::Uniform => rng.gen_range(0..records),
+            KeyDistribution::Zipfian(_) => self.zipfian.as_ref().unwrap().sample(rng),
+            KeyDistribution::ScrambledZipfian(_) => {
+                fnv(self.zipfian.as_ref().unwrap().sample(rng)) % records
+            }
+            KeyDistribution::Latest(_) => records - 1 - self.zipfian.as_ref().unwrap().sample(rng),
+            KeyDistribution::Hotspot {
+                hot_fraction,
+                hot_probability,
+            } => {
+                let hot = ((records as f64 * hot_fraction) as u64).clamp(1, records);
+                if hot == records || rng.gen_bool(hot_probability) {
+                    rng.gen_range(0..hot)
+                } else {
+                    rng.gen_range(hot..records)
+                }
+            }
+            KeyDistribution::Partitioned { overlap } => {
+                let size = (records / self.threads as u64).max(1);
+                let width = ((size as f64 * (1.0 + overlap)) as u64).clamp(1, records);
+                let start = self.thread as u64 * size;
+                (start + rng.gen_range(0..width)) % records
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn histogram(distribution: KeyDistribution, records: u64, thread: usize) -> Vec<u64> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut g = KeyGenerator::new(distribution, records, thread, 4);
+        let mut counts = vec![0; records as usize];
+        for _ in 0..100_000 {
+            counts[g.next(&mut rng, records) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_zipfian() {
+        let counts = histogram(KeyDistribution::Zipfian(0.99), 1000, 0);
+        assert!(counts[0] > counts[1] && counts[1] > counts[10] && counts[10] > counts[500]);
+        // theta = 0.99、1000 个 key 时，key 0 约占 13%
+        assert!((10_000..16_000).contains(&counts[0]), "{}", counts[0]);
+
+        let mut z = Zipfian::new(10, 0.99);
+        z.grow(1000);
+        let direct = Zipfian::new(1000, 0.99);
+        assert!((z.zetan - direct.zetan).abs() < 1e-9);
+        assert!((z.eta - direct.eta).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_scrambled_and_latest() {
+        let counts = histogram(KeyDistribution::ScrambledZipfian(0.99), 1000, 0);
+        let hottest = (0..1000).max_by_key(|i| counts[*i]).unwrap();
+        assert_ne!(hottest, 0);
+        assert!(counts[hottest] > 10_000);
+
+        let counts = histogram(KeyDistribution::Latest(0.99), 1000, 0);
+        assert!(counts[999] > counts[998] && counts[998] > counts[0]);
+    }
+
+    #[test]
+    fn test_hotspot() {
+        let d = KeyDistribution::Hotspot {
+            hot_fraction: 0.1,
+            hot_probability: 0.9,
+        };
+        let counts = histogram(d, 1000, 0);
+        let hot: u64 = counts[..100].iter().sum();
+        assert!((88_000..92_000).contains(&hot), "{}", hot);
+        assert!(counts[100..].iter().all(|c| *c > 0));
+    }
+
+    #[test]
+    fn test_parse() {
+        for d in [
+            KeyDistribution::Uniform,
+            KeyDistribution::Zipfian(0.5),
+            KeyDistribution::ScrambledZipfian(0.99),
+            KeyDistribution::Latest(0.8),
+            KeyDistribution::Hotspot {
+                hot_fraction: 0.01,
+                hot_probability: 0.9,
+            },
+            KeyDistribution::Partitioned { overlap: 0.25 },
+        ] {
+            assert_eq!(d.to_string().parse::<KeyDistribution>(), Ok(d));
+        }
+        assert_eq!("zipfian".parse(), Ok(KeyDistribution::Zipfian(0.99)));
+        assert!("zipfian:1".parse::<KeyDistribution>().is_err());
+        assert!("hotspot:0.1".parse::<KeyDistribution>().is_err());
+        assert!("hotspot:0.1:1.5".parse::<KeyDistribution>().is_err());
+        assert!("hotspot:-0.1:0.9".parse::<KeyDistribution>().is_err());
+        assert!("partitioned:2".parse::<KeyDistribution>().is_err());
+        assert!("partitioned:NaN".parse::<KeyDistribution>().is_err());
+        assert!("normal".parse::<KeyDistribution>().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "hotspot parameters")]
+    fn test_hotspot_range() {
+        let d = KeyDistribution::Hotspot {
+            hot_fraction: 0.1,
+            hot_probability: 1.5,
+        };
+        KeyGenerator::new(d, 1000, 0, 1);
+    }
+
+    #[test]
+    fn test_partitioned() {
+        let counts = histogram(KeyDistribution::Partitioned { overlap: 0.5 }, 1000, 1);
+        // 线程 1 访问 [250, 625)
+        assert!(counts[..250].iter().all(|c| *c == 0));
+        assert!(counts[250..625].iter().all(|c| *c > 0));
+        assert!(counts[625..].iter().all(|c| *c == 0));
+
+        // 最后一个线程回绕到开头
+        let counts = histogram(KeyDistribution::Partitioned { overlap: 0.5 }, 1000, 3);
+        assert!(counts[0] > 0 && counts[124] > 0 && counts[125] == 0);
+    }
+}
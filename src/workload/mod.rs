@@ -0,0 +1,3 @@
+pub mod generator;
+#[allow(clippy::module_inception)]
+pub mod workload;
@@ -0,0 +1,461 @@
+use crate::recording::recording::AccessOp;
+use crate::workload::generator::{KeyDistribution, KeyGenerator};
+use crate::CacheController;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// `Ycsb` YCSB 的核心负载 A–F
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ycsb {
+    /// 50% 读、50% 更新
+    A,
+    /// 95% 读、5% 更新
+    B,
+    /// 只读
+    C,
+    /// 95% 读、5% 插入，读最近插入的 key
+    D,
+    /// 95% 范围扫描、5% 插入
+    E,
+    /// 50% 读、50% 读-改-写
+    F,
+}
+
+impl Ycsb {
+    pub const ALL: [Ycsb; 6] = [Ycsb::A, Ycsb::B, Ycsb::C, Ycsb::D, Ycsb::E, Ycsb::F];
+}
+
+/// `WorkloadConfig` 负载配置，五种操作的比例之和应为 1
+#[derive(Debug, Clone)]
+pub struct WorkloadConfig {
+    /// 初始 key 数
+    pub records: u64,
+    pub distribution: KeyDistribution,
+    pub read: f64,
+    pub update: f64,
+    pub insert: f64,
+    pub scan: f64,
+    pub read_modify_write: f64,
+    /// 范围扫描的长度在 [1, max_scan_length] 中均匀选择
+    pub max_scan_length: u64,
+    /// 生成的 key 为 key_prefix 加编号，不同测试使用不同前缀以免共用数据
+    pub key_prefix: String,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        WorkloadConfig {
+            records: 1000,
+            distribution: KeyDistribution::Uniform,
+            read: 0.5,
+            update: 0.5,
+            insert: 0.0,
+            scan: 0.0,
+            read_modify_write: 0.0,
+            max_scan_length: 100,
+            key_prefix: "user".to_string(),
+        }
+    }
+}
+
+impl WorkloadConfig {
+    /// `ycsb` YCSB 负载的操作比例和分布，其余配置取默认值
+    pub fn ycsb(preset: Ycsb) -> WorkloadConfig {
+        let zipfian = KeyDistribution::Zipfian(0.99);
+        let base = WorkloadConfig {
+            distribution: zipfian,
+            read: 0.0,
+            update: 0.0,
+            ..WorkloadConfig::default()
+        };
+        match preset {
+            Ycsb::A => WorkloadConfig {
+                read: 0.5,
+                update: 0.5,
+                ..base
+            },
+            Ycsb::B => WorkloadConfig {
+                read: 0.95,
+                update: 0.05,
+                ..base
+            },
+            Ycsb::C => WorkloadConfig { read: 1.0, ..base },
+            Ycsb::D => WorkloadConfig {
+                distribution: KeyDistribution::Latest(0.99),
+                read: 0.95,
+                insert: 0.05,
+                ..base
+            },
+            Ycsb::E => WorkloadConfig {
+                scan: 0.95,
+                insert: 0.05,
+                ..base
+            },
+            Ycsb::F => WorkloadConfig {
+                read: 0.5,
+                read_modify_write: 0.5,
+                ..base
+            },
+        }
+    }
+}
+
+/// `Operation` 一次操作及其访问的 key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Read(String),
+    Update(String),
+    Insert(String),
+    /// 依次读取编号连续的 key
+    Scan(Vec<String>),
+    ReadModifyWrite(String),
+}
+
+/// `Inserts` 插入的 key 的编号：生成插入操作时分配，写入完成后才发布
+/// 已发布的编号总是从 0 开始连续的，读和扫描只会选到已经写入的 key
+#[derive(Debug, Default)]
+struct Inserts {
+    next: AtomicU64,
+    published: AtomicU64,
+    // 已写入、但还有更小的编号没有写入的插入
+    done: Mutex<BTreeSet<u64>>,
+}
+
+impl Inserts {
+    fn reserve(&self) -> u64 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn publish(&self, i: u64) {
+        let mut done = self.done.lock();
+        done.insert(i);
+        // 只在持有锁时修改
+        let mut published = self.published.load(Ordering::Relaxed);
+        while done.remove(&published) {
+            published += 1;
+        }
+        self.published.store(published, Ordering::Release);
+    }
+
+    fn published(&self) -> u64 {
+        self.published.load(Ordering::Acquire)
+    }
+}
+
+/// `Workload` 由多个线程共享，记录已插入的 key 数
+#[derive(Debug, Clone)]
+pub struct Workload {
+    pub config: WorkloadConfig,
+    inserts: Arc<Inserts>,
+}
+
+impl Workload {
+    pub fn new(config: WorkloadConfig) -> Workload {
+        Workload {
+            config,
+            inserts: Arc::new(Inserts::default()),
+        }
+    }
+
+    pub fn ycsb(preset: Ycsb) -> Workload {
+        Workload::new(WorkloadConfig::ycsb(preset))
+    }
+
+    /// `records` 当前的 key 数，包括已写入的插入
+    pub fn records(&self) -> u64 {
+        self.config.records + self.inserts.published()
+    }
+
+    pub fn key(&self, i: u64) -> String {
+        format!("{}{}", self.config.key_prefix, i)
+    }
+
+    /// `client` 第 thread 个线程（共 threads 个）使用的操作生成器，seed 相同时生成的序列相同
+    pub fn client(&self, thread: usize, threads: usize, seed: u64) -> Client {
+        Client {
+            workload: self.clone(),
+            keys: KeyGenerator::new(
+                self.config.distribution.clone(),
+                self.config.records,
+                thread,
+                threads,
+            ),
+            rng: StdRng::seed_from_u64(seed ^ (thread as u64).wrapping_mul(0x9e3779b97f4a7c15)),
+            thread,
+            written: 0,
+            inserting: None,
+        }
+    }
+
+    /// `run` 在当前线程中随机交替使用 controllers，共执行 ops 次操作
+    /// 每个 controller 视为一个独立的线程选择 key
+    pub fn run<T: Clone + ToString + Sync + From<String> + 'static>(
+        &self,
+        controllers: &mut [CacheController<T>],
+        ops: usize,
+        seed: u64,
+    ) {
+        let n = controllers.len();
+        let mut clients: Vec<Client> = (0..n).map(|t| self.client(t, n, seed)).collect();
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..ops {
+            let t = rng.gen_range(0..n);
+            clients[t].execute(&mut controllers[t]);
+        }
+    }
+
+    /// `run_threads` 每个 controller 在各自的线程中执行 ops 次操作，结束后归还 controllers
+    pub fn run_threads<T: Clone + ToString + Sync + Send + From<String> + 'static>(
+        &self,
+        controllers: Vec<CacheController<T>>,
+        ops: usize,
+        seed: u64,
+    ) -> Vec<CacheController<T>> {
+        let n = controllers.len();
+        let handles: Vec<_> = controllers
+            .into_iter()
+            .enumerate()
+            .map(|(t, mut ct)| {
+                let mut client = self.client(t, n, seed);
+                thread::spawn(move || {
+                    for _ in 0..ops {
+                        client.execute(&mut ct);
+                    }
+                    ct
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    }
+}
+
+/// `Client` 一个线程的操作生成器
+pub struct Client {
+    workload: Workload,
+    keys: KeyGenerator,
+    rng: StdRng,
+    thread: usize,
+    // 已写入的次数，用于生成写入的值
+    written: u64,
+    // 当前插入操作分配的编号，complete 时发布
+    inserting: Option<u64>,
+}
+
+impl Client {
+    fn next_index(&mut self) -> u64 {
+        let records = self.workload.records();
+        self.keys.next(&mut self.rng, records)
+    }
+
+    fn next_key(&mut self) -> String {
+        let i = self.next_index();
+        self.workload.key(i)
+    }
+
+    /// `next` 按配置的比例选择下一次操作
+    /// 插入的 key 在该操作 `complete` 之后才会被其他操作选到
+    pub fn next_operation(&mut self) -> Operation {
+        let c = &self.workload.config;
+        let (read, update, insert, scan) = (c.read, c.update, c.insert, c.scan);
+        let max_scan_length = c.max_scan_length.max(1);
+
+        let mut p: f64 = self.rng.gen();
+        let mut pick = |w: f64| {
+            p -= w;
+            p < 0.0
+        };
+        if pick(read) {
+            Operation::Read(self.next_key())
+        } else if pick(update) {
+            Operation::Update(self.next_key())
+        } else if pick(insert) {
+            let i = self.workload.inserts.reserve();
+            self.inserting = Some(i);
+            Operation::Insert(self.workload.key(self.workload.config.records + i))
+        } else if pick(scan) {
+            let start = self.next_index();
+            let len = self.rng.gen_range(1..=max_scan_length);
+            let records = self.workload.records();
+            let keys = (start..(start + len).min(records))
+                .map(|i| self.workload.key(i))
+                .collect();
+            Operation::Scan(keys)
+        } else {
+            Operation::ReadModifyWrite(self.next_key())
+        }
+    }
+
+    fn value(&mut self) -> String {
+        self.written += 1;
+        format!("{}-{}", self.thread, self.written)
+    }
+
+    /// `accesses` 把 op 分解为依次执行的读写，写入的值在此时生成，读操作的值为空
+    pub fn accesses(&mut self, op: &Operation) -> Vec<(AccessOp, String, String)> {
+        match op {
+            Operation::Read(key) => vec![(AccessOp::Get, key.clone(), String::new())],
+            Operation::Update(key) | Operation::Insert(key) => {
+                vec![(AccessOp::Set, key.clone(), self.value())]
+            }
+            Operation::Scan(keys) => keys
+                .iter()
+                .map(|key| (AccessOp::Get, key.clone(), String::new()))
+                .collect(),
+            Operation::ReadModifyWrite(key) => vec![
+                (AccessOp::Get, key.clone(), String::new()),
+                (AccessOp::Set, key.clone(), self.value()),
+            ],
+        }
+    }
+
+    /// `complete` 当前操作的读写都已执行，发布它插入的 key
+    pub fn complete(&mut self) {
+        if let Some(i) = self.inserting.take() {
+            self.workload.inserts.publish(i);
+        }
+    }
+
+    /// `execute` 生成下一次操作并在 ct 上执行
+    pub fn execute<T: Clone + ToString + Sync + From<String> + 'static>(
+        &mut self,
+        ct: &mut CacheController<T>,
+    ) -> Operation {
+        let op = self.next_operation();
+        for (access, key, value) in self.accesses(&op) {
+            match access {
+                AccessOp::Get => {
+                    ct.get(key);
+                }
+                AccessOp::Set => ct.set(key, value.into()),
+            }
+        }
+        self.complete();
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::db::DbSession;
+    use crate::{Directory, DirectoryConfig};
+    use parking_lot::RwLock;
+    use std::time::Duration;
+
+    fn mix(preset: Ycsb) -> [usize; 5] {
+        let workload = Workload::ycsb(preset);
+        let mut client = workload.client(0, 1, 7);
+        let mut counts = [0; 5];
+        for _ in 0..10_000 {
+            let i = match client.next_operation() {
+                Operation::Read(_) => 0,
+                Operation::Update(_) => 1,
+                Operation::Insert(_) => 2,
+                Operation::Scan(_) => 3,
+                Operation::ReadModifyWrite(_) => 4,
+            };
+            counts[i] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_presets() {
+        let within = |n: usize, expect: usize| n.abs_diff(expect) < 300;
+        let a = mix(Ycsb::A);
+        assert!(within(a[0], 5000) && within(a[1], 5000));
+        assert_eq!(mix(Ycsb::C), [10_000, 0, 0, 0, 0]);
+        let d = mix(Ycsb::D);
+        assert!(within(d[2], 500) && d[1] == 0);
+        let e = mix(Ycsb::E);
+        assert!(within(e[3], 9500) && within(e[2], 500));
+        let f = mix(Ycsb::F);
+        assert!(within(f[4], 5000));
+    }
+
+    #[test]
+    fn test_insert_and_scan() {
+        let config = WorkloadConfig {
+            records: 10,
+            insert: 0.5,
+            read: 0.0,
+            update: 0.0,
+            scan: 0.5,
+            max_scan_length: 20,
+            key_prefix: "k".to_string(),
+            ..WorkloadConfig::default()
+        };
+        let workload = Workload::new(config);
+        let mut c0 = workload.client(0, 2, 1);
+        let mut c1 = workload.client(1, 2, 1);
+        let mut inserted = Vec::new();
+        for _ in 0..100 {
+            for c in [&mut c0, &mut c1] {
+                match c.next_operation() {
+                    Operation::Insert(k) => inserted.push(k),
+                    Operation::Scan(keys) => {
+                        assert!(!keys.is_empty() && keys.len() <= 20);
+                        // 不超过当前的 key 数
+                        let last: u64 = keys.last().unwrap()[1..].parse().unwrap();
+                        assert!(last < workload.records());
+                    }
+                    op => panic!("unexpected {:?}", op),
+                }
+                c.complete();
+            }
+        }
+        // 两个线程插入的 key 互不相同且连续
+        inserted.sort_by_key(|k| k[1..].parse::<u64>().unwrap());
+        let expect: Vec<String> = (10..10 + inserted.len() as u64)
+            .map(|i| format!("k{}", i))
+            .collect();
+        assert_eq!(inserted, expect);
+    }
+
+    #[test]
+    fn test_insert_publish() {
+        let workload = Workload::new(WorkloadConfig {
+            records: 10,
+            insert: 1.0,
+            read: 0.0,
+            update: 0.0,
+            ..WorkloadConfig::default()
+        });
+        let mut c0 = workload.client(0, 2, 1);
+        let mut c1 = workload.client(1, 2, 1);
+        c0.next_operation();
+        c1.next_operation();
+        // 写入完成之前不可见，编号更小的插入完成之前更大的也不可见
+        assert_eq!(workload.records(), 10);
+        c1.complete();
+        assert_eq!(workload.records(), 10);
+        c0.complete();
+        assert_eq!(workload.records(), 12);
+    }
+
+    #[test]
+    fn test_run() {
+        let directory = Arc::new(RwLock::new(Directory::with_db(
+            DbSession::temporary().with_latency(Duration::ZERO),
+            DirectoryConfig::default(),
+        )));
+        let config = WorkloadConfig {
+            key_prefix: "workload.".to_string(),
+            ..WorkloadConfig::ycsb(Ycsb::A)
+        };
+        let workload = Workload::new(config);
+
+        let mut controllers: Vec<CacheController<String>> = (0..2)
+            .map(|_| CacheController::new(directory.clone()))
+            .collect();
+        workload.run(&mut controllers, 200, 1);
+        let controllers = workload.run_threads(controllers, 200, 2);
+        let ops: u32 = controllers.iter().map(|c| c.collect().1).sum();
+        assert_eq!(ops, 600);
+    }
+}
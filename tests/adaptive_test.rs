@@ -0,0 +1,189 @@
+use mymesi::adaptive::adaptive::{AdaptiveConfig, SharingPattern};
+use mymesi::db::db::DbSession;
+use mymesi::metrics::metrics::Registry;
+use mymesi::recording::recording::Recorder;
+use mymesi::recording::replay::replay;
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn directory(adaptive: bool) -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig {
+            adaptive: adaptive.then(AdaptiveConfig::default),
+            ..DirectoryConfig::default()
+        },
+    )))
+}
+
+fn controllers(directory: &Arc<RwLock<Directory>>, n: usize) -> Vec<CacheController<String>> {
+    (0..n)
+        .map(|_| CacheController::new(directory.clone()))
+        .collect()
+}
+
+/// 每个线程轮流读 key 后写回加一的值
+fn migrate(cts: &mut [CacheController<String>], key: &str, rounds: usize) {
+    for _ in 0..rounds {
+        for ct in cts.iter_mut() {
+            let n: u64 = ct.get(key.to_string()).parse().unwrap_or(0);
+            ct.set(key.to_string(), (n + 1).to_string());
+        }
+    }
+}
+
+/// 一个线程写，其他线程读每个新值
+fn produce(cts: &mut [CacheController<String>], key: &str, rounds: usize) {
+    for i in 0..rounds {
+        cts[0].set(key.to_string(), i.to_string());
+        for ct in cts[1..].iter_mut() {
+            assert_eq!(ct.get(key.to_string()), i.to_string());
+        }
+    }
+}
+
+fn traffic(directory: &Arc<RwLock<Directory>>) -> u64 {
+    let d = directory.read().stats();
+    d.messages() + 2 * (d.read_requests + d.write_requests)
+}
+
+/// `migratory_test` 轮流先读后写的 key 读时装入 Exclusive，之后的写不再 upgrade
+#[test]
+fn migratory_test() {
+    let run = |adaptive| {
+        let directory = directory(adaptive);
+        let mut cts = controllers(&directory, 4);
+        migrate(&mut cts, "m", 50);
+        assert_eq!(cts[0].get("m".to_string()), "200");
+        let upgrades: u64 = cts.iter().map(|ct| ct.stats().upgrades).sum();
+        (directory, upgrades)
+    };
+    let (default, default_upgrades) = run(false);
+    let (adaptive, adaptive_upgrades) = run(true);
+
+    let d = adaptive.read();
+    assert_eq!(d.pattern("m"), SharingPattern::Migratory);
+    let stats = d.adaptive_stats().unwrap();
+    assert_eq!(stats.switches_to_migratory, 1);
+    assert_eq!(stats.switches(), 1);
+    assert_eq!(stats.migratory_keys, 1);
+    assert!(stats.migratory_grants > 190, "{}", stats);
+    assert!(default.read().adaptive_stats().is_none());
+
+    assert!(default_upgrades > 190, "{}", default_upgrades);
+    assert!(adaptive_upgrades < 10, "{}", adaptive_upgrades);
+    assert!(traffic(&adaptive) * 3 < traffic(&default) * 2);
+}
+
+/// `producer_consumer_test` 只有一个写者的 key 改为写更新，消费者的读命中
+#[test]
+fn producer_consumer_test() {
+    let default = directory(false);
+    produce(&mut controllers(&default, 4), "p", 100);
+    let adaptive = directory(true);
+    let mut cts = controllers(&adaptive, 4);
+    produce(&mut cts, "p", 100);
+
+    let d = adaptive.read();
+    assert_eq!(d.pattern("p"), SharingPattern::ProducerConsumer);
+    let stats = d.adaptive_stats().unwrap();
+    assert_eq!(stats.switches_to_update, 1);
+    assert!(stats.update_writes > 90, "{}", stats);
+    assert!(d.stats().updates > 270);
+    assert!(cts[1].stats().read_hits > 90);
+    assert!(traffic(&adaptive) * 2 < traffic(&default));
+}
+
+/// `patterns_test` 同一个目录中不同 key 分别分类，分类和切换次数可以查询，也输出到 metrics
+#[test]
+fn patterns_test() {
+    let directory = directory(true);
+    let mut cts = controllers(&directory, 3);
+    migrate(&mut cts, "m", 10);
+    produce(&mut cts, "p", 10);
+    // 只读共享的 key 保持默认协议
+    for ct in cts.iter_mut() {
+        ct.get("r".to_string());
+    }
+
+    let patterns = directory.read().patterns();
+    let find = |key: &str| patterns.iter().find(|p| p.key == key).unwrap();
+    assert_eq!(find("m").pattern, SharingPattern::Migratory);
+    assert_eq!(find("p").pattern, SharingPattern::ProducerConsumer);
+    assert_eq!(find("r").pattern, SharingPattern::Other);
+    assert_eq!(find("r").switches, 0);
+
+    // 生产者-消费者变为多个写者后回到默认协议
+    for ct in cts.iter_mut() {
+        ct.set("p".to_string(), "x".to_string());
+    }
+    assert_eq!(directory.read().pattern("p"), SharingPattern::Other);
+    let stats = directory.read().adaptive_stats().unwrap();
+    assert_eq!(stats.switches_to_default, 1);
+    assert_eq!(directory.read().patterns()[1].switches, 2);
+
+    let registry = Registry::new();
+    registry.register_directory(&directory);
+    let text = registry.render();
+    assert!(text.contains("mesi_sharing_pattern_keys{pattern=\"migratory\"} 1"));
+    assert!(text.contains("mesi_protocol_switches_total{to=\"default\"} 1"));
+}
+
+/// `adaptive_replay_test` 自适应协议选择不改变读到的值
+#[test]
+fn adaptive_replay_test() {
+    let workload = Workload::new(WorkloadConfig {
+        records: 20,
+        key_prefix: "adaptive.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    });
+    let recorder = Arc::new(Recorder::new());
+    let mut cts = controllers(&directory(false), 4);
+    for ct in cts.iter_mut() {
+        ct.attach_recorder(recorder.clone());
+    }
+    workload.run(&mut cts, 4000, 11);
+    migrate(&mut cts, "m", 20);
+    produce(&mut cts, "p", 20);
+    let records = recorder.records();
+
+    let directory = directory(true);
+    let mut cts = controllers(&directory, 4);
+    let report = replay(&records, &mut cts);
+    assert_eq!(report.divergent_reads, 0);
+    let stats = directory.read().adaptive_stats().unwrap();
+    assert!(
+        stats.migratory_grants > 0 && stats.update_writes > 0,
+        "{}",
+        stats
+    );
+}
+
+/// `adaptive_multithread_test` 并发读写结束后，每个 key 仍然满足单写者多读者
+#[test]
+fn adaptive_multithread_test() {
+    let workload = Workload::new(WorkloadConfig {
+        records: 20,
+        key_prefix: "adaptive.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::F)
+    });
+    let directory = directory(true);
+    let cts = workload.run_threads(controllers(&directory, 4), 3000, 5);
+    let caches: Vec<_> = cts.iter().map(|ct| ct.collect_caches()).collect();
+    for i in 0..workload.records() {
+        let key = workload.key(i);
+        let copies: Vec<(Status, String)> = caches
+            .iter()
+            .filter_map(|c| c.get(&key).map(|c| (c.status.clone(), c.value.clone())))
+            .filter(|c| c.0 != Status::Invalid)
+            .collect();
+        if copies.len() > 1 {
+            assert!(copies.iter().all(|c| c.0 == Status::Shared), "{:?}", copies);
+            assert!(copies.iter().all(|c| c.1 == copies[0].1), "{:?}", copies);
+        }
+    }
+    assert!(directory.read().fenced().is_empty());
+}
@@ -0,0 +1,61 @@
+//! 各个测试共用的 proptest 策略，生成 `oracle::check` 的输入
+//!
+//! `scenario` 在公共的基础配置上叠加一个配置维度，例如协议、victim buffer 容量，
+//! 各个测试只需要给出该维度的取值以及如何把它写入 `OracleConfig`。
+use mymesi::oracle::oracle::{Op, OracleConfig, Step};
+use mymesi::{ConsistencyModel, ControllerConfig};
+use proptest::prelude::*;
+
+pub fn model() -> impl Strategy<Value = ConsistencyModel> {
+    prop_oneof![
+        Just(ConsistencyModel::SC),
+        Just(ConsistencyModel::TSO),
+        Just(ConsistencyModel::PSO),
+    ]
+}
+
+/// `config` controller 数量 1~6，缓存容量从 2 项到默认的 1024 项
+pub fn config() -> impl Strategy<Value = OracleConfig> {
+    (
+        1..=6usize,
+        prop::sample::select(vec![2usize, 4, 16, 1024]),
+        model(),
+    )
+        .prop_map(|(controllers, cache_size, model)| OracleConfig {
+            controllers,
+            controller: ControllerConfig {
+                model,
+                cache_size,
+                flush_size: cache_size / 2,
+                ..ControllerConfig::default()
+            },
+            ..OracleConfig::default()
+        })
+}
+
+/// `step` 读写之比为 3:1，key 为 0..keys
+pub fn step(controllers: usize, keys: usize) -> impl Strategy<Value = Step> {
+    let op = prop_oneof![
+        3 => Just(Op::Get),
+        1 => (0..1000u32).prop_map(|v| Op::Set(v.to_string())),
+    ];
+    (0..controllers, 0..keys, op).prop_map(|(thread, key, op)| Step {
+        thread,
+        key: key.to_string(),
+        op,
+    })
+}
+
+/// `scenario` 生成配置以及在该配置下的 (thread, op, key, value) 序列
+/// dimension 的取值由 apply 写入基础配置
+pub fn scenario<D, F>(dimension: D, apply: F) -> impl Strategy<Value = (OracleConfig, Vec<Step>)>
+where
+    D: Strategy,
+    F: Fn(&mut OracleConfig, D::Value),
+{
+    (config(), dimension, 1..=12usize).prop_flat_map(move |(mut config, value, keys)| {
+        apply(&mut config, value);
+        let steps = prop::collection::vec(step(config.controllers, keys), 1..80);
+        (Just(config), steps)
+    })
+}
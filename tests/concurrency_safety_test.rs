@@ -1,3 +1,5 @@
+#![allow(unused_imports, clippy::clone_on_copy)]
+
 use mymesi::*;
 use parking_lot::RwLock;
 use rand_distr::Normal;
@@ -11,10 +13,22 @@ use std::{
 /// 通过查看 log 确认
 #[test]
 fn concurrency_safety_test() {
+    for protocol in Protocol::ALL {
+        sub_concurrency_safety_test(protocol);
+    }
+}
+
+fn sub_concurrency_safety_test(protocol: Protocol) {
     let n = 8;
     let round = 3000;
 
-    let directory = Arc::new(RwLock::new(Directory::new(&"./data/db".to_string())));
+    let directory = Arc::new(RwLock::new(Directory::with_config(
+        &format!("./data/db.{}", protocol),
+        DirectoryConfig {
+            protocol,
+            ..DirectoryConfig::default()
+        },
+    )));
     let barrier = Arc::new(Barrier::new(n));
 
     let mut handles = Vec::with_capacity(n);
@@ -1,8 +1,9 @@
+#![allow(unused_imports)]
+
+use mymesi::workload::generator::{KeyDistribution, KeyGenerator};
 use mymesi::*;
 use parking_lot::RwLock;
 use rand::Rng;
-use rand_distr::num_traits::ToPrimitive;
-use rand_distr::{Distribution, Normal};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
@@ -11,6 +12,12 @@ use std::time::Instant;
 /// 用于测试缓存一致性
 #[test]
 fn consistency_seq_test() {
+    for protocol in Protocol::ALL {
+        sub_consistency_seq_test(protocol);
+    }
+}
+
+fn sub_consistency_seq_test(protocol: Protocol) {
     let mut map: HashMap<String, String> = HashMap::new();
     let mut read_count: HashMap<String, i32> = HashMap::new();
     let mut write_count: HashMap<String, i32> = HashMap::new();
@@ -18,7 +25,13 @@ fn consistency_seq_test() {
     let n = 4; // 线程数
     let round = 10000; // 测试次数
 
-    let directory = Arc::new(RwLock::new(Directory::new(&"./data/db".to_string())));
+    let directory = Arc::new(RwLock::new(Directory::with_config(
+        &format!("./data/db.{}", protocol),
+        DirectoryConfig {
+            protocol,
+            ..DirectoryConfig::default()
+        },
+    )));
 
     let mut cache_controllers: Vec<CacheController<String>> = Vec::new();
     for _ in 0..n {
@@ -27,10 +40,11 @@ fn consistency_seq_test() {
 
     let start = Instant::now();
     let mut rng = rand::thread_rng();
+    let mut keys = KeyGenerator::new(KeyDistribution::Zipfian(0.99), 64, 0, 1);
     for i in 0..round {
         let op: i32 = rng.gen();
         let t_id = rng.gen_range(0..n);
-        let key = key_gen();
+        let key = keys.next(&mut rng, 64).to_string();
 
         if op % 4 != 0 {
             let val = cache_controllers[t_id].get(key.clone());
@@ -62,17 +76,5 @@ fn consistency_seq_test() {
     }
 
     println!("time cost: {:?} ms", start.elapsed().as_millis());
-    println!("consistency test passed");
-}
-
-/// `key_gen` 生成正态分布的随机数
-fn key_gen() -> String {
-    let normal: Normal<f64> = Normal::new(0.0, 15.0).unwrap();
-    normal
-        .sample(&mut rand::thread_rng())
-        .abs()
-        .floor()
-        .to_i64()
-        .unwrap()
-        .to_string()
+    println!("consistency test passed: {}", protocol);
 }
@@ -0,0 +1,161 @@
+use mymesi::db::db::DbSession;
+use mymesi::thread_socket::faulty_transport::{FaultConfig, FaultCount};
+use mymesi::*;
+use parking_lot::RwLock;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::Duration;
+
+fn faulty_directory(path: &str) -> Arc<RwLock<Directory>> {
+    let config = DirectoryConfig {
+        faults: FaultConfig {
+            delay: 0.05,
+            max_delay: Duration::from_millis(2),
+            reorder: 0.05,
+            duplicate: 0.05,
+            drop: 0.05,
+            seed: Some(2023),
+        },
+        retry_timeout: Duration::from_millis(5),
+        ..DirectoryConfig::default()
+    };
+    Arc::new(RwLock::new(Directory::with_config(
+        &path.to_string(),
+        config,
+    )))
+}
+
+/// `fenced_rejoin_test`
+/// 所有消息都丢失，共享者超时后被隔离；被隔离的线程之后读到的是其他线程写入的值，而不是过期的副本
+#[test]
+fn fenced_rejoin_test() {
+    let config = DirectoryConfig {
+        faults: FaultConfig {
+            drop: 1.0,
+            seed: Some(2023),
+            ..FaultConfig::default()
+        },
+        retry_timeout: Duration::from_millis(1),
+        max_retries: 2,
+        protocol: Protocol::WriteThrough,
+        ..DirectoryConfig::default()
+    };
+    let directory = Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        config,
+    )));
+    let mut reader: CacheController<String> = CacheController::new(directory.clone());
+    let mut writer: CacheController<String> = CacheController::new(directory.clone());
+    let key = "k".to_string();
+    assert_eq!(reader.get(key.clone()), "");
+
+    for (i, value) in ["new", "newer"].into_iter().enumerate() {
+        writer.set(key.clone(), value.to_string());
+        assert_eq!(directory.read().fenced(), vec![reader.thread_id]);
+        assert_eq!(reader.get(key.clone()), value);
+        assert!(directory.read().fenced().is_empty());
+        assert_eq!(reader.stats().rejoins, i as u64 + 1);
+    }
+}
+
+/// `fault_injection_seq_test`
+/// 在延迟、乱序、重复、丢包的 socket 上顺序读写，结果应与 HashMap 一致
+#[test]
+fn fault_injection_seq_test() {
+    let mut map: HashMap<String, String> = HashMap::new();
+
+    let n = 4;
+    let round = 3000;
+
+    let directory = faulty_directory("./data/db_fault_seq");
+    let mut cache_controllers: Vec<CacheController<String>> = Vec::new();
+    for _ in 0..n {
+        cache_controllers.push(CacheController::new(directory.clone()))
+    }
+
+    let mut rng = rand::thread_rng();
+    for i in 0..round {
+        let t_id = rng.gen_range(0..n);
+        let key = rng.gen_range(0..32).to_string();
+
+        if rng.gen_range(0..4) != 0 {
+            let val = cache_controllers[t_id].get(key.clone());
+            assert_eq!(
+                map.get(&key).cloned().unwrap_or_default(),
+                val,
+                "get key {:?} with error value",
+                key
+            )
+        } else {
+            cache_controllers[t_id].set(key.clone(), i.to_string());
+            map.insert(key, i.to_string());
+        }
+    }
+
+    // 所有 socket 上注入的故障都计入目录
+    let faults = directory.read().fault_count();
+    assert!(faults.dropped > 0 && faults.duplicated > 0, "{:?}", faults);
+    assert!(faults.reordered > 0 && faults.delayed > 0, "{:?}", faults);
+    directory.read().reset_stats();
+    assert_eq!(directory.read().fault_count(), FaultCount::default());
+}
+
+/// `fault_injection_multithread_test`
+/// 在故障 socket 上并发读写，结束后任一 key 至多有一个 Modified/Exclusive 副本
+#[test]
+fn fault_injection_multithread_test() {
+    let n = 4;
+    let round = 1000;
+
+    let directory = faulty_directory("./data/db_fault_multithread");
+    let barrier = Arc::new(Barrier::new(n));
+    let collect = Arc::new(Mutex::new(Vec::new()));
+
+    let mut handles = Vec::with_capacity(n);
+    for idx in 0..n {
+        let collect = collect.clone();
+        let b = barrier.clone();
+        let bl = directory.clone();
+
+        handles.push(thread::spawn(move || {
+            let mut ct: CacheController<String> = CacheController::new(bl);
+            b.wait();
+
+            let mut rng = rand::thread_rng();
+            for i in 0..round {
+                let key = rng.gen_range(0..64).to_string();
+                if idx % 2 == 0 {
+                    ct.set(key, (idx * 10 + i).to_string());
+                } else {
+                    ct.get(key);
+                }
+            }
+
+            collect.lock().unwrap().push(ct.collect_caches());
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap()
+    }
+
+    let caches_set = collect.lock().unwrap();
+    for i in 0..64 {
+        let status: Vec<Status> = caches_set
+            .iter()
+            .filter_map(|caches| caches.get(&i.to_string()).map(|c| c.status.clone()))
+            .collect();
+        if status.len() == 1 {
+            assert_ne!(
+                status[0],
+                Status::Shared,
+                "exclusive cache has status shared"
+            );
+        } else {
+            for s in status {
+                assert_eq!(s, Status::Shared, "shared caches has status of {:?}", s);
+            }
+        }
+    }
+}
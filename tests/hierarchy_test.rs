@@ -0,0 +1,194 @@
+use mymesi::db::db::DbSession;
+use mymesi::hierarchy::hierarchy::GlobalDirectory;
+use mymesi::llc::llc::{InclusionPolicy, LlcConfig};
+use mymesi::recording::recording::Recorder;
+use mymesi::recording::replay::replay;
+use mymesi::workload::generator::KeyDistribution;
+use mymesi::workload::workload::{Workload, WorkloadConfig};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// `machine` clusters 个集群，每个集群 per_cluster 个 CacheController，按集群顺序排列
+fn machine(
+    clusters: usize,
+    per_cluster: usize,
+) -> (Arc<GlobalDirectory>, Vec<CacheController<String>>) {
+    let global = GlobalDirectory::new(DbSession::temporary().with_latency(Duration::ZERO));
+    let directories: Vec<_> = (0..clusters)
+        .map(|_| global.cluster(DirectoryConfig::default()))
+        .collect();
+    let cts = directories
+        .iter()
+        .flat_map(|d| (0..per_cluster).map(|_| CacheController::new(d.clone())))
+        .collect();
+    (global, cts)
+}
+
+fn status(ct: &CacheController<String>, key: &str) -> Status {
+    ct.collect_caches()
+        .get(key)
+        .map_or(Status::Invalid, |c| c.status.clone())
+}
+
+/// `hierarchy_test` 两个集群各两个线程，集群之间的失效和数据传输单独计数
+#[test]
+fn hierarchy_test() {
+    let (global, mut cts) = machine(2, 2);
+    let x = "x".to_string();
+
+    // 集群 0 写，集群 1 读：集群 0 降级，数据跨集群传输
+    cts[0].set(x.clone(), "1".to_string());
+    assert_eq!(cts[2].get(x.clone()), "1");
+    assert_eq!(status(&cts[0], "x"), Status::Shared);
+    let stats = global.stats();
+    assert_eq!((stats.write_requests, stats.read_requests), (1, 1));
+    assert_eq!((stats.downgrades, stats.transfers), (1, 1));
+
+    // 集群 1 已有副本，再读只在集群内完成
+    assert_eq!(cts[3].get(x.clone()), "1");
+    assert_eq!(global.stats().read_requests, 1);
+
+    // 集群 0 的另一个线程写，集群 1 的所有副本失效
+    cts[1].set(x.clone(), "2".to_string());
+    assert_eq!(status(&cts[2], "x"), Status::Invalid);
+    assert_eq!(status(&cts[3], "x"), Status::Invalid);
+    assert_eq!(status(&cts[0], "x"), Status::Invalid);
+    assert_eq!(global.stats().invalidations, 1);
+    assert_eq!(cts[3].get(x.clone()), "2");
+
+    // 另一个集群也有副本时不能装入 Exclusive，否则写时不会通知全局目录
+    let y = "y".to_string();
+    assert_eq!(cts[0].get(y.clone()), "");
+    assert_eq!(status(&cts[0], "y"), Status::Exclusive);
+    cts[2].get(y.clone());
+    assert_eq!(status(&cts[0], "y"), Status::Shared);
+    assert_eq!(status(&cts[2], "y"), Status::Shared);
+    cts[0].set(y.clone(), "3".to_string());
+    assert_eq!(status(&cts[2], "y"), Status::Invalid);
+    assert_eq!(cts[2].get(y.clone()), "3");
+
+    let entry = global.entry("y").unwrap();
+    assert_eq!(entry.sharers, vec![0, 1]);
+    assert!(!entry.exclusive);
+    println!("{}", global.stats());
+}
+
+/// `hierarchy_replay_test` 同一个访问序列在单个目录和两级目录上读到的值相同
+#[test]
+fn hierarchy_replay_test() {
+    let directory = Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig::default(),
+    )));
+    let recorder = Arc::new(Recorder::new());
+    let mut cts: Vec<CacheController<String>> = (0..4)
+        .map(|_| CacheController::new(directory.clone()))
+        .collect();
+    for ct in cts.iter_mut() {
+        ct.attach_recorder(recorder.clone());
+    }
+    let workload = Workload::new(WorkloadConfig {
+        records: 100,
+        ..WorkloadConfig::default()
+    });
+    workload.run(&mut cts, 4000, 11);
+
+    let (_, mut cts) = machine(2, 2);
+    let report = replay(&recorder.records(), &mut cts);
+    assert_eq!(report.divergent_reads, 0);
+}
+
+/// `hierarchy_locality_test` 每个集群访问自己的 key 时，集群之间几乎没有消息
+#[test]
+fn hierarchy_locality_test() {
+    let run = |distribution| {
+        let (global, cts) = machine(4, 2);
+        let workload = Workload::new(WorkloadConfig {
+            records: 400,
+            distribution,
+            read: 0.5,
+            update: 0.5,
+            ..WorkloadConfig::default()
+        });
+        let cts = workload.run_threads(cts, 1000, 3);
+
+        // 所有集群中每个 key 至多有一个 Exclusive 或 Modified 副本，Shared 副本的值相同
+        for i in 0..workload.records() {
+            let key = workload.key(i);
+            let copies: Vec<_> = cts
+                .iter()
+                .filter_map(|ct| {
+                    ct.collect_caches()
+                        .get(&key)
+                        .map(|c| (c.status.clone(), c.value.clone()))
+                })
+                .collect();
+            if copies.len() > 1 {
+                assert!(copies.iter().all(|c| c.0 == Status::Shared), "{:?}", copies);
+                assert!(copies.iter().all(|c| c.1 == copies[0].1), "{:?}", copies);
+            }
+        }
+        global.stats()
+    };
+    // 线程之间不共享 key，只有第一次访问需要全局目录
+    let local = run(KeyDistribution::Partitioned { overlap: 0.0 });
+    // 同一集群的两个线程共享 key，相邻集群之间也有少量共享
+    let clustered = run(KeyDistribution::Partitioned { overlap: 1.0 });
+    let uniform = run(KeyDistribution::Uniform);
+    println!("{}\n{}\n{}", local, clustered, uniform);
+    assert_eq!(local.invalidations + local.downgrades, 0);
+    assert_eq!(local.read_requests + local.write_requests, 400);
+    assert!(clustered.locality() > uniform.locality());
+    assert!(uniform.transfers > clustered.transfers);
+    assert!(uniform.messages() > 4 * local.messages());
+}
+
+/// `hierarchy_lease_test` 集群目录各有自己的逻辑时间，不支持租约协议
+#[test]
+#[should_panic(expected = "lease protocol")]
+fn hierarchy_lease_test() {
+    let global = GlobalDirectory::new(DbSession::temporary().with_latency(Duration::ZERO));
+    global.cluster(DirectoryConfig {
+        protocol: Protocol::Lease,
+        ..DirectoryConfig::default()
+    });
+}
+
+/// `hierarchy_llc_test` 集群的 LLC 中的脏数据在其他集群读写前写回 db，过期的数据被丢弃
+#[test]
+fn hierarchy_llc_test() {
+    let global = GlobalDirectory::new(DbSession::temporary().with_latency(Duration::ZERO));
+    let config = DirectoryConfig {
+        llc: Some(LlcConfig {
+            policy: InclusionPolicy::NonInclusive,
+            ..LlcConfig::default()
+        }),
+        ..DirectoryConfig::default()
+    };
+    let mut cts: Vec<_> = (0..2)
+        .map(|_| {
+            CacheController::with_config(
+                global.cluster(config.clone()),
+                ControllerConfig {
+                    cache_size: 2,
+                    flush_size: 1,
+                    ..ControllerConfig::default()
+                },
+            )
+        })
+        .collect();
+
+    // 集群 0 写 x 之后淘汰，x 只在集群 0 的 LLC 中
+    cts[0].set("x".to_string(), "1".to_string());
+    for i in 0..4 {
+        cts[0].get(format!("y.{}", i));
+    }
+    assert_eq!(status(&cts[0], "x"), Status::Invalid);
+    assert_eq!(cts[1].get("x".to_string()), "1");
+
+    // 集群 1 写 x，集群 0 的 LLC 中的旧值不再被读到
+    cts[1].set("x".to_string(), "2".to_string());
+    assert_eq!(cts[0].get("x".to_string()), "2");
+}
@@ -0,0 +1,196 @@
+use mymesi::db::db::DbSession;
+use mymesi::litmus::litmus::LitmusTest;
+use mymesi::litmus::runner;
+use mymesi::llc::llc::{InclusionPolicy, LlcConfig};
+use mymesi::recording::recording::Recorder;
+use mymesi::recording::replay::replay;
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn config(lease: u64) -> DirectoryConfig {
+    DirectoryConfig {
+        protocol: Protocol::Lease,
+        lease,
+        ..DirectoryConfig::default()
+    }
+}
+
+fn directory(lease: u64) -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        config(lease),
+    )))
+}
+
+fn controllers(directory: &Arc<RwLock<Directory>>, n: usize) -> Vec<CacheController<String>> {
+    let config = ControllerConfig {
+        cache_size: 32,
+        flush_size: 8,
+        ..ControllerConfig::default()
+    };
+    (0..n)
+        .map(|_| CacheController::with_config(directory.clone(), config.clone()))
+        .collect()
+}
+
+/// `lease_expiry_test` 租约内的读命中；写不发送失效消息，其他线程的副本随之过期
+#[test]
+fn lease_expiry_test() {
+    let directory = directory(4);
+    let mut cts = controllers(&directory, 2);
+    let key = "x".to_string();
+
+    cts[0].set(key.clone(), "1".to_string());
+    assert_eq!(cts[1].get(key.clone()), "1");
+    assert_eq!(cts[1].get(key.clone()), "1");
+    assert_eq!(cts[1].stats().read_hits, 1);
+
+    cts[0].set(key.clone(), "2".to_string());
+    assert_eq!(cts[1].get(key.clone()), "2");
+    assert_eq!(cts[1].stats().lease_expirations, 1);
+
+    // 只读的副本在目录处理了 lease 个其他请求之后过期
+    for i in 0..5 {
+        cts[0].get(format!("y.{}", i));
+    }
+    assert_eq!(cts[1].get(key.clone()), "2");
+    assert_eq!(cts[1].stats().lease_expirations, 2);
+    assert_eq!(cts[1].stats().read_misses, 3);
+
+    let caches = cts[1].collect_caches();
+    let cache = caches.get("x").unwrap();
+    assert_eq!(cache.status, Status::Shared);
+    assert!(cache.lease >= directory.read().now());
+
+    let stats = directory.read().stats();
+    assert_eq!(stats.messages(), 0);
+    assert_eq!(stats.lease_jumps, 1);
+    assert_eq!(cts[0].stats().invalidations_sent, 0);
+    let (sharers, _) = directory.read().sharers();
+    assert!(sharers.iter().all(|(_, s)| s.is_empty()));
+}
+
+/// `lease_exclusive_llc_test` 租约已过期的副本可能是旧值，淘汰时不放入 Exclusive 的 LLC
+#[test]
+fn lease_exclusive_llc_test() {
+    let directory = Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig {
+            llc: Some(LlcConfig {
+                policy: InclusionPolicy::Exclusive,
+                ..LlcConfig::default()
+            }),
+            ..config(64)
+        },
+    )));
+    let config = ControllerConfig {
+        cache_size: 2,
+        flush_size: 0,
+        ..ControllerConfig::default()
+    };
+    let mut cts: Vec<_> = (0..2)
+        .map(|_| CacheController::with_config(directory.clone(), config.clone()))
+        .collect();
+    let x = "x".to_string();
+
+    assert_eq!(cts[0].get(x.clone()), "");
+    cts[1].set(x.clone(), "1".to_string());
+    // 淘汰 x 的旧副本
+    cts[0].get("y".to_string());
+    assert!(!cts[0].collect_caches().contains_key("x"));
+    assert!(!directory.read().llc_contains("x"));
+    assert_eq!(cts[0].get(x.clone()), "1");
+}
+
+/// `lease_litmus_test` SC 下不应观测到不允许的结果，TSO 和 PSO 下的结果在模型允许的范围内
+#[test]
+fn lease_litmus_test() {
+    let mut paths: Vec<_> = fs::read_dir("./litmus")
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+    let tests: Vec<_> = paths
+        .iter()
+        .map(|p| LitmusTest::from_file(p).unwrap())
+        .collect();
+
+    let directory = directory(16);
+    for test in &tests {
+        let report = runner::run(test, directory.clone(), ConsistencyModel::SC, 200);
+        assert!(report.forbidden().is_empty(), "{}", report);
+        assert!(!report.exists_observed(), "{}", report);
+    }
+    for model in [ConsistencyModel::TSO, ConsistencyModel::PSO] {
+        for test in &tests {
+            let report = runner::run(test, directory.clone(), model, 100);
+            assert!(report.violations().is_empty(), "{}", report);
+        }
+    }
+    assert_eq!(directory.read().stats().messages(), 0);
+}
+
+/// `lease_replay_test` 同一个访问序列在租约协议下读到的值与写失效相同
+#[test]
+fn lease_replay_test() {
+    let workload = Workload::new(WorkloadConfig {
+        records: 100,
+        key_prefix: "lease.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    });
+    let recorder = Arc::new(Recorder::new());
+    let invalidate = Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig::default(),
+    )));
+    let mut cts = controllers(&invalidate, 4);
+    for ct in cts.iter_mut() {
+        ct.attach_recorder(recorder.clone());
+    }
+    workload.run(&mut cts, 4000, 7);
+    let records = recorder.records();
+
+    let mut cts = controllers(&directory(32), 4);
+    let report = replay(&records, &mut cts);
+    assert_eq!(report.divergent_reads, 0);
+}
+
+/// `lease_multithread_test` 并发读写结束后，租约未到期的副本都是最新的值
+#[test]
+fn lease_multithread_test() {
+    let workload = Workload::new(WorkloadConfig {
+        records: 50,
+        key_prefix: "lease.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    });
+    let directory = directory(64);
+    let cts = workload.run_threads(controllers(&directory, 4), 2000, 3);
+    let caches: Vec<_> = cts.iter().map(|ct| ct.collect_caches()).collect();
+    let now = directory.read().now();
+    let mut reader = controllers(&directory, 1).pop().unwrap();
+    for i in 0..workload.records() {
+        let key = workload.key(i);
+        let copies: Vec<(Status, String)> = caches
+            .iter()
+            .filter_map(|c| {
+                c.get(&key)
+                    .map(|c| (c.status.clone(), c.value.clone(), c.lease))
+            })
+            .filter(|c| c.2 >= now)
+            .map(|c| (c.0, c.1))
+            .collect();
+        let latest = reader.get(key);
+        assert!(copies.iter().all(|c| c.0 == Status::Shared), "{:?}", copies);
+        assert!(
+            copies.iter().all(|c| c.1 == latest),
+            "{:?} {}",
+            copies,
+            latest
+        );
+    }
+    assert!(directory.read().fenced().is_empty());
+}
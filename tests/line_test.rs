@@ -0,0 +1,168 @@
+mod common;
+
+use mymesi::db::db::DbSession;
+use mymesi::experiment::experiment::{run, ExperimentConfig, ExperimentResult};
+use mymesi::oracle::oracle;
+use mymesi::workload::generator::KeyDistribution;
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+use parking_lot::RwLock;
+use proptest::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn directory(line_size: usize) -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig {
+            line_size,
+            ..DirectoryConfig::default()
+        },
+    )))
+}
+
+fn controllers(directory: &Arc<RwLock<Directory>>, n: usize) -> Vec<CacheController<String>> {
+    (0..n)
+        .map(|_| CacheController::new(directory.clone()))
+        .collect()
+}
+
+fn status(ct: &CacheController<String>, key: &str) -> Status {
+    let caches = ct.collect_caches();
+    let status = caches.get(key).map(|c| c.status.clone());
+    status.unwrap_or(Status::Invalid)
+}
+
+/// `false_sharing_test` 写同一行的其他 slot 使读者的整行失效，计为伪共享
+#[test]
+fn false_sharing_test() {
+    let run = |line_size| {
+        let mut cts = controllers(&directory(line_size), 2);
+        cts[0].get("k1".to_string());
+        cts[1].set("k2".to_string(), "x".to_string());
+        assert_eq!(cts[0].get("k1".to_string()), "");
+        assert_eq!(cts[0].get("k2".to_string()), "x");
+        // 写读者用过的 slot 是真共享
+        cts[1].set("k1".to_string(), "y".to_string());
+        assert_eq!(cts[0].get("k1".to_string()), "y");
+        cts[0].stats()
+    };
+
+    let lines = run(4);
+    assert_eq!(lines.invalidations_received, 2);
+    assert_eq!(lines.false_sharing_invalidations, 1);
+    let keys = run(1);
+    assert_eq!(keys.invalidations_received, 1);
+    assert_eq!(keys.false_sharing_invalidations, 0);
+}
+
+/// `line_fill_test` 已持有该行时读其他 slot 不需要广播，降级和失效作用于整行
+#[test]
+fn line_fill_test() {
+    let directory = directory(4);
+    let mut cts = controllers(&directory, 2);
+    let (c0, c1) = cts.split_at_mut(1);
+    let (c0, c1) = (&mut c0[0], &mut c1[0]);
+
+    c0.get("k0".to_string());
+    c0.get("k1".to_string());
+    assert_eq!(status(c0, "k1"), Status::Exclusive);
+    assert_eq!(directory.read().stats().messages(), 0);
+
+    c0.set("k1".to_string(), "1".to_string());
+    assert_eq!(c1.get("k2".to_string()), "");
+    assert_eq!(status(c0, "k0"), Status::Shared);
+    assert_eq!(status(c0, "k1"), Status::Shared);
+    assert_eq!(c0.stats().write_backs, 1);
+    assert_eq!(c1.get("k1".to_string()), "1");
+    assert_eq!(status(c1, "k1"), Status::Shared);
+    assert_eq!(directory.read().stats().remote_reads, 1);
+
+    c0.set("k0".to_string(), "2".to_string());
+    assert_eq!(status(c1, "k1"), Status::Invalid);
+    assert_eq!(status(c1, "k2"), Status::Invalid);
+    let stats = c1.stats();
+    assert_eq!(stats.invalidations_received, 1);
+    assert_eq!(stats.false_sharing_invalidations, 1);
+
+    let (sharers, _) = directory.read().sharers();
+    assert_eq!(sharers.len(), 1);
+    assert_eq!(sharers[0], ("k#0".to_string(), vec![0]));
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    /// `line_oracle_test` 按行维护一致性时，任意顺序的读写结果都应与参照 HashMap 一致
+    #[test]
+    fn line_oracle_test((config, steps) in common::scenario(
+        prop::sample::select(vec![2usize, 4, 8]),
+        |c, line_size| c.directory.line_size = line_size,
+    )) {
+        prop_assert_eq!(oracle::check(&config, &steps), Ok(()));
+    }
+}
+
+/// `line_multithread_test` 并发读写结束后，每一行仍然满足单写者多读者
+#[test]
+fn line_multithread_test() {
+    let workload = Workload::new(WorkloadConfig {
+        records: 64,
+        key_prefix: "line.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    });
+    let directory = directory(4);
+    let lines = directory.read().lines();
+    let cts = workload.run_threads(controllers(&directory, 4), 3000, 5);
+
+    // 行 -> 持有有效 slot 的 (线程, 状态)
+    let mut holders: HashMap<String, Vec<(usize, Status)>> = HashMap::new();
+    for (t, ct) in cts.iter().enumerate() {
+        for c in ct.collect_caches().iter() {
+            if c.status != Status::Invalid {
+                let line = lines.line(c.key());
+                holders.entry(line).or_default().push((t, c.status.clone()));
+            }
+        }
+    }
+    for (line, h) in &holders {
+        let exclusive = h.iter().any(|c| c.1 != Status::Shared);
+        if exclusive {
+            assert!(h.iter().all(|c| c.0 == h[0].0), "{} {:?}", line, h);
+        }
+    }
+    let stats: u64 = cts
+        .iter()
+        .map(|ct| ct.stats().false_sharing_invalidations)
+        .sum();
+    assert!(stats > 0);
+    assert!(directory.read().fenced().is_empty());
+}
+
+/// `line_experiment_test` 实验结果按行大小报告伪共享失效
+#[test]
+fn line_experiment_test() {
+    let false_sharing = |line_size| {
+        let config = ExperimentConfig {
+            threads: vec![4],
+            read_ratios: vec![0.5],
+            distributions: vec![KeyDistribution::Uniform],
+            line_size,
+            ops: 200,
+            records: 32,
+            ..ExperimentConfig::default()
+        };
+        let results = run(&config, |_| {});
+        let n = results[0].stats.false_sharing_invalidations;
+        let column = ExperimentResult::CSV_HEADER
+            .split(',')
+            .position(|h| h == "false_sharing")
+            .unwrap();
+        let csv = results[0].csv();
+        assert_eq!(csv.split(',').nth(column), Some(n.to_string().as_str()));
+        n
+    };
+    assert_eq!(false_sharing(1), 0);
+    assert!(false_sharing(8) > 0);
+}
@@ -0,0 +1,62 @@
+use mymesi::litmus::litmus::LitmusTest;
+use mymesi::litmus::runner;
+use mymesi::*;
+use parking_lot::RwLock;
+use std::fs;
+use std::sync::Arc;
+
+fn litmus_tests() -> Vec<LitmusTest> {
+    let mut paths: Vec<_> = fs::read_dir("./litmus")
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+    assert_eq!(paths.len(), 7);
+
+    paths
+        .iter()
+        .map(|p| LitmusTest::from_file(p).unwrap())
+        .collect()
+}
+
+/// `litmus_test`
+/// 运行 litmus 目录下的所有测试，CacheController 的写操作同步可见，
+/// 不应观测到顺序一致性下不允许的结果
+#[test]
+fn litmus_test() {
+    let directory = Arc::new(RwLock::new(Directory::new(&"./data/db_litmus".to_string())));
+
+    for test in litmus_tests() {
+        let report = runner::run(&test, directory.clone(), ConsistencyModel::SC, 200);
+        println!("{}\n", report);
+
+        assert_eq!(report.histogram.values().sum::<usize>(), 200);
+        assert!(report.forbidden().is_empty(), "{}", report);
+        assert!(!report.exists_observed(), "{}", report);
+    }
+}
+
+/// `litmus_relaxed_test`
+/// TSO 和 PSO 下观测到的结果都应在对应模型允许的范围内，
+/// 且 TSO 下能观测到 SB 的写-读乱序
+#[test]
+fn litmus_relaxed_test() {
+    let directory = Arc::new(RwLock::new(Directory::new(
+        &"./data/db_litmus_relaxed".to_string(),
+    )));
+
+    for model in [ConsistencyModel::TSO, ConsistencyModel::PSO] {
+        for test in litmus_tests() {
+            let report = runner::run(&test, directory.clone(), model, 100);
+            println!("{}\n", report);
+
+            assert!(report.violations().is_empty(), "{}", report);
+            if test.name == "SB" {
+                assert!(report.exists_observed(), "{}", report);
+            }
+            if test.name == "SB+fences" {
+                assert!(!report.exists_observed(), "{}", report);
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use mymesi::db::db::DbSession;
+use mymesi::llc::llc::{InclusionPolicy, LlcConfig, Replacement};
+use mymesi::recording::recording::Recorder;
+use mymesi::recording::replay::replay;
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn directory(llc: Option<LlcConfig>, slices: usize) -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig {
+            slices,
+            llc,
+            ..DirectoryConfig::default()
+        },
+    )))
+}
+
+fn llc(capacity: usize, policy: InclusionPolicy, replacement: Replacement) -> Option<LlcConfig> {
+    Some(LlcConfig {
+        capacity,
+        policy,
+        replacement,
+        ..LlcConfig::default()
+    })
+}
+
+/// 私有缓存很小，淘汰频繁
+fn controllers(directory: &Arc<RwLock<Directory>>, n: usize) -> Vec<CacheController<String>> {
+    let config = ControllerConfig {
+        cache_size: 16,
+        flush_size: 4,
+        ..ControllerConfig::default()
+    };
+    (0..n)
+        .map(|_| CacheController::with_config(directory.clone(), config.clone()))
+        .collect()
+}
+
+fn workload() -> Workload {
+    Workload::new(WorkloadConfig {
+        records: 100,
+        key_prefix: "llc.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    })
+}
+
+fn status(ct: &CacheController<String>, key: &str) -> Status {
+    let caches = ct.collect_caches();
+    let status = caches.get(key).map(|c| c.status.clone());
+    status.unwrap_or(Status::Invalid)
+}
+
+/// `llc_replay_test` 每种包含策略和替换策略下，同一个访问序列读到的值与没有 LLC 时相同
+#[test]
+fn llc_replay_test() {
+    let recorder = Arc::new(Recorder::new());
+    let mut cts = controllers(&directory(None, 1), 4);
+    for ct in cts.iter_mut() {
+        ct.attach_recorder(recorder.clone());
+    }
+    workload().run(&mut cts, 4000, 3);
+    drop(cts);
+    let records = recorder.records();
+
+    let policies = [
+        InclusionPolicy::Inclusive,
+        InclusionPolicy::Exclusive,
+        InclusionPolicy::NonInclusive,
+    ];
+    let replacements = [Replacement::Lru, Replacement::Fifo, Replacement::Random];
+    for policy in policies {
+        for replacement in replacements {
+            let directory = directory(llc(24, policy, replacement), 2);
+            let mut cts = controllers(&directory, 4);
+            let report = replay(&records, &mut cts);
+            assert_eq!(report.divergent_reads, 0, "{} {}", policy, replacement);
+
+            let stats = directory.read().llc_stats().unwrap();
+            assert!(stats.hits > 0, "{} {}\n{}", policy, replacement, stats);
+            assert!(stats.evictions > 0, "{} {}\n{}", policy, replacement, stats);
+            assert_eq!(
+                stats.back_invalidations > 0,
+                policy == InclusionPolicy::Inclusive
+            );
+        }
+    }
+}
+
+/// `llc_db_reads_test` LLC 装得下全部 key 时，每个 key 只从 db 读一次
+#[test]
+fn llc_db_reads_test() {
+    let run = |llc: Option<LlcConfig>| {
+        let directory = directory(llc, 1);
+        let mut cts = controllers(&directory, 4);
+        workload().run(&mut cts, 4000, 3);
+        let d = directory.read();
+        (d.stats().db_reads, d.llc_stats())
+    };
+
+    let (without, none) = run(None);
+    assert!(none.is_none());
+    let (with, stats) = run(llc(128, InclusionPolicy::Inclusive, Replacement::Lru));
+    let stats = stats.unwrap();
+    assert!(with <= workload().records(), "{}", with);
+    assert!(without > 4 * with, "{} {}", without, with);
+    assert_eq!(stats.misses, with);
+    assert_eq!(stats.evictions, 0);
+}
+
+/// `llc_back_invalidation_test` Inclusive 的 LLC 淘汰一项时，私有缓存中的副本失效，Modified 的副本写回
+#[test]
+fn llc_back_invalidation_test() {
+    let directory = directory(llc(2, InclusionPolicy::Inclusive, Replacement::Lru), 1);
+    let mut cts = controllers(&directory, 2);
+    let (c0, c1) = cts.split_at_mut(1);
+    let (c0, c1) = (&mut c0[0], &mut c1[0]);
+
+    c0.set("x".to_string(), "1".to_string());
+    c1.get("y".to_string());
+    assert_eq!(status(c0, "x"), Status::Modified);
+    assert!(directory.read().llc_contains("x"));
+
+    // 装入 z 淘汰最久未访问的 x
+    c1.get("z".to_string());
+    assert!(!directory.read().llc_contains("x"));
+    assert_eq!(status(c0, "x"), Status::Invalid);
+    assert_eq!(status(c1, "y"), Status::Exclusive);
+    let stats = directory.read().llc_stats().unwrap();
+    assert_eq!(stats.back_invalidations, 1);
+    assert_eq!(stats.back_invalidation_messages, 1);
+
+    // 写回的数据没有丢失，再次读取又淘汰了 y
+    assert_eq!(c1.get("x".to_string()), "1");
+    assert_eq!(status(c1, "y"), Status::Invalid);
+    let sharers = directory.read().sharers().0;
+    assert!(sharers.iter().all(|(k, v)| k != "y" || v.is_empty()));
+}
+
+/// `llc_multithread_test` 并发回收与正常请求交错时不会死锁，每个 key 仍然满足单写者多读者
+#[test]
+fn llc_multithread_test() {
+    let directory = directory(llc(16, InclusionPolicy::Inclusive, Replacement::Random), 2);
+    let cts = workload().run_threads(controllers(&directory, 4), 3000, 9);
+    let caches: Vec<_> = cts.iter().map(|ct| ct.collect_caches()).collect();
+
+    for i in 0..workload().records() {
+        let key = workload().key(i);
+        let copies: Vec<(Status, String)> = caches
+            .iter()
+            .filter_map(|c| c.get(&key).map(|c| (c.status.clone(), c.value.clone())))
+            .filter(|c| c.0 != Status::Invalid)
+            .collect();
+        if copies.len() > 1 {
+            assert!(copies.iter().all(|c| c.0 == Status::Shared), "{:?}", copies);
+            assert!(copies.iter().all(|c| c.1 == copies[0].1), "{:?}", copies);
+        }
+    }
+    let d = directory.read();
+    assert!(d.fenced().is_empty());
+    assert!(d.llc_stats().unwrap().back_invalidation_messages > 0);
+}
@@ -0,0 +1,195 @@
+use mymesi::db::db::DbSession;
+use mymesi::*;
+use parking_lot::RwLock;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LATENCY: Duration = Duration::from_millis(20);
+
+/// `directory` db 中 key.i 的值为 i，每次读写 db 需要 latency
+fn directory(keys: usize, latency: Duration) -> Arc<RwLock<Directory>> {
+    let db = DbSession::temporary().with_latency(Duration::ZERO);
+    for i in 0..keys {
+        db.set(format!("key.{}", i), i.to_string());
+    }
+    Arc::new(RwLock::new(Directory::with_db(
+        db.with_latency(latency),
+        DirectoryConfig::default(),
+    )))
+}
+
+fn controller(directory: &Arc<RwLock<Directory>>, mshrs: usize) -> CacheController<String> {
+    CacheController::with_config(
+        directory.clone(),
+        ControllerConfig {
+            mshrs,
+            ..ControllerConfig::default()
+        },
+    )
+}
+
+/// `wait_all` 取走所有非阻塞读的结果
+fn wait_all(ct: &mut CacheController<String>) -> HashMap<u64, String> {
+    let mut values = HashMap::new();
+    while let Some((ticket, v)) = ct.wait() {
+        values.insert(ticket, v);
+    }
+    values
+}
+
+/// `mshr_overlap_test` 多个 MSHR 时缺失的延迟相互重叠，只有一个时依次等待
+#[test]
+fn mshr_overlap_test() {
+    let run = |mshrs| {
+        let directory = directory(8, LATENCY);
+        let mut ct = controller(&directory, mshrs);
+        let start = Instant::now();
+        let tickets: Vec<_> = (0..8).map(|i| ct.issue(format!("key.{}", i))).collect();
+        let values = wait_all(&mut ct);
+        let elapsed = start.elapsed();
+        for (i, ticket) in tickets.iter().enumerate() {
+            assert_eq!(values[ticket], i.to_string());
+        }
+        let stats = ct.stats();
+        assert_eq!(stats.read_misses, 8);
+        assert_eq!(ct.outstanding(), 0);
+        (elapsed, stats, ct.mshr_occupancy().max())
+    };
+
+    let (overlapped, stats, peak) = run(8);
+    assert!(overlapped < LATENCY * 4, "{:?}", overlapped);
+    assert_eq!(stats.mshr_stalls, 0);
+    assert_eq!(peak, 8);
+    let (serial, stats, peak) = run(1);
+    assert!(serial >= LATENCY * 8, "{:?}", serial);
+    assert_eq!(stats.mshr_stalls, 7);
+    assert_eq!(peak, 1);
+}
+
+/// `mshr_merge_test` 同一个 key 上的读合并到一个 MSHR，目录只收到一次读请求
+#[test]
+fn mshr_merge_test() {
+    let directory = directory(2, LATENCY);
+    let mut ct = controller(&directory, 4);
+    let tickets: HashSet<_> = (0..3).map(|_| ct.issue("key.1".to_string())).collect();
+    assert_eq!(tickets.len(), 3);
+    assert_eq!(ct.outstanding(), 1);
+
+    let values = wait_all(&mut ct);
+    assert_eq!(values.keys().copied().collect::<HashSet<_>>(), tickets);
+    assert!(values.values().all(|v| v == "1"));
+    let stats = ct.stats();
+    assert_eq!(stats.read_misses, 1);
+    assert_eq!(stats.mshr_merges, 2);
+    assert_eq!(directory.read().stats().read_requests, 1);
+
+    // 完成之后的读命中缓存
+    let ticket = ct.issue("key.1".to_string());
+    assert_eq!(ct.poll(), [(ticket, "1".to_string())]);
+    assert_eq!(ct.stats().read_hits, 1);
+}
+
+/// `mshr_out_of_order_test` 命中的读先于之前发出的缺失完成
+#[test]
+fn mshr_out_of_order_test() {
+    let directory = directory(2, LATENCY);
+    let mut ct = controller(&directory, 4);
+    assert_eq!(ct.get("key.1".to_string()), "1");
+
+    let miss = ct.issue("key.0".to_string());
+    let hit = ct.issue("key.1".to_string());
+    assert!(miss < hit);
+    assert_eq!(ct.wait(), Some((hit, "1".to_string())));
+    assert_eq!(ct.wait(), Some((miss, "0".to_string())));
+    assert_eq!(ct.wait(), None);
+}
+
+/// `mshr_stall_test` MSHR 用完时 issue 等待，被占用的 MSHR 数不超过上限
+#[test]
+fn mshr_stall_test() {
+    let directory = directory(5, LATENCY);
+    let mut ct = controller(&directory, 2);
+    let start = Instant::now();
+    for i in 0..5 {
+        ct.issue(format!("key.{}", i));
+    }
+    // 发出第五个缺失时至少已经完成了三个
+    assert!(start.elapsed() >= LATENCY * 2);
+    assert_eq!(wait_all(&mut ct).len(), 5);
+    // 前两个缺失几乎同时完成时，第四个缺失不需要等待
+    let stalls = ct.stats().mshr_stalls;
+    assert!((2..=3).contains(&stalls), "{}", stalls);
+    assert_eq!(ct.mshr_occupancy().max(), 2);
+    assert_eq!(ct.mshr_occupancy().count(), 5);
+}
+
+/// `mshr_conflict_test` 之后对同一个 key 的写等待缺失完成，缺失装入的旧值不会覆盖新值
+#[test]
+fn mshr_conflict_test() {
+    let directory = directory(1, LATENCY);
+    let mut cts: Vec<_> = (0..2).map(|_| controller(&directory, 4)).collect();
+    let ticket = cts[0].issue("key.0".to_string());
+    cts[0].set("key.0".to_string(), "new".to_string());
+    assert_eq!(cts[0].wait(), Some((ticket, "0".to_string())));
+    assert_eq!(cts[0].get("key.0".to_string()), "new");
+    assert_eq!(cts[1].get("key.0".to_string()), "new");
+}
+
+/// `mshr_multithread_test` 并发的非阻塞读和写结束后，有效的副本都是最新的值
+#[test]
+fn mshr_multithread_test() {
+    let keys = 32;
+    let directory = directory(keys, Duration::ZERO);
+    let barrier = Arc::new(Barrier::new(4));
+    let handles: Vec<_> = (0..4)
+        .map(|t| {
+            let mut ct = controller(&directory, 4);
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(t);
+                barrier.wait();
+                for i in 0..300 {
+                    for _ in 0..4 {
+                        ct.issue(format!("key.{}", rng.gen_range(0..keys)));
+                    }
+                    let key = format!("key.{}", rng.gen_range(0..keys));
+                    ct.set(key, format!("{}.{}", t, i));
+                    if rng.gen_ratio(1, 2) {
+                        wait_all(&mut ct);
+                    }
+                }
+                wait_all(&mut ct);
+                ct
+            })
+        })
+        .collect();
+    let cts: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    let mut reader = controller(&directory, 1);
+    for i in 0..keys {
+        let key = format!("key.{}", i);
+        let latest = reader.get(key.clone());
+        let copies: Vec<_> = cts
+            .iter()
+            .filter_map(|ct| {
+                let caches = ct.collect_caches();
+                let c = caches.get(&key)?;
+                (c.status != Status::Invalid).then(|| c.value.clone())
+            })
+            .collect();
+        assert!(
+            copies.iter().all(|v| *v == latest),
+            "{:?} {}",
+            copies,
+            latest
+        );
+    }
+    let merged = cts.iter().map(|ct| ct.stats().mshr_merges).sum::<u64>();
+    assert!(merged > 0);
+    assert!(directory.read().fenced().is_empty());
+    assert!(directory.read().transactions().is_empty());
+}
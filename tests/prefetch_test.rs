@@ -0,0 +1,161 @@
+mod common;
+
+use mymesi::db::db::DbSession;
+use mymesi::oracle::oracle;
+use mymesi::prefetch::prefetch::{PrefetchConfig, Prefetcher};
+use mymesi::*;
+use parking_lot::RwLock;
+use proptest::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn directory() -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig::default(),
+    )))
+}
+
+fn controller(
+    directory: &Arc<RwLock<Directory>>,
+    prefetch: Option<PrefetchConfig>,
+) -> CacheController<String> {
+    CacheController::with_config(
+        directory.clone(),
+        ControllerConfig {
+            prefetch,
+            ..ControllerConfig::default()
+        },
+    )
+}
+
+/// `scan` 按顺序读 keys，返回读缺失数
+fn scan(ct: &mut CacheController<String>, keys: impl Iterator<Item = String>) -> u64 {
+    let misses = ct.stats().read_misses;
+    for key in keys {
+        ct.get(key);
+    }
+    ct.stats().read_misses - misses
+}
+
+/// `next_n_test` 顺序扫描时只有第一次读缺失，预取的项都被用上
+#[test]
+fn next_n_test() {
+    let directory = directory();
+    let keys = || (0..200).map(|i| format!("scan.{}", i));
+    assert_eq!(scan(&mut controller(&directory, None), keys()), 200);
+
+    let directory = self::directory();
+    let mut ct = controller(&directory, Some(PrefetchConfig::NextN(2)));
+    assert_eq!(scan(&mut ct, keys()), 1);
+    let stats = ct.stats();
+    // 最后两个 key 预取了却没有读
+    assert_eq!(stats.prefetches, 201);
+    assert_eq!(stats.prefetch_hits, 199);
+    assert!(stats.prefetch_accuracy() > 0.99, "{}", stats);
+    assert!(stats.prefetch_coverage() > 0.99, "{}", stats);
+    assert_eq!(stats.prefetch_messages, 0);
+    // 预取是 GetS，没有其他共享者时也装入 Shared
+    let caches = ct.collect_caches();
+    assert_eq!(caches.get("scan.100").unwrap().status, Status::Shared);
+
+    // 清零之前预取的项不计入之后的预取命中
+    ct.reset_stats();
+    ct.get("scan.200".to_string());
+    let stats = ct.stats();
+    assert_eq!((stats.prefetches, stats.prefetch_hits), (1, 0));
+    assert!(stats.prefetch_accuracy() <= 1.0);
+}
+
+/// `stride_test` 步长确定之后的读都命中，不同前缀的访问流互不干扰
+#[test]
+fn stride_test() {
+    let directory = directory();
+    let mut ct = controller(&directory, Some(PrefetchConfig::Stride(2)));
+    let keys = (0..100).flat_map(|i| [format!("a.{}", i * 3), format!("b.{}", 1000 - i * 5)]);
+    assert_eq!(scan(&mut ct, keys), 6);
+    let stats = ct.stats();
+    assert!(stats.prefetch_accuracy() > 0.95, "{}", stats);
+    assert_eq!(stats.prefetch_hits, 194);
+}
+
+/// `markov_test` 重复出现的访问序列在被其他线程的写失效后，由预取重新装入
+/// 预取从持有 Modified 副本的线程取数据，产生额外的一致性消息
+#[test]
+fn markov_test() {
+    let run = |prefetch| {
+        let directory = directory();
+        let mut reader = controller(&directory, prefetch);
+        let mut writer = controller(&directory, None);
+        let order: Vec<String> = (0..50).map(|i| format!("m{}", i * 7 % 50)).collect();
+        let mut misses = 0;
+        for round in 0..5 {
+            for key in &order {
+                writer.set(key.clone(), round.to_string());
+            }
+            misses = scan(&mut reader, order.iter().cloned());
+            // 通过缓存检查值，额外的读会改变 Markov 学到的后继
+            let caches = reader.collect_caches();
+            assert_eq!(caches.get("m43").unwrap().value, round.to_string());
+        }
+        let stats = directory.read().stats();
+        (misses, reader.stats(), stats)
+    };
+
+    let (demand, _, without) = run(None);
+    assert_eq!(demand, 50);
+    let (misses, stats, with) = run(Some(PrefetchConfig::Markov(1)));
+    assert_eq!(misses, 1);
+    assert!(stats.prefetch_coverage() > 0.75, "{}", stats);
+    assert!(stats.prefetch_messages > 150, "{}", stats);
+    // 预取只是把读提前，消息数没有增加
+    assert_eq!(with.messages(), without.messages());
+}
+
+/// `Fixed` 总是预取同一组 key
+struct Fixed(Vec<String>);
+
+impl Prefetcher for Fixed {
+    fn access(&mut self, _key: &str, _hit: bool) -> Vec<String> {
+        self.0.clone()
+    }
+}
+
+/// `wasted_prefetch_test` 没有用上的预取降低准确率，并使持有 Modified 副本的线程降级
+#[test]
+fn wasted_prefetch_test() {
+    let directory = directory();
+    let mut writer = controller(&directory, None);
+    let mut reader = controller(&directory, None);
+    reader.attach_prefetcher(Box::new(Fixed(vec!["hot".to_string()])));
+
+    for i in 0..10 {
+        writer.set("hot".to_string(), i.to_string());
+        reader.get(format!("cold.{}", i));
+    }
+    let stats = reader.stats();
+    assert_eq!(stats.prefetches, 10);
+    assert_eq!(stats.prefetch_hits, 0);
+    assert_eq!(stats.prefetch_accuracy(), 0.0);
+    assert_eq!(stats.prefetch_messages, 10);
+    // 每次写都要使预取的副本失效，写变成了 upgrade
+    assert_eq!(writer.stats().invalidations_sent, 9);
+    assert_eq!(writer.stats().write_backs, 10);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    /// `prefetch_oracle_test` 预取不改变任意顺序的读写读到的值
+    #[test]
+    fn prefetch_oracle_test((config, steps) in common::scenario(
+        prop::sample::select(vec![
+            PrefetchConfig::NextN(2),
+            PrefetchConfig::Stride(2),
+            PrefetchConfig::Markov(2),
+        ]),
+        |c, prefetch| c.controller.prefetch = Some(prefetch),
+    )) {
+        prop_assert_eq!(oracle::check(&config, &steps), Ok(()));
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use mymesi::adaptive::adaptive::AdaptiveConfig;
+use mymesi::llc::llc::{InclusionPolicy, LlcConfig, Replacement};
+use mymesi::oracle::oracle;
+use mymesi::*;
+use proptest::prelude::*;
+
+/// `protocol` 四种协议，以及在失效协议上按共享模式自适应
+fn protocol() -> impl Strategy<Value = (Protocol, Option<AdaptiveConfig>)> {
+    let adaptive = AdaptiveConfig {
+        history: 8,
+        min_accesses: 2,
+        ..AdaptiveConfig::default()
+    };
+    prop::sample::select(vec![
+        (Protocol::Invalidate, None),
+        (Protocol::WriteThrough, None),
+        (Protocol::Update, None),
+        (Protocol::Lease, None),
+        (Protocol::Invalidate, Some(adaptive)),
+    ])
+}
+
+/// `llc` 不使用 LLC，或者容量很小、三种包含关系和替换方式的 LLC
+fn llc() -> impl Strategy<Value = Option<LlcConfig>> {
+    let policy = prop::sample::select(vec![
+        InclusionPolicy::Inclusive,
+        InclusionPolicy::Exclusive,
+        InclusionPolicy::NonInclusive,
+    ]);
+    let replacement = prop::sample::select(vec![
+        Replacement::Lru,
+        Replacement::Fifo,
+        Replacement::Random,
+    ]);
+    prop::option::of((policy, replacement, 4..=16usize).prop_map(
+        |(policy, replacement, capacity)| LlcConfig {
+            capacity,
+            policy,
+            replacement,
+            ..LlcConfig::default()
+        },
+    ))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    /// `oracle_property_test`
+    /// 任意配置下任意顺序的读写，结果都应与参照 HashMap 一致
+    /// 配置覆盖协议及租约长度、LLC、目录分片数以及集群数
+    #[test]
+    fn oracle_property_test((config, steps) in common::scenario(
+        (
+            protocol(),
+            prop::sample::select(vec![0u64, 4, 64]),
+            llc(),
+            prop::sample::select(vec![1usize, 2, 4]),
+            prop::sample::select(vec![0usize, 2, 3]),
+        ),
+        |c, ((protocol, adaptive), lease, llc, slices, clusters)| {
+            c.directory.protocol = protocol;
+            c.directory.adaptive = adaptive;
+            c.directory.lease = lease;
+            c.directory.llc = llc;
+            c.directory.slices = slices;
+            // 集群目录不支持租约协议
+            c.clusters = if protocol == Protocol::Lease { 0 } else { clusters };
+        },
+    )) {
+        prop_assert_eq!(oracle::check(&config, &steps), Ok(()));
+    }
+}
@@ -0,0 +1,212 @@
+use mymesi::db::db::DbSession;
+use mymesi::experiment::experiment::{compare, run, ExperimentConfig};
+use mymesi::recording::recording::Recorder;
+use mymesi::recording::replay::replay;
+use mymesi::workload::generator::KeyDistribution;
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn directory(protocol: Protocol) -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig {
+            protocol,
+            ..DirectoryConfig::default()
+        },
+    )))
+}
+
+fn controllers(directory: &Arc<RwLock<Directory>>, n: usize) -> Vec<CacheController<String>> {
+    let config = ControllerConfig {
+        cache_size: 32,
+        flush_size: 8,
+        ..ControllerConfig::default()
+    };
+    (0..n)
+        .map(|_| CacheController::with_config(directory.clone(), config.clone()))
+        .collect()
+}
+
+fn status(ct: &CacheController<String>, key: &str) -> Option<(Status, String)> {
+    let caches = ct.collect_caches();
+    let cache = caches.get(key);
+    cache.map(|c| (c.status.clone(), c.value.clone()))
+}
+
+/// `write_update_test` 写共享的 key 时其他副本被更新而不是失效，之后的读仍然命中
+#[test]
+fn write_update_test() {
+    let directory = directory(Protocol::Update);
+    let mut cts = controllers(&directory, 3);
+    let key = "x".to_string();
+    cts[0].get(key.clone());
+    cts[1].get(key.clone());
+
+    cts[0].set(key.clone(), "1".to_string());
+    assert_eq!(
+        status(&cts[0], "x"),
+        Some((Status::Shared, "1".to_string()))
+    );
+    assert_eq!(
+        status(&cts[1], "x"),
+        Some((Status::Shared, "1".to_string()))
+    );
+    assert_eq!(cts[1].get(key.clone()), "1");
+    assert_eq!(cts[1].stats().read_hits, 1);
+    assert_eq!(cts[1].stats().updates_received, 1);
+    assert_eq!(cts[0].stats().updates_sent, 1);
+    // 写入了 db，第三个线程读缺失时读到新值
+    assert_eq!(cts[2].get(key.clone()), "1");
+
+    // 写缺失也会更新其他副本
+    cts[2].set(key.clone(), "2".to_string());
+    for ct in &cts {
+        assert_eq!(status(ct, "x"), Some((Status::Shared, "2".to_string())));
+    }
+    let stats = directory.read().stats();
+    assert_eq!(stats.updates, 3);
+    assert_eq!(stats.remote_writes, 0);
+
+    // 没有其他共享者时在本地修改
+    cts[0].set("y".to_string(), "1".to_string());
+    cts[0].set("y".to_string(), "2".to_string());
+    assert_eq!(
+        status(&cts[0], "y"),
+        Some((Status::Modified, "2".to_string()))
+    );
+    assert_eq!(cts[0].stats().write_hits, 1);
+    assert_eq!(cts[1].get("y".to_string()), "2");
+}
+
+/// `write_through_test` 每次写都经过目录写入 db 并使其他副本失效，写缺失不分配缓存项
+#[test]
+fn write_through_test() {
+    let directory = directory(Protocol::WriteThrough);
+    let mut cts = controllers(&directory, 2);
+    let key = "x".to_string();
+
+    cts[0].set(key.clone(), "1".to_string());
+    assert_eq!(status(&cts[0], "x"), None);
+    assert_eq!(cts[1].get(key.clone()), "1");
+    assert_eq!(cts[0].get(key.clone()), "1");
+
+    cts[0].set(key.clone(), "2".to_string());
+    assert_eq!(
+        status(&cts[0], "x"),
+        Some((Status::Shared, "2".to_string()))
+    );
+    assert!(status(&cts[1], "x").is_none());
+    assert_eq!(cts[1].get(key.clone()), "2");
+
+    let stats = cts[0].stats();
+    assert_eq!((stats.write_hits, stats.write_misses), (1, 1));
+    assert_eq!(stats.invalidations_sent, 1);
+    assert_eq!(stats.write_backs, 0);
+    let d = directory.read().stats();
+    assert_eq!(d.db_writes, 2);
+    assert_eq!(d.write_requests, 2);
+}
+
+/// `protocol_replay_test` 同一个访问序列在三种协议下读到的值相同
+#[test]
+fn protocol_replay_test() {
+    let workload = Workload::new(WorkloadConfig {
+        records: 100,
+        key_prefix: "protocol.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    });
+    let recorder = Arc::new(Recorder::new());
+    let mut cts = controllers(&directory(Protocol::Invalidate), 4);
+    for ct in cts.iter_mut() {
+        ct.attach_recorder(recorder.clone());
+    }
+    workload.run(&mut cts, 4000, 7);
+    let records = recorder.records();
+
+    for protocol in [Protocol::WriteThrough, Protocol::Update] {
+        let mut cts = controllers(&directory(protocol), 4);
+        let report = replay(&records, &mut cts);
+        assert_eq!(report.divergent_reads, 0, "{}", protocol);
+    }
+}
+
+/// `protocol_multithread_test` 并发读写结束后，每个 key 的所有有效副本的值相同
+#[test]
+fn protocol_multithread_test() {
+    let workload = Workload::new(WorkloadConfig {
+        records: 50,
+        key_prefix: "protocol.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    });
+    for protocol in [Protocol::WriteThrough, Protocol::Update] {
+        let directory = directory(protocol);
+        let cts = workload.run_threads(controllers(&directory, 4), 2000, 3);
+        let caches: Vec<_> = cts.iter().map(|ct| ct.collect_caches()).collect();
+        for i in 0..workload.records() {
+            let key = workload.key(i);
+            let copies: Vec<(Status, String)> = caches
+                .iter()
+                .filter_map(|c| c.get(&key).map(|c| (c.status.clone(), c.value.clone())))
+                .filter(|c| c.0 != Status::Invalid)
+                .collect();
+            assert!(copies.iter().all(|c| c.1 == copies[0].1), "{:?}", copies);
+            if protocol == Protocol::WriteThrough {
+                assert!(copies.iter().all(|c| c.0 != Status::Modified));
+            }
+            if copies.len() > 1 {
+                assert!(copies.iter().all(|c| c.0 == Status::Shared), "{:?}", copies);
+            }
+        }
+        assert!(directory.read().fenced().is_empty());
+    }
+}
+
+/// `producer_consumer_test` 一个线程写、其他线程读每个新值时，写更新的消息数少于写失效
+#[test]
+fn producer_consumer_test() {
+    let traffic = |protocol| {
+        let directory = directory(protocol);
+        let mut cts = controllers(&directory, 4);
+        for i in 0..200 {
+            let key = format!("slot.{}", i % 4);
+            cts[0].set(key.clone(), i.to_string());
+            for ct in cts[1..].iter_mut() {
+                assert_eq!(ct.get(key.clone()), i.to_string());
+            }
+        }
+        let mut stats = cts[1].stats();
+        stats.merge(&cts[2].stats());
+        let d = directory.read().stats();
+        (
+            d.messages() + 2 * (d.read_requests + d.write_requests),
+            stats,
+        )
+    };
+    let (invalidate, consumers) = traffic(Protocol::Invalidate);
+    let (update, updated) = traffic(Protocol::Update);
+    let (through, _) = traffic(Protocol::WriteThrough);
+    assert!(update * 2 < invalidate, "{} {}", update, invalidate);
+    assert!(through < invalidate, "{} {}", through, invalidate);
+    assert!(updated.read_hits > consumers.read_hits);
+    assert_eq!(updated.read_misses, 8);
+
+    // 实验输出中按协议比较消息数
+    let config = ExperimentConfig {
+        threads: vec![2],
+        read_ratios: vec![0.9],
+        distributions: vec![KeyDistribution::Uniform],
+        protocols: vec![Protocol::Invalidate, Protocol::Update],
+        ops: 50,
+        records: 20,
+        ..ExperimentConfig::default()
+    };
+    let results = run(&config, |_| {});
+    assert_eq!(results.len(), 2);
+    assert!(results[1].csv().starts_with("uniform,update,"));
+    let table = compare(&results);
+    assert_eq!(table.lines().count(), 3);
+    assert!(table.lines().nth(1).unwrap().ends_with("1.00x"));
+}
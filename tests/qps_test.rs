@@ -1,12 +1,14 @@
+#![allow(clippy::clone_on_copy)]
+
+use mymesi::stats::stats::Latencies;
+use mymesi::workload::generator::KeyDistribution;
+use mymesi::workload::workload::{Workload, WorkloadConfig};
 use mymesi::*;
 use parking_lot::RwLock;
-use rand_distr::num_traits::ToPrimitive;
-use rand_distr::{Distribution, Normal};
 use std::ops::Add;
 use std::sync::{Arc, Barrier};
 use std::thread;
 use std::time::Instant;
-use rand::Rng;
 
 #[test]
 fn qps_test() {
@@ -21,48 +23,48 @@ fn qps_bench(n: i32, round: i32) {
         &"./data/db".to_string().add(n.to_string().as_str()),
     )));
     let barrier = Arc::new(Barrier::new(n as usize));
+    // 每个线程集中访问自己的 100 个 key，与相邻线程部分重叠
+    let workload = Workload::new(WorkloadConfig {
+        records: (n * 100) as u64,
+        distribution: KeyDistribution::Partitioned { overlap: 0.6 },
+        read: 6.0 / 7.0,
+        update: 1.0 / 7.0,
+        key_prefix: "".to_string(),
+        ..WorkloadConfig::default()
+    });
 
     let mut handles = Vec::with_capacity(n as usize);
     for i in 0..n {
         let b = barrier.clone();
         let idx = i.clone();
         let bl = directory.clone();
+        let mut client = workload.client(idx as usize, n as usize, rand::random());
 
         let handle = thread::spawn(move || {
-            let mut ct = CacheController::new(bl);
-            let normal: Normal<f64> = Normal::new((idx * 100) as f64, 80 as f64).unwrap();
-            let mut rng = rand::thread_rng();
+            let mut ct: CacheController<String> = CacheController::new(bl);
             let round = if idx == 0 {
                 round * (n + 1)
             } else { round };
 
             b.wait();
-            for i in 0..round {
-                let key = normal
-                    .sample(&mut rand::thread_rng())
-                    .abs()
-                    .floor()
-                    .to_i64()
-                    .unwrap()
-                    .to_string();
-                let opt: u32 = rng.gen();
-                if opt % 7 == 0 {
-                    ct.set(key, (idx * 10 + i).to_string());
-                } else {
-                    ct.get(key);
-                }
+            for _ in 0..round {
+                client.execute(&mut ct);
             }
+            ct.latencies()
         });
 
         handles.push(handle);
     }
     let start = Instant::now();
+    let mut latencies = Latencies::default();
     for handle in handles {
-        handle.join().unwrap()
+        latencies.merge(&handle.join().unwrap());
     }
 
     println!(
         "test {:?} threads, total average qps: {:?}", n.clone(),
         ((((n * 2) * round) as f64) / (start.elapsed().as_secs_f64())) as i32
-    )
+    );
+    println!("{}", latencies);
+    println!("{}", directory.read().latencies());
 }
@@ -0,0 +1,113 @@
+use mymesi::db::db::DbSession;
+use mymesi::recording::recording::{self, AccessOp, Recorder};
+use mymesi::recording::replay::{replay, replay_threads, threads};
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn controllers(n: usize, config: ControllerConfig) -> Vec<CacheController<String>> {
+    let directory = Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig::default(),
+    )));
+    (0..n)
+        .map(|_| CacheController::with_config(directory.clone(), config.clone()))
+        .collect()
+}
+
+fn workload() -> Workload {
+    Workload::new(WorkloadConfig {
+        records: 50,
+        key_prefix: "replay.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    })
+}
+
+/// `replay_test` 记录一次顺序执行的负载，保存后在不同配置下重放，输入完全相同
+#[test]
+fn replay_test() {
+    let recorder = Arc::new(Recorder::new());
+    let mut cts = controllers(4, ControllerConfig::default());
+    for ct in cts.iter_mut() {
+        ct.attach_recorder(recorder.clone());
+    }
+    workload().run(&mut cts, 2000, 1);
+    assert_eq!(recorder.len(), 2000);
+
+    let path = std::env::temp_dir().join(format!("mymesi_replay_{}.trace", std::process::id()));
+    recorder.save(&path).unwrap();
+    let records = recording::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(records, recorder.records());
+
+    // 顺序一致性下按原顺序重放，每次读到的值都与记录相同
+    for config in [
+        ControllerConfig::default(),
+        ControllerConfig {
+            cache_size: 8,
+            flush_size: 2,
+            ..ControllerConfig::default()
+        },
+    ] {
+        let mut cts = controllers(4, config);
+        let again = Arc::new(Recorder::new());
+        for ct in cts.iter_mut() {
+            ct.attach_recorder(again.clone());
+        }
+        let report = replay(&records, &mut cts);
+        assert_eq!(report.ops, 2000);
+        assert_eq!(report.divergent_reads, 0);
+
+        // 线程号按第一次出现的顺序对应
+        let stream = |rs: &[recording::AccessRecord]| -> Vec<_> {
+            let threads = threads(rs);
+            rs.iter()
+                .map(|r| {
+                    let t = threads.iter().position(|t| *t == r.thread).unwrap();
+                    (t, r.op, r.key.clone(), r.value.clone())
+                })
+                .collect()
+        };
+        assert_eq!(stream(&again.records()), stream(&records));
+    }
+
+    // TSO 下写操作延迟可见，但写入的序列不变，最终每个 key 的值相同
+    let config = ControllerConfig {
+        model: ConsistencyModel::TSO,
+        ..ControllerConfig::default()
+    };
+    let mut cts = controllers(4, config);
+    replay(&records, &mut cts);
+    let last = records
+        .iter()
+        .rev()
+        .find(|r| r.op == AccessOp::Set)
+        .unwrap();
+    assert_eq!(cts[0].get(last.key.clone()), last.value);
+}
+
+/// `replay_threads_test` 多线程记录的负载按线程重放
+#[test]
+fn replay_threads_test() {
+    let recorder = Arc::new(Recorder::new());
+    let mut cts = controllers(4, ControllerConfig::default());
+    for ct in cts.iter_mut() {
+        ct.attach_recorder(recorder.clone());
+    }
+    workload().run_threads(cts, 500, 2);
+    let records = recorder.records();
+    assert_eq!(records.len(), 2000);
+
+    let (cts, report) =
+        replay_threads(&records, controllers(4, ControllerConfig::default()), false);
+    assert_eq!(report.ops, 2000);
+    let ops: u32 = cts.iter().map(|c| c.collect().1).sum();
+    assert_eq!(ops, 2000);
+
+    // 按记录的时间间隔重放，耗时不少于原来的跨度
+    let span = records.last().unwrap().timestamp - records[0].timestamp;
+    let (_, report) = replay_threads(&records, controllers(4, ControllerConfig::default()), true);
+    assert!(report.elapsed >= span);
+}
@@ -0,0 +1,187 @@
+use mymesi::llc::llc::LlcConfig;
+use mymesi::sim::cost::CostModel;
+use mymesi::sim::sim::{simulate, SimConfig};
+use mymesi::topology::topology::Topology;
+use mymesi::workload::generator::KeyDistribution;
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+
+fn workload(records: u64, read: f64) -> Workload {
+    Workload::new(WorkloadConfig {
+        records,
+        distribution: KeyDistribution::Uniform,
+        read,
+        update: 1.0 - read,
+        key_prefix: "sim.".to_string(),
+        ..WorkloadConfig::default()
+    })
+}
+
+/// `sim_cost_test` 单线程反复读同一个 key，只有第一次缺失
+#[test]
+fn sim_cost_test() {
+    let config = SimConfig {
+        threads: 1,
+        ops: 10,
+        ..SimConfig::default()
+    };
+    let report = simulate(&workload(1, 1.0), &config);
+    let cost = CostModel::default();
+    // 请求和带数据的回复各经过一条链路
+    let serialize = |bytes: u64| bytes.div_ceil(cost.link_bandwidth);
+    let miss = cost.l1_hit
+        + 2 * cost.network_hop
+        + serialize(cost.control_bytes)
+        + serialize(cost.data_bytes)
+        + cost.directory_lookup
+        + cost.dram_access;
+    assert_eq!(report.ops, 10);
+    assert_eq!(report.cycles, miss + 9 * cost.l1_hit);
+    assert_eq!(report.latency.max(), miss);
+    assert_eq!(report.stats.read_hits, 9);
+    assert_eq!(report.dram_bytes, cost.data_bytes);
+    assert_eq!(report.network_bytes, cost.control_bytes + cost.data_bytes);
+}
+
+/// `sim_deterministic_test` 相同的配置得到完全相同的结果，包括批量淘汰和 TSO 的随机写回
+#[test]
+fn sim_deterministic_test() {
+    for model in [ConsistencyModel::SC, ConsistencyModel::TSO] {
+        let config = SimConfig {
+            threads: 4,
+            ops: 2000,
+            seed: 3,
+            controller: ControllerConfig {
+                model,
+                cache_size: 16,
+                flush_size: 4,
+                ..ControllerConfig::default()
+            },
+            ..SimConfig::default()
+        };
+        let workload = || Workload::ycsb(Ycsb::A);
+        let a = simulate(&workload(), &config);
+        let b = simulate(&workload(), &config);
+        println!("{}", a);
+        assert_eq!(a, b);
+        assert_eq!(a.ops, 8000);
+    }
+}
+
+/// `sim_sharing_test` 写共享的 key 比只读的负载慢，消耗更多带宽
+#[test]
+fn sim_sharing_test() {
+    let config = SimConfig {
+        threads: 4,
+        ops: 2000,
+        ..SimConfig::default()
+    };
+    let read_only = simulate(&workload(16, 1.0), &config);
+    let shared = simulate(&workload(16, 0.5), &config);
+    println!("{}\n{}", read_only, shared);
+    assert!(read_only.stats.hit_ratio() > 0.99);
+    assert!(shared.cycles_per_op() > 5.0 * read_only.cycles_per_op());
+    assert!(shared.network_bytes > 10 * read_only.network_bytes);
+    assert!(shared.directory.remote_writes > 0);
+
+    // db 更慢时总周期数增加
+    let slow = SimConfig {
+        cost: CostModel {
+            dram_access: 2000,
+            ..CostModel::default()
+        },
+        ..config
+    };
+    assert!(simulate(&workload(16, 0.5), &slow).cycles > shared.cycles);
+}
+
+/// `sim_topology_test` 环和网格上消息经过更多跳，每条链路都有流量统计
+#[test]
+fn sim_topology_test() {
+    let run = |topology| {
+        let config = SimConfig {
+            threads: 8,
+            ops: 500,
+            topology,
+            ..SimConfig::default()
+        };
+        simulate(&workload(64, 0.5), &config)
+    };
+    let crossbar = run(Topology::Crossbar);
+    let ring = run(Topology::Ring);
+    let mesh = run(Topology::Mesh { width: 0 });
+    println!("{}\n{}\n{}", crossbar, ring, mesh);
+
+    // 交叉开关上每条消息只经过一条链路，每个控制器与目录之间各有两条
+    let messages: u64 = crossbar.links.iter().map(|l| l.1.messages).sum();
+    let bytes: u64 = crossbar.links.iter().map(|l| l.1.bytes).sum();
+    assert_eq!(crossbar.links.len(), 16);
+    assert_eq!(bytes, crossbar.network_bytes);
+    assert!(messages >= 2 * (crossbar.directory.read_requests + crossbar.directory.write_requests));
+
+    // 环上相邻节点之间的链路转发了其他节点的消息
+    let ring_bytes: u64 = ring.links.iter().map(|l| l.1.bytes).sum();
+    assert!(ring_bytes > ring.network_bytes);
+    assert!(ring
+        .links
+        .iter()
+        .all(|((a, b), _)| (a + 1) % 9 == *b || (b + 1) % 9 == *a));
+    assert!(ring.cycles_per_op() > crossbar.cycles_per_op());
+    assert!(mesh.cycles_per_op() > crossbar.cycles_per_op());
+    let (_, busiest) = ring.busiest_link().unwrap();
+    assert!(busiest.utilization(ring.cycles) > 0.0);
+}
+
+/// `sim_slices_test` 目录分片越多，目录和 db 的排队越少
+#[test]
+fn sim_slices_test() {
+    let run = |slices| {
+        let config = SimConfig {
+            threads: 8,
+            ops: 500,
+            directory: DirectoryConfig {
+                slices,
+                ..DirectoryConfig::default()
+            },
+            ..SimConfig::default()
+        };
+        simulate(&workload(256, 0.5), &config)
+    };
+    let one = run(1);
+    let four = run(4);
+    println!("{}\n{}", one, four);
+    assert!(four.throughput() > 2.0 * one.throughput());
+    // 交叉开关上每个控制器与每个分片之间各有两条链路
+    assert_eq!(four.links.len(), 2 * 8 * 4);
+}
+
+/// `sim_llc_test` 私有缓存放不下的 key 命中 LLC，不再访问 db
+#[test]
+fn sim_llc_test() {
+    let run = |llc| {
+        let config = SimConfig {
+            threads: 2,
+            ops: 2000,
+            controller: ControllerConfig {
+                cache_size: 16,
+                flush_size: 4,
+                ..ControllerConfig::default()
+            },
+            directory: DirectoryConfig {
+                llc,
+                ..DirectoryConfig::default()
+            },
+            ..SimConfig::default()
+        };
+        simulate(&workload(64, 1.0), &config)
+    };
+    let none = run(None);
+    let llc = run(Some(LlcConfig::default()));
+    println!("{}\n{}", none, llc);
+    let cost = CostModel::default();
+    assert_eq!(llc.directory.db_reads, 64);
+    assert_eq!(llc.dram_bytes, 64 * cost.data_bytes);
+    assert_eq!(none.dram_bytes, none.directory.db_reads * cost.data_bytes);
+    assert!(none.dram_bytes > 10 * llc.dram_bytes);
+    assert!(none.cycles_per_op() > 2.0 * llc.cycles_per_op());
+}
@@ -0,0 +1,81 @@
+use mymesi::db::db::DbSession;
+use mymesi::recording::recording::Recorder;
+use mymesi::recording::replay::replay;
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn directory(slices: usize) -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig {
+            slices,
+            ..DirectoryConfig::default()
+        },
+    )))
+}
+
+fn controllers(directory: &Arc<RwLock<Directory>>, n: usize) -> Vec<CacheController<String>> {
+    (0..n)
+        .map(|_| CacheController::new(directory.clone()))
+        .collect()
+}
+
+fn workload() -> Workload {
+    Workload::new(WorkloadConfig {
+        records: 200,
+        key_prefix: "slices.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    })
+}
+
+/// `slices_replay_test` 同一个访问序列在单个目录和多个分片上读到的值完全相同
+#[test]
+fn slices_replay_test() {
+    let recorder = Arc::new(Recorder::new());
+    let mut cts = controllers(&directory(1), 4);
+    for ct in cts.iter_mut() {
+        ct.attach_recorder(recorder.clone());
+    }
+    workload().run(&mut cts, 4000, 5);
+    let records = recorder.records();
+
+    let sliced = directory(4);
+    let mut cts = controllers(&sliced, 4);
+    let report = replay(&records, &mut cts);
+    assert_eq!(report.divergent_reads, 0);
+
+    // key 分布到所有分片
+    let d = sliced.read();
+    assert_eq!(d.slices(), 4);
+    let mut homes = HashMap::new();
+    for (key, _) in d.sharers().0 {
+        *homes.entry(d.home(&key)).or_insert(0) += 1;
+    }
+    assert_eq!(homes.len(), 4);
+    assert!(homes.values().all(|n| *n > 20));
+}
+
+/// `slices_multithread_test` 多个分片并发处理请求时，每个 key 仍然满足单写者多读者
+#[test]
+fn slices_multithread_test() {
+    let directory = directory(4);
+    let cts = workload().run_threads(controllers(&directory, 4), 3000, 9);
+    let caches: Vec<_> = cts.iter().map(|ct| ct.collect_caches()).collect();
+
+    for i in 0..workload().records() {
+        let key = workload().key(i);
+        let copies: Vec<(Status, String)> = caches
+            .iter()
+            .filter_map(|c| c.get(&key).map(|c| (c.status.clone(), c.value.clone())))
+            .collect();
+        if copies.len() > 1 {
+            assert!(copies.iter().all(|c| c.0 == Status::Shared), "{:?}", copies);
+            assert!(copies.iter().all(|c| c.1 == copies[0].1), "{:?}", copies);
+        }
+    }
+    assert!(directory.read().fenced().is_empty());
+}
@@ -0,0 +1,91 @@
+use mymesi::db::db::DbSession;
+use mymesi::stats::stats::{DirectoryStats, Latencies, Stats};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// `stats_test` 两个线程依次读写同一个 key，检查各项统计
+#[test]
+fn stats_test() {
+    let directory = Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary(),
+        DirectoryConfig::default(),
+    )));
+    let mut c1: CacheController<String> = CacheController::new(directory.clone());
+    let mut c2: CacheController<String> = CacheController::new(directory.clone());
+    let key = "stats.x".to_string();
+
+    c1.get(key.clone()); // 读缺失 I -> E
+    c1.set(key.clone(), "1".to_string()); // 写命中 E -> M
+    c1.get(key.clone()); // 读命中
+    assert_eq!(c2.get(key.clone()), "1"); // c1 写回 M -> S，c2 I -> S
+    c2.set(key.clone(), "2".to_string()); // upgrade S -> M，c1 S -> I
+
+    let s1 = c1.stats();
+    assert_eq!((s1.read_hits, s1.read_misses), (1, 1));
+    assert_eq!((s1.write_hits, s1.write_misses, s1.upgrades), (1, 0, 0));
+    assert_eq!(s1.write_backs, 1);
+    assert_eq!(s1.invalidations_received, 1);
+    assert_eq!(s1.transition(&Status::Invalid, &Status::Exclusive), 1);
+    assert_eq!(s1.transition(&Status::Exclusive, &Status::Modified), 1);
+    assert_eq!(s1.transition(&Status::Modified, &Status::Shared), 1);
+    assert_eq!(s1.transition(&Status::Shared, &Status::Invalid), 1);
+
+    let s2 = c2.stats();
+    assert_eq!((s2.read_hits, s2.read_misses), (0, 1));
+    assert_eq!((s2.write_hits, s2.write_misses, s2.upgrades), (0, 0, 1));
+    assert_eq!(s2.invalidations_sent, 1);
+    assert_eq!(s2.transition(&Status::Invalid, &Status::Shared), 1);
+    assert_eq!(s2.transition(&Status::Shared, &Status::Modified), 1);
+
+    let d = directory.read().stats();
+    assert_eq!((d.read_requests, d.write_requests), (2, 1));
+    assert_eq!((d.remote_reads, d.remote_writes), (1, 1));
+    assert_eq!(d.confirmations, 2);
+    assert_eq!(d.db_writes, 1);
+
+    let l1 = c1.latencies();
+    assert_eq!((l1.hit.count(), l1.miss.count()), (2, 1));
+    let l2 = c2.latencies();
+    assert_eq!((l2.miss.count(), l2.upgrade.count()), (1, 1));
+    let dl = directory.read().latencies();
+    assert_eq!(dl.remote_read.count(), 1);
+    assert_eq!(dl.remote_invalidate.count(), 1);
+    // 广播包含在请求方的 miss 延迟内
+    assert!(l2.upgrade.max() >= dl.remote_invalidate.min());
+
+    // 运行中清零
+    c1.reset_stats();
+    directory.read().reset_stats();
+    assert_eq!(c1.stats(), Stats::default());
+    assert_eq!(c1.latencies(), Latencies::default());
+    assert_eq!(directory.read().stats(), DirectoryStats::default());
+    c1.get(key.clone());
+    assert_eq!(c1.stats().read_misses, 1);
+    assert_eq!(directory.read().stats().remote_reads, 1);
+}
+
+/// `eviction_stats_test` 缓存写满后按状态统计淘汰
+#[test]
+fn eviction_stats_test() {
+    let directory = Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary(),
+        DirectoryConfig::default(),
+    )));
+    let config = ControllerConfig {
+        cache_size: 4,
+        flush_size: 0,
+        ..ControllerConfig::default()
+    };
+    let mut ct: CacheController<String> = CacheController::with_config(directory, config);
+    ct.set("e.0".to_string(), "0".to_string());
+    ct.set("e.1".to_string(), "1".to_string());
+    ct.get("e.2".to_string());
+    ct.get("e.3".to_string());
+
+    let s = ct.stats();
+    assert_eq!(s.evicted(&Status::Modified), 2);
+    assert_eq!(s.evicted(&Status::Exclusive), 2);
+    assert_eq!(s.write_backs, 2);
+    assert_eq!(s.transition(&Status::Modified, &Status::Invalid), 2);
+}
@@ -0,0 +1,78 @@
+use mymesi::db::db::DbSession;
+use mymesi::trace::chrome::write_chrome_trace;
+use mymesi::trace::trace::{Side, TraceKind};
+use mymesi::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// `trace_test` 记录一次读缺失使另一线程的 Modified 副本降级的全过程，并导出 Chrome trace
+#[test]
+fn trace_test() {
+    let config = DirectoryConfig {
+        trace: true,
+        ..DirectoryConfig::default()
+    };
+    let directory = Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary(),
+        config,
+    )));
+    let mut c0: CacheController<String> = CacheController::new(directory.clone());
+    let mut c1: CacheController<String> = CacheController::new(directory.clone());
+    c0.set("t.x".to_string(), "1".to_string());
+    c1.get("t.x".to_string());
+
+    let tracer = directory.read().tracer().unwrap();
+    let records = tracer.records();
+    let transactions: Vec<_> = records
+        .iter()
+        .filter(|r| matches!(r.kind, TraceKind::Transaction(_)))
+        .map(|r| (r.thread_id, r.key.as_str()))
+        .collect();
+    assert_eq!(transactions, vec![(0, "t.x"), (1, "t.x")]);
+
+    // 目录发出 RemoteRead，监听线程收到后 Modified -> Shared，回复后目录收到确认
+    let messages: Vec<_> = records
+        .iter()
+        .filter(|r| !matches!(r.kind, TraceKind::Transaction(_)))
+        .collect();
+    assert_eq!(messages.len(), 4);
+    assert_eq!(
+        (
+            messages[0].kind.clone(),
+            messages[0].side,
+            messages[0].thread_id
+        ),
+        (TraceKind::Send, Side::Directory, 0)
+    );
+    assert!(matches!(messages[0].event, Event::RemoteRead(_)));
+    assert_eq!(messages[0].requester, Some(1));
+    assert_eq!(messages[1].side, Side::Listener);
+    assert_eq!(messages[1].before, Some(Status::Modified));
+    assert_eq!(messages[1].after, Some(Status::Shared));
+    assert!(matches!(messages[3].event, Event::Confirmed(false)));
+    assert_eq!(messages[3].side, Side::Directory);
+    assert!(messages.iter().all(|m| m.seq == messages[0].seq));
+
+    let mut out = Vec::new();
+    write_chrome_trace(&records, &mut out).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let events = json["traceEvents"].as_array().unwrap();
+    // 两条进程名元数据
+    assert_eq!(events.len(), records.len() + 2);
+    let spans: Vec<_> = events.iter().filter(|e| e["ph"] == "X").collect();
+    assert_eq!(spans.len(), 2);
+    assert_eq!(spans[1]["tid"], 1);
+    assert!(events
+        .iter()
+        .any(|e| e["args"]["before"] == "Modified" && e["args"]["after"] == "Shared"));
+
+    tracer.clear();
+    assert!(tracer.is_empty());
+}
+
+/// `trace_disabled_test` 默认不记录
+#[test]
+fn trace_disabled_test() {
+    let directory = Directory::with_db(DbSession::temporary(), DirectoryConfig::default());
+    assert!(directory.tracer().is_none());
+}
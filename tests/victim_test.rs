@@ -0,0 +1,179 @@
+mod common;
+
+use mymesi::db::db::DbSession;
+use mymesi::oracle::oracle;
+use mymesi::workload::workload::{Workload, WorkloadConfig, Ycsb};
+use mymesi::*;
+use parking_lot::RwLock;
+use proptest::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn directory(protocol: Protocol) -> Arc<RwLock<Directory>> {
+    Arc::new(RwLock::new(Directory::with_db(
+        DbSession::temporary().with_latency(Duration::ZERO),
+        DirectoryConfig {
+            protocol,
+            ..DirectoryConfig::default()
+        },
+    )))
+}
+
+fn config(victims: usize) -> ControllerConfig {
+    ControllerConfig {
+        cache_size: 4,
+        flush_size: 2,
+        victims,
+        ..ControllerConfig::default()
+    }
+}
+
+fn controllers(
+    directory: &Arc<RwLock<Directory>>,
+    n: usize,
+    victims: usize,
+) -> Vec<CacheController<String>> {
+    (0..n)
+        .map(|_| CacheController::with_config(directory.clone(), config(victims)))
+        .collect()
+}
+
+/// `victim_hit_test` 批量淘汰的项由 victim buffer 命中，不需要再经过目录
+#[test]
+fn victim_hit_test() {
+    let run = |victims| {
+        let directory = directory(Protocol::Invalidate);
+        let mut ct = controllers(&directory, 1, victims).pop().unwrap();
+        for _ in 0..2 {
+            for i in 0..4 {
+                ct.get(format!("key.{}", i));
+            }
+        }
+        let requests = directory.read().stats().read_requests;
+        (ct.stats(), requests)
+    };
+
+    let (stats, requests) = run(4);
+    assert_eq!(stats.victim_hits, 2);
+    assert_eq!(stats.read_hits, 4);
+    assert_eq!(requests, 4);
+    assert_eq!(stats.evicted(&Status::Exclusive), 0);
+    let (stats, requests) = run(0);
+    assert_eq!(stats.victim_hits, 0);
+    assert_eq!(requests, 6);
+}
+
+/// `victim_coherence_test` victim buffer 中的 Modified 项被其他线程读时写回并降级，被写时失效
+#[test]
+fn victim_coherence_test() {
+    let directory = directory(Protocol::Invalidate);
+    let mut cts = controllers(&directory, 2, 4);
+    for i in 0..4 {
+        cts[0].set(format!("key.{}", i), i.to_string());
+    }
+    let victims = cts[0].collect_victims();
+    assert_eq!(victims.len(), 2);
+    assert!(victims.iter().all(|c| c.status == Status::Modified));
+    // 放入 victim buffer 不写回
+    assert_eq!(cts[0].stats().write_backs, 0);
+    assert_eq!(directory.read().stats().db_writes, 0);
+
+    let key = victims[0].id.clone();
+    assert_eq!(cts[1].get(key.clone()), victims[0].value);
+    assert_eq!(cts[0].stats().write_backs, 1);
+    let status = |ct: &CacheController<String>| {
+        let victims = ct.collect_victims();
+        victims
+            .iter()
+            .find(|c| c.id == key)
+            .map(|c| c.status.clone())
+    };
+    assert_eq!(status(&cts[0]), Some(Status::Shared));
+
+    cts[1].set(key.clone(), "new".to_string());
+    assert_eq!(status(&cts[0]), None);
+    assert_eq!(cts[0].stats().invalidations_received, 1);
+    assert_eq!(cts[0].get(key.clone()), "new");
+    assert_eq!(cts[0].stats().victim_hits, 0);
+
+    // 另一项仍在 victim buffer 中，写命中时移回缓存
+    let other = victims[1].id.clone();
+    cts[0].set(other.clone(), "again".to_string());
+    assert_eq!(cts[0].stats().victim_hits, 1);
+    assert_eq!(cts[1].get(other), "again");
+}
+
+/// `victim_overflow_test` 从 victim buffer 挤出的 Modified 项写回，之后读到的仍是最新的值
+#[test]
+fn victim_overflow_test() {
+    let directory = directory(Protocol::Invalidate);
+    let mut cts = controllers(&directory, 2, 1);
+    for i in 0..32 {
+        cts[0].set(format!("key.{}", i), i.to_string());
+    }
+    assert_eq!(cts[0].collect_victims().len(), 1);
+    assert!(cts[0].stats().evicted(&Status::Modified) > 0);
+    for i in 0..32 {
+        assert_eq!(cts[1].get(format!("key.{}", i)), i.to_string());
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    /// `victim_oracle_test` 使用 victim buffer 时，任意顺序的读写结果都应与参照 HashMap 一致
+    #[test]
+    fn victim_oracle_test((config, steps) in common::scenario(
+        (
+            prop::sample::select(vec![1usize, 2, 4]),
+            prop::sample::select(Protocol::ALL.to_vec()),
+        ),
+        |c, (victims, protocol)| {
+            c.controller.victims = victims;
+            c.directory.protocol = protocol;
+        },
+    )) {
+        prop_assert_eq!(oracle::check(&config, &steps), Ok(()));
+    }
+}
+
+/// `victim_multithread_test` 并发读写结束后，缓存和 victim buffer 中的有效副本仍然满足单写者多读者
+#[test]
+fn victim_multithread_test() {
+    let workload = Workload::new(WorkloadConfig {
+        records: 32,
+        key_prefix: "victim.".to_string(),
+        ..WorkloadConfig::ycsb(Ycsb::A)
+    });
+    let directory = directory(Protocol::Invalidate);
+    let cts = workload.run_threads(controllers(&directory, 4, 4), 3000, 11);
+
+    let mut reader = controllers(&directory, 1, 0).pop().unwrap();
+    for i in 0..workload.records() {
+        let key = workload.key(i);
+        let copies: Vec<(Status, String)> = cts
+            .iter()
+            .flat_map(|ct| {
+                let caches = ct.collect_caches();
+                let cached = caches.get(&key).map(|c| c.clone());
+                let victim = ct.collect_victims().into_iter().find(|c| c.id == key);
+                cached.into_iter().chain(victim)
+            })
+            .filter(|c| c.status != Status::Invalid)
+            .map(|c| (c.status, c.value))
+            .collect();
+        if copies.iter().any(|c| c.0 != Status::Shared) {
+            assert_eq!(copies.len(), 1, "{} {:?}", key, copies);
+        }
+        let latest = reader.get(key.clone());
+        assert!(
+            copies.iter().all(|c| c.1 == latest),
+            "{:?} {}",
+            copies,
+            latest
+        );
+    }
+    let hits: u64 = cts.iter().map(|ct| ct.stats().victim_hits).sum();
+    assert!(hits > 0);
+    assert!(directory.read().fenced().is_empty());
+}